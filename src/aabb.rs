@@ -1,6 +1,7 @@
 use crate::vec3::Point3;
 use crate::ray::Ray;
 
+#[derive(Clone, Copy)]
 pub struct Aabb {
     minimum: Point3,
     maximum: Point3,
@@ -18,29 +19,12 @@ impl Aabb {
         self.maximum
     }
 
-    pub fn naive_hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
-        for a in 0..3 {
-            let t0 = ((self.minimum.e()[a] - ray.origin.e()[a])/ray.direction.e()[a]).min(
-                (self.maximum.e()[a] - ray.origin.e()[a])/ray.direction.e()[a]
-            );
-            let t1 = ((self.minimum.e()[a] - ray.origin.e()[a])/ray.direction.e()[a]).max(
-                (self.maximum.e()[a] - ray.origin.e()[a])/ray.direction.e()[a]
-            );
-            let t_min = t0.max(t_min);
-            let t_max = t1.min(t_max);
-            if t_max <= t_min {
-                return false
-            }
-        }
-        return true
-    }
-
     pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
         for a in 0..3 {
-            let invD = 1.0/ray.direction.e()[a];
-            let mut t0 = (self.min().e()[a] - ray.origin.e()[a])*invD;
-            let mut t1 = (self.max().e()[a] - ray.origin.e()[a])*invD;
-            if invD < 0.0 {
+            let inv_d = 1.0/ray.direction.e()[a];
+            let mut t0 = (self.min().e()[a] - ray.origin.e()[a])*inv_d;
+            let mut t1 = (self.max().e()[a] - ray.origin.e()[a])*inv_d;
+            if inv_d < 0.0 {
                 std::mem::swap(&mut t0, &mut t1);
             }
             let t_min = if t0 > t_min { t0 } else { t_min };
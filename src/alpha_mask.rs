@@ -0,0 +1,71 @@
+use crate::ray::{Hittable, Ray, HitRecord};
+use std::rc::Rc;
+use crate::texture::Texture;
+use crate::aabb::Aabb;
+use crate::random_double;
+use crate::light_bvh::LightBounds;
+use crate::vec3::{Point3, Vec3};
+
+// How far past a skipped hit the search for the next one starts
+const SKIP_EPSILON: f64 = 1e-6;
+
+/// Cuts holes into another object, e.g. a quad carrying leaves or a chain-link fence. The luminance of
+/// `alpha` is the opacity at each point: hits where it is zero are ignored, and where it is fractional
+/// they are kept with that probability. This happens during intersection, so rays and shadow rays pass
+/// through the holes as if the surface weren't there.
+pub struct AlphaMask {
+    object: Rc<dyn Hittable>,
+    alpha: Box<dyn Texture>,
+}
+
+impl AlphaMask {
+    pub fn new(object: Rc<dyn Hittable>, alpha: Box<dyn Texture>) -> AlphaMask {
+        AlphaMask {object, alpha}
+    }
+
+    /// Whether the surface is there at `rec`, decided at random where it's partly transparent.
+    fn is_opaque(&self, rec: &HitRecord) -> bool {
+        let alpha = self.alpha.value(rec.u, rec.v, &rec.p).luminance();
+        alpha >= 1.0 || (alpha > 0.0 && random_double(0.0, 1.0) < alpha)
+    }
+}
+
+impl Hittable for AlphaMask {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut t_min = t_min;
+        loop {
+            let rec = self.object.hit(ray, t_min, t_max)?;
+            if self.is_opaque(&rec) {
+                return Some(rec)
+            }
+            t_min = rec.t + SKIP_EPSILON;
+        }
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.object.bounding_box(time0, time1)
+    }
+
+    // Sampled as if it had no holes; rays that go through them just find no light
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        self.object.random(origin)
+    }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        self.object.light_bounds()
+    }
+
+    // Points in the holes emit nothing, so the samples that land there are lost like the rays that
+    // go through them
+    fn sample_surface(&self) -> Option<(HitRecord, f64)> {
+        self.object.sample_surface().filter(|(rec, _)| self.is_opaque(rec))
+    }
+
+    fn surface_pdf(&self, p: &Point3) -> f64 {
+        self.object.surface_pdf(p)
+    }
+}
@@ -0,0 +1,35 @@
+use crate::integrator::{Integrator, Scene};
+use crate::pdf::{CosinePdf, Pdf};
+use crate::ray::Ray;
+use crate::vec3::Color;
+
+/// Shows how open the surroundings of each point are: white where nothing is within `distance` above
+/// the surface and black in creases, weighted by the cosine with the normal. Misses are black.
+pub struct AmbientOcclusion {
+    distance: f64,
+}
+
+impl AmbientOcclusion {
+    pub fn new(distance: f64) -> AmbientOcclusion {
+        AmbientOcclusion {distance}
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn ray_color(&self, ray: Ray, scene: &Scene) -> Color {
+        let rec = match scene.world.hit(&ray, 0.001, f64::MAX) {
+            Some(rec) => rec,
+            None => return Color::new(0.0, 0.0, 0.0),
+        };
+        let direction = match CosinePdf::new(&rec.normal).generate() {
+            Some(direction) => direction,
+            None => return Color::new(0.0, 0.0, 0.0),
+        };
+        let probe = Ray::new(rec.p, direction, ray.time);
+        if scene.world.hit(&probe, 0.001, self.distance / direction.length()).is_some() {
+            Color::new(0.0, 0.0, 0.0)
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use crate::aabb::Aabb;
+use crate::exr::{write_exr, Channel};
+use crate::film::Film;
+use crate::integrator::Scene;
+use crate::light_bvh::LightBounds;
+use crate::material::Lobe;
+use crate::ray::{HitRecord, Hittable, Ray};
+use crate::vec3::{Color, Point3, Vec3};
+use std::collections::HashMap;
+use std::io;
+use std::ops;
+use std::rc::Rc;
+
+/// Light arriving along a camera ray, split by how it got there: `emission` is light from what the ray
+/// hits, or the background it sees. Direct light bounced once on its way to the camera, and indirect
+/// light more than once. The split between diffuse and specular goes by the lobe the path took at the
+/// first bounce, with scattering inside media counting as diffuse.
+#[derive(Clone, Copy)]
+pub struct LightPaths {
+    pub emission: Color,
+    pub direct_diffuse: Color,
+    pub direct_specular: Color,
+    pub indirect_diffuse: Color,
+    pub indirect_specular: Color,
+}
+
+impl LightPaths {
+    pub fn new() -> LightPaths {
+        let black = Color::new(0.0, 0.0, 0.0);
+        LightPaths {emission: black, direct_diffuse: black, direct_specular: black, indirect_diffuse: black, indirect_specular: black}
+    }
+
+    /// Adds light that reached the camera after `bounces` bounces, the first of which off `first_lobe`.
+    pub fn add(&mut self, bounces: u32, first_lobe: Lobe, light: Color) {
+        let diffuse = matches!(first_lobe, Lobe::Diffuse | Lobe::Volume);
+        let component = match (bounces, diffuse) {
+            (0, _) => &mut self.emission,
+            (1, true) => &mut self.direct_diffuse,
+            (1, false) => &mut self.direct_specular,
+            (_, true) => &mut self.indirect_diffuse,
+            (_, false) => &mut self.indirect_specular,
+        };
+        *component = *component + light;
+    }
+
+    /// All the light, whichever way it came.
+    pub fn total(&self) -> Color {
+        self.emission + self.direct_diffuse + self.direct_specular + self.indirect_diffuse + self.indirect_specular
+    }
+
+    pub fn map(&self, f: impl Fn(&Color) -> Color) -> LightPaths {
+        LightPaths {
+            emission: f(&self.emission),
+            direct_diffuse: f(&self.direct_diffuse),
+            direct_specular: f(&self.direct_specular),
+            indirect_diffuse: f(&self.indirect_diffuse),
+            indirect_specular: f(&self.indirect_specular),
+        }
+    }
+}
+
+impl ops::Add<LightPaths> for LightPaths {
+    type Output = LightPaths;
+
+    fn add(self, rhs: LightPaths) -> LightPaths {
+        LightPaths {
+            emission: self.emission + rhs.emission,
+            direct_diffuse: self.direct_diffuse + rhs.direct_diffuse,
+            direct_specular: self.direct_specular + rhs.direct_specular,
+            indirect_diffuse: self.indirect_diffuse + rhs.indirect_diffuse,
+            indirect_specular: self.indirect_specular + rhs.indirect_specular,
+        }
+    }
+}
+
+/// Gives an object a number, which hits on it carry for the object ID pass. The triangles of a mesh
+/// share one.
+pub struct Tagged {
+    object: Rc<dyn Hittable>,
+    id: usize,
+}
+
+impl Tagged {
+    pub fn new(object: Rc<dyn Hittable>, id: usize) -> Tagged {
+        Tagged {object, id}
+    }
+}
+
+impl Hittable for Tagged {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.object.hit(ray, t_min, t_max).map(|rec| HitRecord {object: Some(self.id), ..rec})
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.object.bounding_box(time0, time1)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        self.object.random(origin)
+    }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        self.object.light_bounds()
+    }
+
+    fn sample_surface(&self) -> Option<(HitRecord, f64)> {
+        self.object.sample_surface().map(|(rec, pdf)| (HitRecord {object: Some(self.id), ..rec}, pdf))
+    }
+
+    fn surface_pdf(&self, p: &Point3) -> f64 {
+        self.object.surface_pdf(p)
+    }
+}
+
+/// Render passes besides the image, for compositing: what the first surface seen through each pixel
+/// is, and the light of the image split by the way it came. Albedo, normals and light are averaged
+/// over the samples, antialiased like the image. Depth, position, texture coordinates and IDs can't be
+/// blended between surfaces, so they come from the first sample through each pixel.
+///
+/// Pixels where camera rays miss everything have an infinite depth, and zero for the other surface
+/// passes. Objects and materials are numbered from one, materials in the order they're first seen.
+pub struct Aovs {
+    width: usize,
+    height: usize,
+    albedo: Vec<Color>,
+    normal: Vec<Vec3>,
+    depth: Vec<f64>,
+    position: Vec<Point3>,
+    uv: Vec<(f64, f64)>,
+    object: Vec<usize>,
+    material: Vec<usize>,
+    /// Split light of each pixel, if the integrator splits it.
+    light: Option<Vec<LightPaths>>,
+    materials: HashMap<*const (), usize>,
+}
+
+impl Aovs {
+    pub fn new(width: usize, height: usize) -> Aovs {
+        let count = width * height;
+        let black = Color::new(0.0, 0.0, 0.0);
+        Aovs {
+            width,
+            height,
+            albedo: vec![black; count],
+            normal: vec![black; count],
+            depth: vec![f64::INFINITY; count],
+            position: vec![black; count],
+            uv: vec![(0.0, 0.0); count],
+            object: vec![0; count],
+            material: vec![0; count],
+            light: None,
+            materials: HashMap::new(),
+        }
+    }
+
+    /// Adds what camera ray `ray` through pixel (`i`, `j`) hits first, with rows counted from the bottom.
+    /// `first` tells whether it's the pixel's first sample.
+    pub fn add_surface(&mut self, i: usize, j: usize, first: bool, ray: &Ray, scene: &Scene) {
+        let index = j * self.width + i;
+        let rec = match scene.world.hit(ray, 0.001, f64::MAX) {
+            Some(rec) => rec,
+            None => return,
+        };
+        // The weight of a sampled direction is the albedo for diffuse surfaces, and close to the
+        // reflectance of others
+        let albedo = rec.material.sample(ray, &rec).map_or(Color::new(0.0, 0.0, 0.0), |scatter| scatter.attenuation);
+        self.albedo[index] = self.albedo[index] + albedo;
+        self.normal[index] = self.normal[index] + if rec.front_face { rec.normal } else { -rec.normal };
+        if first {
+            let next_id = self.materials.len() + 1;
+            self.depth[index] = rec.t * ray.direction.length();
+            self.position[index] = rec.p;
+            self.uv[index] = (rec.u, rec.v);
+            self.object[index] = rec.object.unwrap_or(0);
+            self.material[index] = *self.materials.entry(Rc::as_ptr(&rec.material) as *const ()).or_insert(next_id);
+        }
+    }
+
+    /// Adds the split light of a sample through pixel (`i`, `j`).
+    pub fn add_light(&mut self, i: usize, j: usize, paths: &LightPaths) {
+        let count = self.width * self.height;
+        let light = self.light.get_or_insert_with(|| vec![LightPaths::new(); count]);
+        let index = j * self.width + i;
+        light[index] = light[index] + *paths;
+    }
+
+    /// Writes the image on `film` and the passes to an EXR file, each pass as a layer.
+    pub fn write(&self, path: &str, film: &Film, samples_per_pixel: i32) -> io::Result<()> {
+        let scale = 1.0 / samples_per_pixel.max(1) as f64;
+        let image = film.average(samples_per_pixel);
+        let mut channels = Vec::new();
+        let mut add = |name: &str, value: &dyn Fn(usize) -> f64| {
+            let values = (0..self.height).rev()
+                .flat_map(|j| (0..self.width).map(move |i| j * self.width + i))
+                .map(|index| value(index) as f32)
+                .collect();
+            channels.push(Channel {name: name.to_string(), values});
+        };
+        let mut add_color = |layer: &str, color: &dyn Fn(usize) -> Color| {
+            for (channel, axis) in ["R", "G", "B"].iter().zip(0..) {
+                let name = if layer.is_empty() { channel.to_string() } else { format!("{}.{}", layer, channel) };
+                add(&name, &|index| color(index).e()[axis]);
+            }
+        };
+        add_color("", &|index| image[index]);
+        add_color("albedo", &|index| self.albedo[index] * scale);
+        if let Some(light) = &self.light {
+            add_color("emission", &|index| light[index].emission * scale);
+            add_color("direct_diffuse", &|index| light[index].direct_diffuse * scale);
+            add_color("direct_specular", &|index| light[index].direct_specular * scale);
+            add_color("indirect_diffuse", &|index| light[index].indirect_diffuse * scale);
+            add_color("indirect_specular", &|index| light[index].indirect_specular * scale);
+        }
+        for (channel, axis) in ["X", "Y", "Z"].iter().zip(0..) {
+            add(&format!("normal.{}", channel), &|index| (self.normal[index] * scale).e()[axis]);
+            add(&format!("position.{}", channel), &|index| self.position[index].e()[axis]);
+        }
+        add("depth.Z", &|index| self.depth[index]);
+        add("uv.U", &|index| self.uv[index].0);
+        add("uv.V", &|index| self.uv[index].1);
+        add("object_id.id", &|index| self.object[index] as f64);
+        add("material_id.id", &|index| self.material[index] as f64);
+        write_exr(path, self.width, self.height, &mut channels)
+    }
+}
@@ -0,0 +1,121 @@
+use crate::distribution::Distribution2D;
+use crate::hdr::read_hdr;
+use crate::random_double;
+use crate::vec3::{Color, Vec3};
+use std::f64::consts::PI;
+use std::io;
+
+/// Radiance arriving from infinitely far away, seen by rays that escape the scene.
+pub trait Background {
+    fn value(&self, direction: &Vec3) -> Color;
+
+    /// Picks a direction proportionally to the incoming light, for backgrounds that support it.
+    fn sample(&self) -> Option<Vec3> {
+        None
+    }
+
+    /// Density per unit solid angle with which `sample` picks `direction`.
+    fn pdf_value(&self, _direction: &Vec3) -> f64 {
+        0.0
+    }
+}
+
+/// Vertical blend from `bottom` to `top`.
+pub struct Gradient {
+    bottom: Color,
+    top: Color,
+}
+
+impl Gradient {
+    pub fn new(bottom: Color, top: Color) -> Gradient {
+        Gradient {bottom, top}
+    }
+}
+
+impl Background for Gradient {
+    fn value(&self, direction: &Vec3) -> Color {
+        let unit_direction = direction.unit();
+        let t = 0.5 * (unit_direction.y() + 1.0);
+        (self.bottom * (1.0 - t)) + (self.top * t)
+    }
+}
+
+/// Equirectangular (latitude-longitude) HDR image surrounding the scene. The top row of the image is
+/// straight up and the columns sweep around the vertical axis.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    intensity: f64,
+    cos_rotation: f64,
+    sin_rotation: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    /// `rotation` turns the map around the vertical axis, in degrees; `intensity` scales its radiance.
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>, rotation: f64, intensity: f64) -> EnvironmentMap {
+        // Weigh each texel by sin(theta) to account for the rows shrinking towards the poles
+        let mut func = Vec::with_capacity(width * height);
+        for row in 0..height {
+            let sin_theta = (PI * (row as f64 + 0.5) / height as f64).sin();
+            func.extend(pixels[row * width..(row + 1) * width].iter().map(|p| p.luminance().max(0.0) * sin_theta));
+        }
+        let distribution = Distribution2D::new(&func, width, height);
+        let rotation = rotation.to_radians();
+        EnvironmentMap {
+            width, height, pixels, intensity, cos_rotation: rotation.cos(), sin_rotation: rotation.sin(), distribution,
+        }
+    }
+
+    pub fn load(path: &str, rotation: f64, intensity: f64) -> io::Result<EnvironmentMap> {
+        let (width, height, pixels) = read_hdr(path)?;
+        Ok(EnvironmentMap::new(width, height, pixels, rotation, intensity))
+    }
+
+    fn world_to_map(&self, d: &Vec3) -> Vec3 {
+        Vec3::new(self.cos_rotation * d.x() - self.sin_rotation * d.z(), d.y(), self.sin_rotation * d.x() + self.cos_rotation * d.z())
+    }
+
+    fn map_to_world(&self, d: &Vec3) -> Vec3 {
+        Vec3::new(self.cos_rotation * d.x() + self.sin_rotation * d.z(), d.y(), -self.sin_rotation * d.x() + self.cos_rotation * d.z())
+    }
+
+    /// Texture coordinates in [0, 1)^2 of a world space direction.
+    fn direction_to_uv(&self, direction: &Vec3) -> (f64, f64) {
+        let d = self.world_to_map(&direction.unit());
+        let theta = d.y().clamp(-1.0, 1.0).acos();
+        let phi = d.z().atan2(d.x());
+        let phi = if phi < 0.0 { phi + 2.0 * PI } else { phi };
+        (phi / (2.0 * PI), theta / PI)
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn value(&self, direction: &Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.pixels[j * self.width + i] * self.intensity
+    }
+
+    fn sample(&self) -> Option<Vec3> {
+        let ((u, v), pdf) = self.distribution.sample_continuous(random_double(0.0, 1.0), random_double(0.0, 1.0));
+        if pdf == 0.0 {
+            return None
+        }
+        let theta = v * PI;
+        let phi = u * 2.0 * PI;
+        let d = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+        Some(self.map_to_world(&d))
+    }
+
+    fn pdf_value(&self, direction: &Vec3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0.0 {
+            return 0.0
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
@@ -0,0 +1,280 @@
+use crate::integrator::{Integrator, Scene};
+use crate::mesh::Mesh;
+use crate::options::DepthLimits;
+use crate::path_tracer::PathTracer;
+use crate::pdf::{CosinePdf, Pdf};
+use crate::ray::Ray;
+use crate::vec3::{Color, Point3, Vec3};
+
+/// Height above the surface radiance is looked at from, clear of the hits rays ignore near their origin.
+const OFFSET: f64 = 0.01;
+
+/// What a bake stores for each texel.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BakeMode {
+    /// Light arriving at the surface from all over the hemisphere above it, weighted by the cosine.
+    Irradiance,
+    /// Share of the hemisphere, weighted by the cosine, with nothing in it up to a distance.
+    AmbientOcclusion,
+    /// Average direction the hemisphere is open in, mapped from [-1, 1] to [0, 1] per axis.
+    BentNormals,
+    /// Light the surface sends back along its normal, with its material and all the light on it.
+    Radiance,
+}
+
+impl BakeMode {
+    /// Whether the bake holds light, which is gamma corrected like rendered images, rather than data.
+    fn is_light(&self) -> bool {
+        matches!(self, BakeMode::Irradiance | BakeMode::Radiance)
+    }
+}
+
+/// Texel of the lightmap inside a triangle, and the point of the surface it stands for.
+struct TexelPoint {
+    index: usize,
+    p: Point3,
+    normal: Vec3,
+}
+
+/// Values baked over the texture coordinates of a mesh, in rows from the bottom, as `v` goes up. Texels no
+/// triangle covers are empty.
+pub struct Lightmap {
+    size: usize,
+    texels: Vec<Option<Color>>,
+    is_light: bool,
+}
+
+impl Lightmap {
+    /// Grows the baked texels `passes` texels into the empty space around them, each empty texel taking the
+    /// average of its baked neighbours. Without the margin, filtering near the edges of UV charts blends in
+    /// the empty space, which shows as dark seams where charts meet on the mesh.
+    pub fn dilate(&mut self, passes: u32) {
+        let size = self.size as i64;
+        for _ in 0..passes {
+            let mut grown = self.texels.clone();
+            for (index, texel) in grown.iter_mut().enumerate() {
+                if texel.is_some() {
+                    continue
+                }
+                let (i, j) = (index as i64 % size, index as i64 / size);
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                let mut count = 0;
+                for (di, dj) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+                    let (x, y) = (i + di, j + dj);
+                    if (0..size).contains(&x) && (0..size).contains(&y) {
+                        if let Some(neighbour) = self.texels[(y * size + x) as usize] {
+                            sum = sum + neighbour;
+                            count += 1;
+                        }
+                    }
+                }
+                if count > 0 {
+                    *texel = Some(sum / count as f64);
+                }
+            }
+            self.texels = grown;
+        }
+    }
+
+    /// Writes the lightmap to stdout as a PPM, top row first. Empty texels are black.
+    pub fn write(&self) {
+        println!("P3");
+        println!("{0} {1}", self.size, self.size);
+        println!("255");
+        for j in (0..self.size).rev() {
+            for i in 0..self.size {
+                let texel = self.texels[j * self.size + i].unwrap_or(Color::new(0.0, 0.0, 0.0));
+                if self.is_light {
+                    crate::write_color(&texel, 1);
+                } else {
+                    let channel = |c: f64| (256.0 * crate::clamp(c, 0.0, 0.999)) as i32;
+                    println!("{0} {1} {2}", channel(texel.r()), channel(texel.g()), channel(texel.b()));
+                }
+            }
+        }
+    }
+}
+
+/// Bakes lighting onto the texture coordinates of a mesh that's part of the scene, for real-time renderers
+/// to look up instead of computing it. Every texel a triangle covers stands for the point of the triangle
+/// at its center, and gathers light or occlusion over the hemisphere above it.
+pub struct Baker<'a> {
+    scene: Scene<'a>,
+    mode: BakeMode,
+    path_tracer: PathTracer,
+    /// How far ambient occlusion and bent normals look for occluders.
+    distance: f64,
+}
+
+impl<'a> Baker<'a> {
+    pub fn new(scene: Scene<'a>, mode: BakeMode, limits: DepthLimits, distance: f64) -> Baker<'a> {
+        Baker {scene, mode, path_tracer: PathTracer::new(limits), distance}
+    }
+
+    /// Bakes a `size` by `size` lightmap, averaging `samples` samples per texel.
+    pub fn bake(&self, mesh: &Mesh, size: usize, samples: u32) -> Lightmap {
+        let points = texel_points(mesh, size);
+        let mut sums = vec![Color::new(0.0, 0.0, 0.0); points.len()];
+        for pass in 0..samples {
+            eprintln!("Passes remaining {0}", samples - pass);
+            for (sum, point) in sums.iter_mut().zip(&points) {
+                *sum = *sum + self.sample(point);
+            }
+        }
+
+        let mut texels = vec![None; size * size];
+        for (sum, point) in sums.iter().zip(&points) {
+            texels[point.index] = Some(match self.mode {
+                BakeMode::BentNormals => {
+                    let bent = if sum.near_zero() { point.normal } else { sum.unit() };
+                    (bent + Vec3::new(1.0, 1.0, 1.0)) * 0.5
+                },
+                _ => *sum / samples.max(1) as f64,
+            });
+        }
+        Lightmap {size, texels, is_light: self.mode.is_light()}
+    }
+
+    fn sample(&self, point: &TexelPoint) -> Color {
+        let TexelPoint {p, normal, ..} = point;
+        if self.mode == BakeMode::Radiance {
+            return self.path_tracer.ray_color(Ray::new(*p + *normal * OFFSET, -*normal, 0.0), &self.scene)
+        }
+        let direction = match CosinePdf::new(normal).generate() {
+            Some(direction) => direction.unit(),
+            None => return Color::new(0.0, 0.0, 0.0),
+        };
+        let ray = Ray::new(*p, direction, 0.0);
+        match self.mode {
+            // Cosine-weighted directions make the irradiance π times the average radiance
+            BakeMode::Irradiance => self.path_tracer.ray_color(ray, &self.scene) * std::f64::consts::PI,
+            _ if self.scene.world.hit(&ray, 0.001, self.distance).is_some() => Color::new(0.0, 0.0, 0.0),
+            BakeMode::AmbientOcclusion => Color::new(1.0, 1.0, 1.0),
+            _ => direction,
+        }
+    }
+}
+
+/// Texels whose centers lie inside a triangle, found by going over the texels around each triangle in UV
+/// space. Where triangles overlap in UV space, the first one keeps the texel.
+fn texel_points(mesh: &Mesh, size: usize) -> Vec<TexelPoint> {
+    let mut covered = vec![false; size * size];
+    let mut points = Vec::new();
+    for triangle in 0..mesh.triangle_count() {
+        let [a, b, c] = match mesh.uvs(triangle) {
+            Some(uvs) => uvs,
+            None => continue,
+        };
+        let (e1, e2) = ((b.0 - a.0, b.1 - a.1), (c.0 - a.0, c.1 - a.1));
+        let det = e1.0 * e2.1 - e1.1 * e2.0;
+        if det.abs() < 1e-12 {
+            continue
+        }
+        let texel_range = |coordinates: [f64; 3]| {
+            let min = coordinates.iter().cloned().fold(f64::INFINITY, f64::min);
+            let max = coordinates.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            let first = (min * size as f64 - 0.5).floor().max(0.0) as usize;
+            let last = ((max * size as f64 - 0.5).ceil().max(0.0) as usize).min(size - 1);
+            first..=last
+        };
+        for j in texel_range([a.1, b.1, c.1]) {
+            for i in texel_range([a.0, b.0, c.0]) {
+                let index = j * size + i;
+                let (du, dv) = ((i as f64 + 0.5) / size as f64 - a.0, (j as f64 + 0.5) / size as f64 - a.1);
+                let b1 = (du * e2.1 - dv * e2.0) / det;
+                let b2 = (e1.0 * dv - e1.1 * du) / det;
+                if covered[index] || b1 < 0.0 || b2 < 0.0 || b1 + b2 > 1.0 {
+                    continue
+                }
+                covered[index] = true;
+                let (p, normal) = mesh.surface(triangle, b1, b2);
+                points.push(TexelPoint {index, p, normal});
+            }
+        }
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+    use std::rc::Rc;
+
+    /// Loads a mesh whose positions are its texture coordinates, at z = 0.
+    fn flat_mesh(name: &str, uvs: &[(f64, f64)], faces: &str) -> Mesh {
+        let mut obj = String::new();
+        for (u, v) in uvs {
+            obj += &format!("v {0} {1} 0\nvt {0} {1}\n", u, v);
+        }
+        obj += faces;
+        let path = std::env::temp_dir().join(format!("bake-{0}-{1}.obj", name, std::process::id()));
+        std::fs::write(&path, obj).unwrap();
+        let material = Rc::new(Lambertian::new(Box::new(SolidColor::new(Color::new(0.5, 0.5, 0.5)))));
+        let mesh = Mesh::load_obj(path.to_str().unwrap(), material).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        mesh
+    }
+
+    fn center(index: usize, size: usize) -> Point3 {
+        Point3::new(((index % size) as f64 + 0.5) / size as f64, ((index / size) as f64 + 0.5) / size as f64, 0.0)
+    }
+
+    #[test]
+    fn covers_every_texel_of_the_square_once() {
+        let size = 16;
+        let square = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let mesh = flat_mesh("square", &square, "f 1/1 2/2 3/3\nf 1/1 3/3 4/4\nf 1/1 2/2 3/3\n");
+        let points = texel_points(&mesh, size);
+        let mut seen = vec![false; size * size];
+        for point in &points {
+            assert!(!seen[point.index]);
+            seen[point.index] = true;
+            assert!((point.p - center(point.index, size)).length() < 1e-9);
+            assert!((point.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+        }
+        assert_eq!(points.len(), size * size);
+    }
+
+    #[test]
+    fn covers_the_texels_centered_inside_a_triangle() {
+        let size = 16;
+        let mesh = flat_mesh("triangle", &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)], "f 1/1 2/2 3/3\n");
+        let points = texel_points(&mesh, size);
+        // The diagonal runs through the centers of the texels along it, which may fall on either side
+        let diagonal = |point: &&TexelPoint| point.index % size + point.index / size;
+        assert!(points.iter().all(|point| diagonal(&point) < size));
+        let inside = size * (size - 1) / 2;
+        assert_eq!(points.iter().filter(|point| diagonal(point) < size - 1).count(), inside);
+        assert!(points.len() >= inside && points.len() <= inside + size);
+    }
+
+    #[test]
+    fn skips_triangles_without_texture_coordinates() {
+        let mesh = flat_mesh("untextured", &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)], "f 1 2 3\n");
+        assert!(texel_points(&mesh, 8).is_empty());
+    }
+
+    #[test]
+    fn dilates_into_the_empty_texels_one_ring_per_pass() {
+        let (one, three) = (Color::new(1.0, 1.0, 1.0), Color::new(3.0, 3.0, 3.0));
+        let mut texels = vec![None; 9];
+        texels[0] = Some(one);
+        texels[2] = Some(three);
+        let mut lightmap = Lightmap {size: 3, texels, is_light: true};
+
+        lightmap.dilate(0);
+        assert_eq!(lightmap.texels.iter().filter(|texel| texel.is_some()).count(), 2);
+
+        lightmap.dilate(1);
+        let r = |index: usize| lightmap.texels[index].map(|texel| texel.r());
+        assert_eq!([r(0), r(1), r(2)], [Some(1.0), Some(2.0), Some(3.0)]);
+        assert_eq!([r(3), r(4), r(5)], [Some(1.0), Some(2.0), Some(3.0)]);
+        assert_eq!([r(6), r(7), r(8)], [None, None, None]);
+
+        lightmap.dilate(1);
+        let r = |index: usize| lightmap.texels[index].map(|texel| texel.r());
+        assert_eq!([r(6), r(7), r(8)], [Some(1.5), Some(2.0), Some(2.5)]);
+    }
+}
@@ -0,0 +1,520 @@
+use crate::integrator::{Integrator, Scene};
+use crate::light::Light;
+use crate::light_bvh::Sampled;
+use crate::material::{Lobe, TransportMode};
+use crate::pdf::{CosinePdf, Pdf};
+use crate::ray::{HitRecord, Ray};
+use crate::vec3::{Color, Point3, Vec3};
+use std::f64::consts::PI;
+
+/// Kind of light a path of light starts from.
+#[derive(Clone, Copy)]
+enum Emitter<'a> {
+    /// Emissive surface, leaving light around its normal.
+    Area,
+    Delta(&'a dyn Light),
+}
+
+// Most vertices are surfaces, so boxing them would only add allocations
+#[allow(clippy::large_enum_variant)]
+enum Kind<'a> {
+    /// Point on the lens.
+    Camera,
+    /// Point on a light.
+    Light(Emitter<'a>),
+    /// Point where `ray` hit a surface.
+    Surface {rec: HitRecord, ray: Ray},
+}
+
+/// Vertex of a camera or light subpath.
+struct Vertex<'a> {
+    kind: Kind<'a>,
+    p: Point3,
+    /// Geometric normal, for turning densities per solid angle into densities per unit area at the
+    /// vertex. Points off any surface have none.
+    normal: Option<Vec3>,
+    /// Importance the camera subpath or light the light subpath carries up to the vertex, divided by
+    /// the density of having sampled it.
+    beta: Color,
+    /// Whether the path went on from here through a specular lobe, or one that can't be evaluated, so
+    /// that no connection can go through the vertex.
+    delta: bool,
+    /// Density per unit area with which the subpath sampled the vertex.
+    pdf_fwd: f64,
+    /// Density per unit area with which a subpath coming the other way would have sampled the vertex.
+    pdf_rev: f64,
+}
+
+impl<'a> Vertex<'a> {
+    fn new(kind: Kind<'a>, p: Point3, normal: Option<Vec3>, beta: Color, pdf_fwd: f64) -> Vertex<'a> {
+        Vertex {kind, p, normal, beta, delta: false, pdf_fwd, pdf_rev: 0.0}
+    }
+
+    fn connectible(&self) -> bool {
+        !self.delta
+    }
+
+    /// Turns `pdf`, a density per unit solid angle of going from the vertex towards `next`, into a
+    /// density per unit area at `next`.
+    fn convert(&self, pdf: f64, next: &Vertex) -> f64 {
+        let w = next.p - self.p;
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0
+        }
+        let cosine = next.normal.map_or(1.0, |normal| normal.dot(&w).abs() / distance_squared.sqrt());
+        pdf * cosine / distance_squared
+    }
+
+    /// BSDF times cosine for transporting `mode` between the vertex the surface was reached from and
+    /// `to`: importance on light subpaths, radiance on camera subpaths.
+    fn f(&self, to: &Point3, mode: TransportMode) -> Color {
+        match &self.kind {
+            Kind::Surface {rec, ray} => {
+                let direction = (*to - self.p).unit();
+                rec.material.eval(ray, rec, &direction, mode) * shading_correction(rec, &-ray.direction, &direction, mode)
+            },
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Density per unit area with which a subpath that reached the vertex from `prev` goes on to `next`.
+    fn pdf(&self, scene: &Scene, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let direction = (next.p - self.p).unit();
+        let pdf = match &self.kind {
+            Kind::Camera => scene.camera.pdf(&self.p, &direction),
+            Kind::Light(_) => return self.pdf_light(next),
+            Kind::Surface {rec, ray} => match prev {
+                Some(prev) => {
+                    let incoming = Ray::new(prev.p, (self.p - prev.p).unit(), ray.time);
+                    rec.material.pdf(&incoming, &facing(rec, &incoming), &direction)
+                },
+                None => 0.0,
+            },
+        };
+        self.convert(pdf, next)
+    }
+
+    /// Density per unit area with which light leaving the vertex, on a light or an emissive surface,
+    /// reaches `next`.
+    fn pdf_light(&self, next: &Vertex) -> f64 {
+        let direction = (next.p - self.p).unit();
+        let pdf = match &self.kind {
+            Kind::Light(Emitter::Delta(light)) => light.emission_pdf(&direction),
+            Kind::Light(Emitter::Area) => self.normal.map_or(0.0, |normal| normal.dot(&direction).max(0.0) / PI),
+            Kind::Surface {rec, ..} => {
+                let normal = if rec.front_face { rec.geometric_normal } else { -rec.geometric_normal };
+                normal.dot(&direction).max(0.0) / PI
+            },
+            Kind::Camera => 0.0,
+        };
+        self.convert(pdf, next)
+    }
+
+    /// Density per unit area with which a path of light starts at the vertex, on an emissive surface
+    /// a camera subpath hit.
+    fn pdf_light_origin(&self, scene: &Scene) -> f64 {
+        match &self.kind {
+            Kind::Surface {rec, ..} => scene.lights.find_emitter(rec).map_or(0.0, |(shape, pmf)| pmf * shape.surface_pdf(&self.p)),
+            _ => 0.0,
+        }
+    }
+
+    fn is_delta_light(&self) -> bool {
+        matches!(self.kind, Kind::Light(Emitter::Delta(_)))
+    }
+}
+
+/// `rec` as seen by `ray`, flipped when the ray comes from the other side than the surface was hit from.
+fn facing(rec: &HitRecord, ray: &Ray) -> HitRecord {
+    let mut rec = rec.clone();
+    if ray.direction.dot(&rec.geometric_normal) > 0.0 {
+        rec.normal = -rec.normal;
+        rec.geometric_normal = -rec.geometric_normal;
+        rec.front_face = !rec.front_face;
+    }
+    rec
+}
+
+/// Makes up for importance not following the cosines with the shading normal of `rec` the way radiance
+/// does, for `mode` arriving from `wo` and leaving towards `wi` (Veach 1997, 5.3). Materials perturbing
+/// the normal further correct for that themselves.
+fn shading_correction(rec: &HitRecord, wo: &Vec3, wi: &Vec3, mode: TransportMode) -> f64 {
+    if mode == TransportMode::Radiance {
+        return 1.0
+    }
+    let (wo, wi) = (wo.unit(), wi.unit());
+    let denominator = wo.dot(&rec.geometric_normal).abs() * wi.dot(&rec.normal).abs();
+    if denominator == 0.0 {
+        return 0.0
+    }
+    wo.dot(&rec.normal).abs() * wi.dot(&rec.geometric_normal).abs() / denominator
+}
+
+fn visible(scene: &Scene, from: &Point3, to: &Point3, time: f64) -> bool {
+    let w = *to - *from;
+    let distance = w.length();
+    scene.world.hit(&Ray::new(*from, w / distance, time), 0.001, distance - 0.001).is_none()
+}
+
+fn is_black(c: &Color) -> bool {
+    c.x() == 0.0 && c.y() == 0.0 && c.z() == 0.0
+}
+
+/// Camera ray that left the scene after the last vertex of the camera subpath.
+struct Escape {
+    ray: Ray,
+    beta: Color,
+    /// Density per unit solid angle with which the ray was picked, zero when through a delta lobe.
+    pdf: f64,
+}
+
+/// Bidirectional path tracer. For every camera ray it traces a subpath from the camera and one from a
+/// light, then connects every prefix of one to every prefix of the other, each connection being
+/// another way of sampling a path. Multiple importance sampling with the balance heuristic weighs the
+/// ways against each other, so each path is mostly counted by whichever samples it best: light that
+/// reaches the camera through small openings or off diffuse surfaces seen in mirrors comes from the
+/// light side. Connections straight to the camera land on arbitrary pixels and are splatted onto the
+/// film.
+///
+/// Paths have at most `max_depth` bounces. The background and directional lights are only found from
+/// the camera side, by rays escaping and by sampling them at each vertex, weighed against each other.
+/// Media are followed but never connected through, like specular surfaces.
+pub struct Bdpt {
+    max_depth: usize,
+}
+
+impl Bdpt {
+    pub fn new(max_depth: u32) -> Bdpt {
+        Bdpt {max_depth: max_depth as usize}
+    }
+
+    fn camera_path<'a>(&self, scene: &Scene<'a>, ray: Ray) -> (Vec<Vertex<'a>>, Option<Escape>) {
+        let camera = Vertex::new(Kind::Camera, ray.origin, Some(scene.camera.forward()), Color::new(1.0, 1.0, 1.0), 0.0);
+        let mut path = vec![camera];
+        let pdf = scene.camera.pdf(&ray.origin, &ray.direction);
+        let escape = self.walk(scene, ray, Color::new(1.0, 1.0, 1.0), pdf, self.max_depth + 2, TransportMode::Radiance, &mut path);
+        (path, escape)
+    }
+
+    fn light_path<'a>(&self, scene: &Scene<'a>, time: f64) -> Vec<Vertex<'a>> {
+        let mut path = Vec::new();
+        let (light, pmf) = match scene.lights.sample_emitter() {
+            Some(picked) => picked,
+            None => return path,
+        };
+        let (ray, beta, pdf) = match light {
+            Sampled::Shape(_, shape) => {
+                let (rec, area_pdf) = match shape.sample_surface() {
+                    Some(sample) => sample,
+                    None => return path,
+                };
+                let normal = rec.geometric_normal;
+                let cosine = CosinePdf::new(&normal);
+                let direction = match cosine.generate() {
+                    Some(direction) => direction.unit(),
+                    None => return path,
+                };
+                let pdf = cosine.value(&direction);
+                if pdf == 0.0 {
+                    return path
+                }
+                let emitted = rec.material.emitted(&Ray::new(rec.p + direction, -direction, time), &rec);
+                let origin_pdf = pmf * area_pdf;
+                path.push(Vertex::new(Kind::Light(Emitter::Area), rec.p, Some(normal), emitted / origin_pdf, origin_pdf));
+                let beta = emitted * (normal.dot(&direction) / (origin_pdf * pdf));
+                (Ray::new(rec.p, direction, time), beta, pdf)
+            },
+            Sampled::Delta(light) => {
+                let emission = match light.emit() {
+                    Some(emission) if emission.pdf > 0.0 => emission,
+                    _ => return path,
+                };
+                path.push(Vertex::new(Kind::Light(Emitter::Delta(light)), emission.origin, None, emission.intensity / pmf, pmf));
+                let beta = emission.intensity / (pmf * emission.pdf);
+                (Ray::new(emission.origin, emission.direction, time), beta, emission.pdf)
+            },
+        };
+        self.walk(scene, ray, beta, pdf, self.max_depth + 1, TransportMode::Importance, &mut path);
+        path
+    }
+
+    /// Extends `path` along `ray`, picked from its last vertex with density `pdf` per unit solid angle,
+    /// until it has `max_vertices` vertices or ends, carrying `mode`. Returns the ray that left the
+    /// scene, if one did.
+    #[allow(clippy::too_many_arguments)]
+    fn walk<'a>(&self, scene: &Scene<'a>, mut ray: Ray, mut beta: Color, mut pdf: f64, max_vertices: usize, mode: TransportMode,
+                path: &mut Vec<Vertex<'a>>) -> Option<Escape> {
+        while path.len() < max_vertices {
+            let rec = match scene.world.hit(&ray, 0.001, f64::MAX) {
+                Some(rec) => rec,
+                None => return Some(Escape {ray, beta, pdf}),
+            };
+            let prev = path.len() - 1;
+            let mut vertex = Vertex::new(Kind::Surface {rec: rec.clone(), ray}, rec.p, Some(rec.geometric_normal), beta, 0.0);
+            vertex.pdf_fwd = path[prev].convert(pdf, &vertex);
+            let scatter = match rec.material.sample(&ray, &rec) {
+                Some(scatter) => scatter,
+                None => {
+                    path.push(vertex);
+                    return None
+                },
+            };
+            let scattered = scatter.scatter;
+            let vertex_delta = scatter.lobe == Lobe::Specular || scatter.pdf == 0.0;
+            vertex.delta = vertex_delta;
+            let pdf_rev = if vertex.delta {
+                0.0
+            } else {
+                let reversed = Ray::new(scattered.origin, -scattered.direction.unit(), ray.time);
+                rec.material.pdf(&reversed, &facing(&rec, &reversed), &(path[prev].p - rec.p).unit())
+            };
+            path[prev].pdf_rev = vertex.convert(pdf_rev, &path[prev]);
+            path.push(vertex);
+
+            pdf = if vertex_delta { 0.0 } else { scatter.pdf };
+            // Materials weigh what they sample for radiance, so importance is weighed by its own BSDF
+            let attenuation = match mode {
+                TransportMode::Importance if !vertex_delta => rec.material.eval(&ray, &rec, &scattered.direction, mode) / scatter.pdf,
+                _ => scatter.attenuation,
+            };
+            beta = beta * attenuation * shading_correction(&rec, &-ray.direction, &scattered.direction, mode);
+            if is_black(&beta) {
+                return None
+            }
+            ray = scattered;
+        }
+        None
+    }
+
+    /// Light carried by the path made of the first `s` vertices of the light subpath and the first `t`
+    /// of the camera subpath, weighed. Light reaching the camera directly (`t` = 1) is splatted onto the
+    /// film instead.
+    fn connect(&self, scene: &Scene, light: &[Vertex], camera: &[Vertex], s: usize, t: usize, time: f64) -> Color {
+        let black = Color::new(0.0, 0.0, 0.0);
+        let pt = &camera[t - 1];
+        if s == 0 {
+            let emitted = match &pt.kind {
+                Kind::Surface {rec, ray} => rec.material.emitted(ray, rec),
+                _ => return black,
+            };
+            if is_black(&emitted) {
+                return black
+            }
+            return pt.beta * emitted * self.mis_weight(scene, light, camera, None, s, t)
+        }
+
+        if t == 1 {
+            let qs = &light[s - 1];
+            if !qs.connectible() {
+                return black
+            }
+            let lens = match scene.camera.sample_lens(&qs.p) {
+                Some(lens) if lens.pdf > 0.0 => lens,
+                _ => return black,
+            };
+            let beta = Color::new(1.0, 1.0, 1.0) * (lens.importance / lens.pdf);
+            let sampled = Vertex::new(Kind::Camera, lens.point, Some(scene.camera.forward()), beta, 0.0);
+            let radiance = qs.beta * qs.f(&lens.point, TransportMode::Importance) * sampled.beta;
+            if is_black(&radiance) || !visible(scene, &qs.p, &lens.point, time) {
+                return black
+            }
+            let weight = self.mis_weight(scene, light, camera, Some(&sampled), s, t);
+            scene.film.splat(lens.s, lens.t, radiance * weight);
+            return black
+        }
+
+        if !pt.connectible() {
+            return black
+        }
+        if s == 1 {
+            let (sampled, radiance) = match sample_light(scene, pt, time) {
+                Some(sample) => sample,
+                None => return black,
+            };
+            if is_black(&radiance) || !visible(scene, &pt.p, &sampled.p, time) {
+                return black
+            }
+            return radiance * self.mis_weight(scene, light, camera, Some(&sampled), s, t)
+        }
+
+        let qs = &light[s - 1];
+        if !qs.connectible() {
+            return black
+        }
+        let radiance = qs.beta * qs.f(&pt.p, TransportMode::Importance) * pt.f(&qs.p, TransportMode::Radiance) * pt.beta / (qs.p - pt.p).length_squared();
+        if is_black(&radiance) || !visible(scene, &pt.p, &qs.p, time) {
+            return black
+        }
+        radiance * self.mis_weight(scene, light, camera, None, s, t)
+    }
+
+    /// Balance heuristic weight of the path connecting `s` light vertices to `t` camera vertices, with
+    /// `sampled` standing in for the last light vertex when `s` is 1 and the camera vertex when `t` is 1.
+    ///
+    /// Following Veach, the densities of the other ways of sampling the same path only differ in a few
+    /// factors, so they are found by walking away from the connection and swapping the density each
+    /// vertex was sampled with for the one the other subpath would have sampled it with.
+    fn mis_weight(&self, scene: &Scene, light: &[Vertex], camera: &[Vertex], sampled: Option<&Vertex>, s: usize, t: usize) -> f64 {
+        if s + t == 2 {
+            return 1.0
+        }
+        let pt = match sampled {
+            Some(sampled) if t == 1 => sampled,
+            _ => &camera[t - 1],
+        };
+        let qs = match sampled {
+            _ if s == 0 => None,
+            Some(sampled) if s == 1 => Some(sampled),
+            _ => Some(&light[s - 1]),
+        };
+        let pt_minus = if t > 1 { Some(&camera[t - 2]) } else { None };
+        let qs_minus = if s > 1 { Some(&light[s - 2]) } else { None };
+
+        // Forward and reverse densities of each vertex, and whether it's delta, in this path
+        let densities = |v: &Vertex| (v.pdf_fwd, v.pdf_rev, v.delta);
+        let mut camera_side: Vec<_> = (0..t).map(|i| if i + 1 == t { pt } else { &camera[i] }).map(densities).collect();
+        let mut light_side: Vec<_> = (0..s).map(|i| match qs {
+            Some(qs) if i + 1 == s => qs,
+            _ => &light[i],
+        }).map(densities).collect();
+
+        // The connection changes the reverse densities around it, and its ends can't be delta
+        camera_side[t - 1].2 = false;
+        camera_side[t - 1].1 = match (qs, pt_minus) {
+            (Some(qs), _) => qs.pdf(scene, qs_minus, pt),
+            (None, _) => pt.pdf_light_origin(scene),
+        };
+        if s == 0 && camera_side[t - 1].1 == 0.0 {
+            // Emitters that aren't among the lights can only be found from the camera
+            return 1.0
+        }
+        if let Some(pt_minus) = pt_minus {
+            camera_side[t - 2].1 = match qs {
+                Some(qs) => pt.pdf(scene, Some(qs), pt_minus),
+                None => pt.pdf_light(pt_minus),
+            };
+        }
+        if let Some(qs) = qs {
+            light_side[s - 1].2 = false;
+            light_side[s - 1].1 = pt.pdf(scene, pt_minus, qs);
+            if let Some(qs_minus) = qs_minus {
+                light_side[s - 2].1 = qs.pdf(scene, Some(pt), qs_minus);
+            }
+        }
+
+        // Delta vertices have no density, and leave the ratio alone
+        let remap = |pdf: f64| if pdf == 0.0 { 1.0 } else { pdf };
+        let mut sum = 0.0;
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            ratio *= remap(camera_side[i].1) / remap(camera_side[i].0);
+            if !camera_side[i].2 && !camera_side[i - 1].2 {
+                sum += ratio;
+            }
+        }
+        let delta_light = qs.is_some_and(|qs| if s == 1 { qs.is_delta_light() } else { light[0].is_delta_light() });
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light_side[i].1) / remap(light_side[i].0);
+            let delta_before = if i > 0 { light_side[i - 1].2 } else { delta_light };
+            if !light_side[i].2 && !delta_before {
+                sum += ratio;
+            }
+        }
+        1.0 / (1.0 + sum)
+    }
+
+    /// Light from the background along a camera ray that left the scene, weighed against sampling the
+    /// background from the vertex it left.
+    fn escaped(&self, scene: &Scene, camera: &[Vertex], escape: &Escape) -> Color {
+        let radiance = escape.beta * scene.background.value(&escape.ray.direction);
+        if camera.len() == 1 || escape.pdf == 0.0 {
+            return radiance
+        }
+        let background_pdf = scene.background.pdf_value(&escape.ray.direction);
+        radiance * (escape.pdf / (escape.pdf + background_pdf))
+    }
+
+    /// Light from the background and directional lights, which no light subpath starts from, sampled
+    /// at a camera vertex.
+    fn sample_unbounded(&self, scene: &Scene, vertex: &Vertex, time: f64) -> Color {
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        let (rec, ray) = match &vertex.kind {
+            Kind::Surface {rec, ray} if vertex.connectible() => (rec, ray),
+            _ => return radiance,
+        };
+        if let Some(direction) = scene.background.sample() {
+            let background_pdf = scene.background.pdf_value(&direction);
+            let f = rec.material.eval(ray, rec, &direction, TransportMode::Radiance);
+            if background_pdf > 0.0 && !is_black(&f) && scene.world.hit(&Ray::new(vertex.p, direction, time), 0.001, f64::MAX).is_none() {
+                let material_pdf = rec.material.pdf(ray, rec, &direction);
+                radiance = radiance + vertex.beta * f * scene.background.value(&direction) / (background_pdf + material_pdf);
+            }
+        }
+        for light in scene.lights.unbounded() {
+            if let Some(sample) = light.sample(&vertex.p) {
+                let shadow = Ray::new(vertex.p, sample.direction, time);
+                if scene.world.hit(&shadow, 0.001, sample.distance).is_none() {
+                    radiance = radiance + vertex.beta * rec.material.eval(ray, rec, &sample.direction, TransportMode::Radiance) * sample.irradiance;
+                }
+            }
+        }
+        radiance
+    }
+}
+
+/// Picks a point on a light for `pt` to connect to, the way light subpaths start, and the light it
+/// brings along the connection, not counting visibility.
+fn sample_light<'a>(scene: &Scene<'a>, pt: &Vertex, time: f64) -> Option<(Vertex<'a>, Color)> {
+    let (light, pmf) = scene.lights.sample_emitter()?;
+    match light {
+        Sampled::Shape(_, shape) => {
+            let (rec, area_pdf) = shape.sample_surface()?;
+            let to_light = rec.p - pt.p;
+            let distance_squared = to_light.length_squared();
+            let direction = to_light.unit();
+            let cosine = -rec.geometric_normal.dot(&direction);
+            if cosine <= 0.0 || distance_squared == 0.0 {
+                return None
+            }
+            let emitted = rec.material.emitted(&Ray::new(pt.p, direction, time), &rec);
+            let origin_pdf = pmf * area_pdf;
+            let radiance = pt.beta * pt.f(&rec.p, TransportMode::Radiance) * emitted * (cosine / (distance_squared * origin_pdf));
+            Some((Vertex::new(Kind::Light(Emitter::Area), rec.p, Some(rec.geometric_normal), emitted / origin_pdf, origin_pdf), radiance))
+        },
+        Sampled::Delta(light) => {
+            let sample = light.sample(&pt.p)?;
+            let p = pt.p + sample.direction * sample.distance;
+            let radiance = pt.beta * pt.f(&p, TransportMode::Radiance) * sample.irradiance / pmf;
+            Some((Vertex::new(Kind::Light(Emitter::Delta(light)), p, None, sample.irradiance / pmf, pmf), radiance))
+        },
+    }
+}
+
+impl Integrator for Bdpt {
+    fn ray_color(&self, ray: Ray, scene: &Scene) -> Color {
+        let time = ray.time;
+        let (camera, escape) = self.camera_path(scene, ray);
+        let light = self.light_path(scene, time);
+
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        for t in 1..=camera.len() {
+            // Points picked on the lights stand in for the first light vertex, so connecting to them
+            // doesn't need the light subpath to have started
+            for s in 0..=light.len().max(1) {
+                if s + t < 2 || s + t - 2 > self.max_depth || (s == 1 && t == 1) {
+                    continue
+                }
+                radiance = radiance + self.connect(scene, &light, &camera, s, t, time);
+            }
+        }
+        if let Some(escape) = &escape {
+            radiance = radiance + self.escaped(scene, &camera, escape);
+        }
+        for vertex in camera.iter().take(self.max_depth + 1).skip(1) {
+            radiance = radiance + self.sample_unbounded(scene, vertex, time);
+        }
+        radiance
+    }
+}
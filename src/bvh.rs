@@ -1,9 +1,17 @@
 use crate::ray::{Hittable, Ray, HitRecord};
 use std::rc::Rc;
 use crate::aabb::Aabb;
-use std::mem::take;
 use crate::random_int;
-use std::cmp::Ordering;
+use std::cell::Cell;
+
+thread_local! {
+    static NODE_VISITS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Number of BVH nodes visited on this thread since the last call.
+pub fn take_node_visits() -> u64 {
+    NODE_VISITS.with(|visits| visits.replace(0))
+}
 
 pub struct BvhNode {
     left: Rc<dyn Hittable>,
@@ -18,7 +26,7 @@ impl BvhNode {
             let right = src_objects.first().unwrap().clone();
             (left, right)
         } else if src_objects.len() == 2 {
-            let left = src_objects.get(0).unwrap().clone();
+            let left = src_objects.first().unwrap().clone();
             let right = src_objects.get(1).unwrap().clone();
             (left, right)
         } else {
@@ -42,17 +50,25 @@ impl BvhNode {
 
 impl Hittable for BvhNode {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        if self.bbox.hit(ray, t_min, t_max) {
+        NODE_VISITS.with(|visits| visits.set(visits.get() + 1));
+        if !self.bbox.hit(ray, t_min, t_max) {
             return None
         }
 
+        // The right side only matters where it's closer than what was found on the left
         let hit_left = self.left.hit(ray, t_min, t_max);
-        let hit_right = self.right.hit(ray, t_min, t_max);
+        let closest = hit_left.as_ref().map_or(t_max, |rec| rec.t);
+        // Nodes over a single object hold it on both sides, and testing it twice would give stochastic
+        // surfaces like alpha masks two chances to be hit
+        if Rc::ptr_eq(&self.left, &self.right) {
+            return hit_left
+        }
+        let hit_right = self.right.hit(ray, t_min, closest);
 
-        hit_left.or(hit_right)
+        hit_right.or(hit_left)
     }
 
-    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
         Some(Aabb::new(self.bbox.min(), self.bbox.max()))
     }
 }
\ No newline at end of file
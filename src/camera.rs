@@ -1,6 +1,19 @@
 use crate::vec3::{Point3, Vec3};
 use crate::ray::Ray;
 use crate::random_double;
+use std::f64::consts::PI;
+
+/// Point on the lens that light from some point in the scene can reach the film through.
+pub struct LensSample {
+    pub point: Point3,
+    /// Importance of light arriving through `point`, see `Camera::importance`.
+    pub importance: f64,
+    /// Density per unit solid angle, seen from the point in the scene, of having picked `point`.
+    pub pdf: f64,
+    /// Coordinates of `get_ray` the light lands at.
+    pub s: f64,
+    pub t: f64,
+}
 
 pub struct Camera{
     origin: Point3,
@@ -11,11 +24,13 @@ pub struct Camera{
     v: Vec3,
     w: Vec3,
     lens_radius: f64,
+    focus_dist: f64,
     time0: f64,
     time1: f64,
 }
 
 impl Camera{
+    #[allow(clippy::too_many_arguments)]
     pub fn new(lookfrom:Point3, lookat:Point3, vup:Vec3, vfov: f64, aspect_ratio: f64, aperture: f64, focus_dist: f64, time0: f64, time1: f64) -> Camera {
         let theta = vfov.to_radians();
         let h = (theta/2.0).tan();
@@ -23,7 +38,7 @@ impl Camera{
         let viewport_width = aspect_ratio * viewport_height;
 
         let w = (lookfrom - lookat).unit();
-        let u = (&vup.cross(&w)).unit();
+        let u = vup.cross(&w).unit();
         let v = w.cross(&u);
 
         let origin = lookfrom;
@@ -33,7 +48,7 @@ impl Camera{
 
         let lens_radius = aperture/2.0;
         Camera{
-            origin, lower_left_corner, horizontal, vertical, u, v, w, lens_radius, time0, time1,
+            origin, lower_left_corner, horizontal, vertical, u, v, w, lens_radius, focus_dist, time0, time1,
         }
     }
 
@@ -44,6 +59,65 @@ impl Camera{
             origin: self.origin + offset,
             direction: self.lower_left_corner + (self.horizontal * s) + (self.vertical * t) - self.origin - offset,
             time: random_double(self.time0, self.time1),
+            wavelengths: None,
+        }
+    }
+
+    // Light tracing needs the camera as a sensor: how much light arriving along a ray counts towards the
+    // image, called importance. It's normalized so that the camera rays `get_ray` picks, uniformly over
+    // the lens and the film, each measure exactly the radiance they carry.
+
+    /// Direction the camera looks in, which is also the normal of the lens.
+    pub fn forward(&self) -> Vec3 {
+        -self.w
+    }
+
+    /// Importance of light reaching the lens at `origin` from `-direction`, and the coordinates of
+    /// `get_ray` it lands at, or `None` when it misses the film.
+    pub fn importance(&self, origin: &Point3, direction: &Vec3) -> Option<(f64, f64, f64)> {
+        let direction = direction.unit();
+        let cos_theta = direction.dot(&self.forward());
+        if cos_theta <= 0.0 {
+            return None
         }
+        let offset = *origin + direction * (self.focus_dist / cos_theta) - self.lower_left_corner;
+        let s = offset.dot(&self.horizontal) / self.horizontal.length_squared();
+        let t = offset.dot(&self.vertical) / self.vertical.length_squared();
+        if !(0.0..1.0).contains(&s) || !(0.0..1.0).contains(&t) {
+            return None
+        }
+        let cos2_theta = cos_theta * cos_theta;
+        Some((1.0 / (self.film_area() * self.lens_area() * cos2_theta * cos2_theta), s, t))
     }
-}
\ No newline at end of file
+
+    /// Density per unit solid angle with which `get_ray` leaves the lens at `origin` along `direction`.
+    pub fn pdf(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self.importance(origin, direction).is_none() {
+            return 0.0
+        }
+        let cos_theta = direction.unit().dot(&self.forward());
+        1.0 / (self.film_area() * cos_theta * cos_theta * cos_theta)
+    }
+
+    /// Picks a point on the lens for light from `p` to reach the film through.
+    pub fn sample_lens(&self, p: &Point3) -> Option<LensSample> {
+        let rd = Vec3::random_in_unit_disk() * self.lens_radius;
+        let point = self.origin + self.u * rd.x() + self.v * rd.y();
+        let to_lens = point - *p;
+        let distance_squared = to_lens.length_squared();
+        let (importance, s, t) = self.importance(&point, &-to_lens)?;
+        let cos_lens = -to_lens.unit().dot(&self.forward());
+        let pdf = distance_squared / (cos_lens * self.lens_area());
+        Some(LensSample {point, importance, pdf, s, t})
+    }
+
+    /// Area of the film scaled to a distance of one from the lens.
+    fn film_area(&self) -> f64 {
+        self.horizontal.length() * self.vertical.length() / (self.focus_dist * self.focus_dist)
+    }
+
+    /// Area of the lens, with pinholes counted as one so that their density per unit area is one.
+    fn lens_area(&self) -> f64 {
+        if self.lens_radius > 0.0 { PI * self.lens_radius * self.lens_radius } else { 1.0 }
+    }
+}
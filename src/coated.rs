@@ -0,0 +1,283 @@
+use crate::fresnel::{fresnel_dielectric, refract};
+use crate::material::{Lobe, Material, Scatter, TransportMode};
+use crate::medium::Absorption;
+use crate::microfacet::{RoughInterface, TrowbridgeReitz};
+use crate::onb::Onb;
+use crate::random_double;
+use crate::ray::{HitRecord, Ray};
+use crate::texture::Texture;
+use crate::vec3::{Color, Vec3};
+use std::rc::Rc;
+
+// Bounces between the coat and the base before a path is given up on.
+const MAX_INTERNAL_BOUNCES: i32 = 16;
+
+/// Clear coat over any other material, like the lacquer on car paint or the glaze on ceramics. Light is
+/// followed through the layers stochastically: it's refracted into the coat, tinted on its way down,
+/// scattered by the base and bounces between the base and the underside of the coat until it gets out.
+/// The coat is thin enough that the point where light leaves is the point where it entered.
+pub struct Coated {
+    base: Rc<dyn Material>,
+    ior: f64,
+    roughness: Option<Box<dyn Texture>>,
+    absorption: Absorption,
+}
+
+impl Coated {
+    pub fn new(base: Rc<dyn Material>, ior: f64) -> Coated {
+        Coated {base, ior, roughness: None, absorption: Absorption::none()}
+    }
+
+    /// Makes the coat rough, with a perceptual GGX roughness read from the luminance of `roughness`.
+    pub fn with_roughness(mut self, roughness: Box<dyn Texture>) -> Coated {
+        self.roughness = Some(roughness);
+        self
+    }
+
+    /// Tints the coat so `transmittance` of the light is left after crossing it straight down once.
+    pub fn with_absorption(mut self, transmittance: Color) -> Coated {
+        self.absorption = Absorption::from_transmittance(transmittance, 1.0);
+        self
+    }
+
+    fn distribution(&self, rec: &HitRecord) -> Option<TrowbridgeReitz> {
+        self.roughness.as_ref().map(|roughness| {
+            let alpha = TrowbridgeReitz::roughness_to_alpha(roughness.value(rec.u, rec.v, &rec.p).luminance());
+            TrowbridgeReitz::new(alpha, alpha)
+        })
+    }
+
+    /// Share of the light left after `distance` through the coat, at the wavelengths of `r_in` when the
+    /// base gives its values at them.
+    fn transmittance(&self, r_in: &Ray, distance: f64) -> Color {
+        let transmittance = self.absorption.transmittance(distance);
+        match r_in.wavelengths {
+            Some(wavelengths) if self.base.is_spectral() => wavelengths.upsample(&transmittance),
+            _ => transmittance,
+        }
+    }
+
+    /// Direction light from `wo` above the coat continues in inside it, tried a few times on rough coats
+    /// as the microfacet picked may reflect it.
+    fn transmit(&self, wo: &Vec3, distribution: &Option<TrowbridgeReitz>) -> Option<Vec3> {
+        match distribution {
+            Some(distribution) => (0..4).find_map(|_| {
+                let wm = distribution.sample_visible_normal(wo, random_double(0.0, 1.0), random_double(0.0, 1.0));
+                refract(wo, &wm, self.ior).filter(|wi| wi.z() < 0.0)
+            }),
+            None => refract(wo, &Vec3::new(0.0, 0.0, 1.0), self.ior),
+        }
+    }
+
+    /// Crosses or reflects off the coat for light going in direction `-wo`, with `wo` above the surface
+    /// and `eta` the index on the far side over the near side. Returns the new direction and its weight.
+    fn interface(&self, wo: &Vec3, eta: f64, distribution: &Option<TrowbridgeReitz>) -> Option<(Vec3, f64)> {
+        match distribution {
+            Some(distribution) => {
+                let interface = RoughInterface {distribution, eta};
+                let wi = interface.sample(wo, random_double(0.0, 1.0), random_double(0.0, 1.0), random_double(0.0, 1.0))?;
+                let (f, pdf) = interface.evaluate(wo, &wi);
+                if pdf == 0.0 {
+                    return None
+                }
+                Some((wi, f * wi.z().abs() / pdf))
+            },
+            None => {
+                let normal = Vec3::new(0.0, 0.0, 1.0);
+                if random_double(0.0, 1.0) < fresnel_dielectric(wo.z(), eta) {
+                    Some((Vec3::new(-wo.x(), -wo.y(), wo.z()), 1.0))
+                } else {
+                    Some((refract(wo, &normal, eta)?, 1.0))
+                }
+            },
+        }
+    }
+}
+
+/// Ray down through the coat onto the base at `rec`, carrying the wavelengths of `r_in`.
+fn towards_base(rec: &HitRecord, down: &Vec3, r_in: &Ray) -> Ray {
+    Ray {wavelengths: r_in.wavelengths, ..Ray::new(rec.p - *down, *down, r_in.time)}
+}
+
+impl Material for Coated {
+    fn sample(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        if !rec.front_face {
+            return self.base.sample(r_in, rec)
+        }
+        let frame = Onb::from_w_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        if wo.z() <= 0.0 {
+            return None
+        }
+        let distribution = self.distribution(rec);
+
+        // Reflect off the top of the coat, or enter it
+        let (mut direction, weight) = self.interface(&wo, self.ior, &distribution)?;
+        let mut attenuation = Color::new(weight, weight, weight);
+        if direction.z() > 0.0 {
+            let scattered = Ray::new(rec.p, frame.local(&direction), r_in.time);
+            return Some(match distribution {
+                Some(_) => Scatter::new(attenuation, scattered, self.pdf(r_in, rec, &scattered.direction), Lobe::Glossy),
+                None => Scatter::specular(attenuation, scattered),
+            })
+        }
+
+        let flip = |d: &Vec3| Vec3::new(d.x(), d.y(), -d.z());
+        // Paths that scatter specularly off the base can't be found by evaluating the layers
+        let mut evaluable = true;
+        for bounce in 0..MAX_INTERNAL_BOUNCES {
+            // Down through the coat to the base, and back up
+            attenuation = attenuation * self.transmittance(r_in, 1.0 / direction.z().abs());
+            let down = frame.local(&direction);
+            let base = self.base.sample(&towards_base(rec, &down, r_in), rec)?;
+            evaluable = evaluable && base.pdf > 0.0;
+            direction = frame.to_local(&base.scatter.direction.unit());
+            if direction.z() <= 0.0 {
+                return None
+            }
+            attenuation = attenuation * base.attenuation * self.transmittance(r_in, 1.0 / direction.z());
+
+            // Leave through the underside of the coat, or bounce back down
+            let (next, weight) = self.interface(&flip(&-direction), 1.0 / self.ior, &distribution)?;
+            attenuation = attenuation * weight;
+            direction = flip(&next);
+            if direction.z() > 0.0 {
+                let scattered = Ray::new(rec.p, frame.local(&direction), r_in.time);
+                let pdf = if evaluable { self.pdf(r_in, rec, &scattered.direction) } else { 0.0 };
+                return Some(Scatter::new(attenuation, scattered, pdf, base.lobe))
+            }
+
+            if bounce >= 3 {
+                let survival = attenuation.x().max(attenuation.y()).max(attenuation.z()).min(0.95);
+                if random_double(0.0, 1.0) >= survival {
+                    return None
+                }
+                attenuation = attenuation / survival;
+            }
+        }
+        None
+    }
+
+    /// Estimated stochastically by a random walk through the layers like the one `sample` takes, which
+    /// at every visit to the base connects to a way out towards `direction`. The way out is picked from
+    /// that side of the coat, as the light leaving through it would have crossed it the other way
+    /// (Guo et al. 2018).
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3, mode: TransportMode) -> Color {
+        if !rec.front_face {
+            return self.base.eval(r_in, rec, direction, mode)
+        }
+        let frame = Onb::from_w_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        let wi = frame.to_local(&direction.unit());
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0)
+        }
+        let distribution = self.distribution(rec);
+        let reflection = distribution.as_ref().map_or(0.0, |distribution| {
+            RoughInterface {distribution, eta: self.ior}.evaluate(&wo, &wi).0 * wi.z()
+        });
+        let mut f = Color::new(reflection, reflection, reflection);
+
+        // The way out, as a direction inside the coat going down. Crossing it upwards instead takes the
+        // squared index of refraction out, and the change of the cosine from inside to outside
+        let (exit, weight) = match self.interface(&wi, self.ior, &distribution) {
+            Some((exit, weight)) if exit.z() < 0.0 => (exit, weight),
+            _ => return f,
+        };
+        let exit_weight = self.transmittance(r_in, 1.0 / exit.z().abs()) * (weight * wi.z() / (self.ior * self.ior * exit.z().abs()));
+        let up = frame.local(&-exit);
+
+        let (mut direction, weight) = match self.interface(&wo, self.ior, &distribution) {
+            Some((direction, weight)) if direction.z() < 0.0 => (direction, weight),
+            _ => return f,
+        };
+        let mut throughput = Color::new(weight, weight, weight);
+        let flip = |d: &Vec3| Vec3::new(d.x(), d.y(), -d.z());
+        for bounce in 0..MAX_INTERNAL_BOUNCES {
+            throughput = throughput * self.transmittance(r_in, 1.0 / direction.z().abs());
+            let down = frame.local(&direction);
+            let to_base = towards_base(rec, &down, r_in);
+            f = f + throughput * self.base.eval(&to_base, rec, &up, mode) * exit_weight;
+
+            // Light leaving through the coat was found by the connections, so the walk only goes on
+            // where the coat reflects it back down
+            let base = match self.base.sample(&to_base, rec) {
+                Some(base) => base,
+                None => break,
+            };
+            let up_from_base = frame.to_local(&base.scatter.direction.unit());
+            if up_from_base.z() <= 0.0 {
+                break
+            }
+            throughput = throughput * base.attenuation * self.transmittance(r_in, 1.0 / up_from_base.z());
+            match self.interface(&flip(&-up_from_base), 1.0 / self.ior, &distribution) {
+                Some((next, weight)) if next.z() > 0.0 => {
+                    throughput = throughput * weight;
+                    direction = flip(&next);
+                },
+                _ => break,
+            }
+
+            if bounce >= 3 {
+                let survival = throughput.x().max(throughput.y()).max(throughput.z()).min(0.95);
+                if random_double(0.0, 1.0) >= survival {
+                    break
+                }
+                throughput = throughput / survival;
+            }
+        }
+        f
+    }
+
+    /// Estimated from the reflection off the top of the coat and the base's density between the
+    /// directions refracted into the coat from either side. Light bouncing inside the coat more than
+    /// once is left out.
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        if !rec.front_face {
+            return self.base.pdf(r_in, rec, direction)
+        }
+        let frame = Onb::from_w_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        let wi = frame.to_local(&direction.unit());
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0
+        }
+        let distribution = self.distribution(rec);
+        let reflection = distribution.as_ref().map_or(0.0, |distribution| {
+            RoughInterface {distribution, eta: self.ior}.evaluate(&wo, &wi).1
+        });
+        let (entry, exit) = match (self.transmit(&wo, &distribution), self.transmit(&wi, &distribution)) {
+            (Some(entry), Some(exit)) => (entry, exit),
+            _ => return reflection,
+        };
+        let down = frame.local(&entry);
+        let base = self.base.pdf(&towards_base(rec, &down, r_in), rec, &frame.local(&-exit));
+        let through = (1.0 - fresnel_dielectric(wo.z(), self.ior)) * base * wi.z() / (self.ior * self.ior * exit.z().abs());
+        reflection + through
+    }
+
+    /// Light the base emits, seen through the coat as if it were smooth: tinted on its way up and cut
+    /// down by what the coat reflects back.
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        if !rec.front_face {
+            return self.base.emitted(r_in, rec)
+        }
+        let frame = Onb::from_w_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        let entry = match refract(&wo, &Vec3::new(0.0, 0.0, 1.0), self.ior) {
+            Some(entry) if wo.z() > 0.0 => entry,
+            _ => return Color::new(0.0, 0.0, 0.0),
+        };
+        let down = frame.local(&entry);
+        let transmittance = self.absorption.transmittance(1.0 / entry.z().abs()) * (1.0 - fresnel_dielectric(wo.z(), self.ior));
+        self.base.emitted(&towards_base(rec, &down, r_in), rec) * transmittance
+    }
+
+    fn emission(&self) -> Color {
+        self.base.emission() * self.absorption.transmittance(1.0) * (1.0 - fresnel_dielectric(1.0, self.ior))
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.base.is_spectral()
+    }
+}
@@ -0,0 +1,142 @@
+use crate::fresnel::fresnel_conductor;
+use crate::material::{Lobe, Material, Scatter, TransportMode};
+use crate::microfacet::{reflect, TrowbridgeReitz};
+use crate::onb::Onb;
+use crate::random_double;
+use crate::ray::{HitRecord, Ray};
+use crate::spectrum::{read_ior, Spectrum};
+use crate::texture::Texture;
+use crate::thin_film::ThinFilm;
+use crate::vec3::{Color, Vec3};
+use std::io;
+
+/// Rough metal with a GGX microfacet distribution and the Fresnel reflectance of a complex index of
+/// refraction. Roughness is perceptual, in [0, 1], and read from the luminance of its textures; when a
+/// separate `roughness_v` is given, `roughness` applies along dp/du and `roughness_v` along dp/dv.
+///
+/// Conductors loaded from measured data keep it, and in spectral mode reflect at the index of
+/// refraction of each wavelength a path carries, rather than one for each color channel.
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    measured: Option<(Spectrum, Spectrum)>,
+    roughness: Box<dyn Texture>,
+    roughness_v: Option<Box<dyn Texture>>,
+    thin_film: Option<ThinFilm>,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: Box<dyn Texture>) -> Conductor {
+        Conductor {eta, k, measured: None, roughness, roughness_v: None, thin_film: None}
+    }
+
+    /// Makes the surface anisotropic, with `roughness_v` along dp/dv.
+    pub fn with_roughness_v(mut self, roughness_v: Box<dyn Texture>) -> Conductor {
+        self.roughness_v = Some(roughness_v);
+        self
+    }
+
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Conductor {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    /// Metal with the measured index of refraction in a file `read_ior` reads.
+    pub fn from_ior_file(path: &str, roughness: Box<dyn Texture>) -> io::Result<Conductor> {
+        let (eta, k) = read_ior(path)?;
+        let conductor = Conductor::new(eta.to_rgb(), k.to_rgb(), roughness);
+        Ok(Conductor {measured: Some((eta, k)), ..conductor})
+    }
+
+    pub fn gold(roughness: Box<dyn Texture>) -> Conductor {
+        Conductor::new(Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603), roughness)
+    }
+
+    pub fn copper(roughness: Box<dyn Texture>) -> Conductor {
+        Conductor::new(Color::new(0.200, 0.924, 1.102), Color::new(3.912, 2.452, 2.142), roughness)
+    }
+
+    pub fn aluminium(roughness: Box<dyn Texture>) -> Conductor {
+        Conductor::new(Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837), roughness)
+    }
+
+    pub fn silver(roughness: Box<dyn Texture>) -> Conductor {
+        Conductor::new(Color::new(0.155, 0.117, 0.138), Color::new(4.828, 3.122, 2.147), roughness)
+    }
+
+    fn distribution(&self, rec: &HitRecord) -> TrowbridgeReitz {
+        let roughness_u = self.roughness.value(rec.u, rec.v, &rec.p).luminance();
+        let roughness_v = self.roughness_v.as_ref().map_or(roughness_u, |t| t.value(rec.u, rec.v, &rec.p).luminance());
+        TrowbridgeReitz::new(TrowbridgeReitz::roughness_to_alpha(roughness_u), TrowbridgeReitz::roughness_to_alpha(roughness_v))
+    }
+
+    /// Fresnel reflectance for the microfacet normal `wm`, with the multiple scattering compensation for
+    /// light arriving from `wo`, at the wavelengths of `r_in` if the index of refraction was measured.
+    fn reflectance(&self, r_in: &Ray, rec: &HitRecord, distribution: &TrowbridgeReitz, wo: &Vec3, wm: &Vec3) -> Color {
+        let (eta, k, lambdas) = match (&self.measured, r_in.wavelengths) {
+            (Some((eta, k)), Some(wavelengths)) => {
+                let lambdas = wavelengths.lambdas();
+                let at = |spectrum: &Spectrum| Color::new(spectrum.evaluate(lambdas[0]), spectrum.evaluate(lambdas[1]), spectrum.evaluate(lambdas[2]));
+                (at(eta), at(k), Some(lambdas))
+            },
+            _ => (self.eta, self.k, None),
+        };
+        let fresnel = match (&self.thin_film, lambdas) {
+            (Some(film), Some(lambdas)) => film.reflectance_conductor_at(rec, wo.dot(wm), lambdas, &eta, &k),
+            (Some(film), None) => film.reflectance_conductor(rec, wo.dot(wm), &eta, &k),
+            (None, _) => fresnel_conductor(wo.dot(wm), &eta, &k),
+        };
+        let f0 = fresnel_conductor(1.0, &eta, &k);
+        let compensation = Color::new(
+            distribution.multiple_scattering_compensation(wo, f0.r()),
+            distribution.multiple_scattering_compensation(wo, f0.g()),
+            distribution.multiple_scattering_compensation(wo, f0.b()),
+        );
+        fresnel * compensation
+    }
+}
+
+impl Material for Conductor {
+    fn sample(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let frame = Onb::from_w_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        if wo.z() <= 0.0 {
+            return None
+        }
+        let distribution = self.distribution(rec);
+        let wm = distribution.sample_visible_normal(&wo, random_double(0.0, 1.0), random_double(0.0, 1.0));
+        let wi = reflect(&wo, &wm);
+        if wi.z() <= 0.0 {
+            return None
+        }
+
+        // With visible normal sampling only the shadowing of the outgoing direction remains in the weight
+        let attenuation = self.reflectance(r_in, rec, &distribution, &wo, &wm) * (distribution.g(&wo, &wi) / distribution.g1(&wo));
+        let pdf = distribution.reflection(&wo, &wi).1;
+        Some(Scatter::new(attenuation, Ray::new(rec.p, frame.local(&wi), r_in.time), pdf, Lobe::Glossy))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3, _mode: TransportMode) -> Color {
+        let frame = Onb::from_w_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        let wi = frame.to_local(&direction.unit());
+        let distribution = self.distribution(rec);
+        let (f, _) = distribution.reflection(&wo, &wi);
+        if f == 0.0 {
+            return Color::new(0.0, 0.0, 0.0)
+        }
+        let wm = (wo + wi).unit();
+        self.reflectance(r_in, rec, &distribution, &wo, &wm) * (f * wi.z())
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let frame = Onb::from_w_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        let wi = frame.to_local(&direction.unit());
+        self.distribution(rec).reflection(&wo, &wi).1
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.measured.is_some()
+    }
+}
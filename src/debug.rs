@@ -0,0 +1,116 @@
+use crate::bvh::take_node_visits;
+use crate::integrator::{Integrator, Scene};
+use crate::ray::{HitRecord, Ray};
+use crate::vec3::Color;
+use std::rc::Rc;
+
+// These show a property of the first surface each camera ray hits as a false color, for finding out
+// quickly what's wrong with a scene. Rays that miss everything are black.
+
+fn first_hit(ray: &Ray, scene: &Scene) -> Option<HitRecord> {
+    scene.world.hit(ray, 0.001, f64::MAX)
+}
+
+/// Outward shading normal, with each axis mapped from [-1, 1] to [0, 1].
+pub struct Normals;
+
+impl Integrator for Normals {
+    fn ray_color(&self, ray: Ray, scene: &Scene) -> Color {
+        match first_hit(&ray, scene) {
+            Some(rec) => {
+                let normal = if rec.front_face { rec.normal } else { -rec.normal };
+                (normal + Color::new(1.0, 1.0, 1.0)) * 0.5
+            },
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// Texture coordinates, u in red and v in green.
+pub struct Uv;
+
+impl Integrator for Uv {
+    fn ray_color(&self, ray: Ray, scene: &Scene) -> Color {
+        match first_hit(&ray, scene) {
+            Some(rec) => Color::new(rec.u, rec.v, 0.0),
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// Distance to the camera, from white up close to black at `max_distance`.
+pub struct Depth {
+    max_distance: f64,
+}
+
+impl Depth {
+    pub fn new(max_distance: f64) -> Depth {
+        Depth {max_distance}
+    }
+}
+
+impl Integrator for Depth {
+    fn ray_color(&self, ray: Ray, scene: &Scene) -> Color {
+        match first_hit(&ray, scene) {
+            Some(rec) => {
+                let shade = (1.0 - rec.t * ray.direction.length() / self.max_distance).max(0.0);
+                Color::new(shade, shade, shade)
+            },
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// Barycentric coordinates of the hit in the two triangles that split the (u, v) square of a surface.
+/// These are the halves of quads, and show how their corners map to the texture.
+pub struct Barycentrics;
+
+impl Integrator for Barycentrics {
+    fn ray_color(&self, ray: Ray, scene: &Scene) -> Color {
+        match first_hit(&ray, scene) {
+            Some(rec) if rec.u + rec.v <= 1.0 => Color::new(1.0 - rec.u - rec.v, rec.u, rec.v),
+            Some(rec) => Color::new(rec.u + rec.v - 1.0, 1.0 - rec.v, 1.0 - rec.u),
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// A color per material, so that objects sharing one look the same.
+pub struct MaterialId;
+
+impl Integrator for MaterialId {
+    fn ray_color(&self, ray: Ray, scene: &Scene) -> Color {
+        let rec = match first_hit(&ray, scene) {
+            Some(rec) => rec,
+            None => return Color::new(0.0, 0.0, 0.0),
+        };
+        // Scramble the address so neighboring allocations get unrelated colors
+        let mut x = Rc::as_ptr(&rec.material) as *const () as usize as u64;
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+        x ^= x >> 31;
+        let channel = |shift: u32| 0.2 + 0.8 * ((x >> shift) & 0xff) as f64 / 255.0;
+        Color::new(channel(0), channel(8), channel(16))
+    }
+}
+
+/// BVH nodes the camera ray visits, from blue for none to red for `max_visits` or more.
+pub struct BvhVisits {
+    max_visits: f64,
+}
+
+impl BvhVisits {
+    pub fn new(max_visits: f64) -> BvhVisits {
+        BvhVisits {max_visits}
+    }
+}
+
+impl Integrator for BvhVisits {
+    fn ray_color(&self, ray: Ray, scene: &Scene) -> Color {
+        take_node_visits();
+        first_hit(&ray, scene);
+        let t = (take_node_visits() as f64 / self.max_visits).min(1.0);
+        let ramp = |center: f64| (1.5 - (4.0 * t - center).abs()).clamp(0.0, 1.0);
+        Color::new(ramp(3.0), ramp(2.0), ramp(1.0))
+    }
+}
@@ -0,0 +1,141 @@
+/// Piecewise-constant 1D distribution over [0, 1) built from non-negative function values, sampled by
+/// inverting its CDF.
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    func_int: f64,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f64>) -> Distribution1D {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 1..=n {
+            cdf[i] = cdf[i - 1] + func[i - 1] / n as f64;
+        }
+        let func_int = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+            *c = if func_int == 0.0 { i as f64 / n as f64 } else { *c / func_int };
+        }
+        Distribution1D {func, cdf, func_int}
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn func_int(&self) -> f64 {
+        self.func_int
+    }
+
+    /// Returns a point in [0, 1), its density and the index of the segment it falls in.
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        let offset = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(self.count() - 1);
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+        let pdf = if self.func_int > 0.0 { self.func[offset] / self.func_int } else { 0.0 };
+        ((offset as f64 + du) / self.count() as f64, pdf, offset)
+    }
+
+    /// Density of the segment containing `x`.
+    pub fn pdf(&self, x: f64) -> f64 {
+        if self.func_int == 0.0 {
+            return 0.0
+        }
+        let offset = ((x * self.count() as f64) as usize).min(self.count() - 1);
+        self.func[offset] / self.func_int
+    }
+}
+
+/// Piecewise-constant 2D distribution over [0, 1)^2, sampled as a marginal over rows followed by the
+/// conditional distribution within the chosen row.
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `func` holds `nv` rows of `nu` values each.
+    pub fn new(func: &[f64], nu: usize, nv: usize) -> Distribution2D {
+        let conditional: Vec<Distribution1D> = (0..nv)
+            .map(|v| Distribution1D::new(func[v * nu..(v + 1) * nu].to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|c| c.func_int()).collect());
+        Distribution2D {conditional, marginal}
+    }
+
+    pub fn sample_continuous(&self, u0: f64, u1: f64) -> ((f64, f64), f64) {
+        let (y, pdf_y, row) = self.marginal.sample_continuous(u1);
+        let (x, pdf_x, _) = self.conditional[row].sample_continuous(u0);
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, x: f64, y: f64) -> f64 {
+        let row = ((y * self.marginal.count() as f64) as usize).min(self.marginal.count() - 1);
+        self.marginal.pdf(y) * self.conditional[row].pdf(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_segments_in_proportion_to_their_values() {
+        let distribution = Distribution1D::new(vec![1.0, 3.0, 0.0, 4.0]);
+        let mut counts = [0; 4];
+        let n = 8000;
+        for i in 0..n {
+            let (x, pdf, offset) = distribution.sample_continuous((i as f64 + 0.5) / n as f64);
+            assert_eq!(offset, (x * 4.0) as usize);
+            assert_eq!(pdf, distribution.pdf(x));
+            counts[offset] += 1;
+        }
+        assert_eq!(counts, [1000, 3000, 0, 4000]);
+        assert_eq!(distribution.func_int(), 2.0);
+    }
+
+    #[test]
+    fn densities_integrate_to_one() {
+        let distribution = Distribution1D::new(vec![0.5, 0.0, 2.0]);
+        let integral: f64 = (0..300).map(|i| distribution.pdf((i as f64 + 0.5) / 300.0) / 300.0).sum();
+        assert!((integral - 1.0).abs() < 1e-9);
+
+        let func = [1.0, 2.0, 0.0, 0.0, 5.0, 1.0];
+        let distribution = Distribution2D::new(&func, 3, 2);
+        let n = 60;
+        let mut integral = 0.0;
+        for j in 0..n {
+            for i in 0..n {
+                integral += distribution.pdf((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64) / (n * n) as f64;
+            }
+        }
+        assert!((integral - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn two_dimensional_samples_have_the_density_of_their_point() {
+        let func = [1.0, 2.0, 0.0, 0.0, 5.0, 1.0];
+        let distribution = Distribution2D::new(&func, 3, 2);
+        for j in 0..20 {
+            for i in 0..20 {
+                let ((x, y), pdf) = distribution.sample_continuous((i as f64 + 0.5) / 20.0, (j as f64 + 0.5) / 20.0);
+                assert!(pdf > 0.0);
+                assert!((pdf - distribution.pdf(x, y)).abs() < 1e-12);
+                let value = func[(y * 2.0) as usize * 3 + (x * 3.0) as usize];
+                assert!((pdf - value * 6.0 / 9.0).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn all_zero_functions_sample_uniformly_with_no_density() {
+        let distribution = Distribution1D::new(vec![0.0, 0.0]);
+        let (x, pdf, offset) = distribution.sample_continuous(0.75);
+        assert_eq!((x, pdf, offset), (0.75, 0.0, 1));
+        assert_eq!(distribution.pdf(0.2), 0.0);
+    }
+}
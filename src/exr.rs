@@ -0,0 +1,174 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Channel of an image: its name, like `R` or `albedo.R` for a channel of a layer, and its values in
+/// row-major order starting from the top-left corner.
+pub struct Channel {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+/// Writes an OpenEXR image with the given channels as 32-bit floats, uncompressed, one scanline per block.
+/// Channels named `layer.X` show up as layers in compositors.
+pub fn write_exr(path: &str, width: usize, height: usize, channels: &mut [Channel]) -> io::Result<()> {
+    // The format wants channels sorted by name, in the header and in every scanline
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+    let mut list = Vec::new();
+    for channel in channels.iter() {
+        list.extend_from_slice(channel.name.as_bytes());
+        list.push(0);
+        list.extend_from_slice(&2i32.to_le_bytes());
+        list.extend_from_slice(&[0, 0, 0, 0]);
+        list.extend_from_slice(&1i32.to_le_bytes());
+        list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    list.push(0);
+    attribute(&mut header, "channels", "chlist", &list);
+    attribute(&mut header, "compression", "compression", &[0]);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1].iter().flat_map(|v| v.to_le_bytes()).collect();
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0u8; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(&header)?;
+    // Offsets of the scanlines, each of which starts with its row and size
+    let line_size = channels.len() * width * 4;
+    let first = header.len() + height * 8;
+    for y in 0..height {
+        file.write_all(&((first + y * (line_size + 8)) as u64).to_le_bytes())?;
+    }
+    for y in 0..height {
+        file.write_all(&(y as i32).to_le_bytes())?;
+        file.write_all(&(line_size as i32).to_le_bytes())?;
+        for channel in channels.iter() {
+            for value in &channel.values[y * width..(y + 1) * width] {
+                file.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn f32_at(bytes: &[u8], at: usize) -> f32 {
+        f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    /// Null-terminated string at `at`, and where the bytes after it start.
+    fn string_at(bytes: &[u8], at: usize) -> (String, usize) {
+        let end = at + bytes[at..].iter().position(|&b| b == 0).unwrap();
+        (String::from_utf8(bytes[at..end].to_vec()).unwrap(), end + 1)
+    }
+
+    struct Attribute {
+        name: String,
+        kind: String,
+        value: Vec<u8>,
+    }
+
+    /// Writes the image and reads back the attributes of its header, the whole file, and where the header ends.
+    fn write_and_read(name: &str, width: usize, height: usize, channels: &mut [Channel]) -> (Vec<Attribute>, Vec<u8>, usize) {
+        let path = std::env::temp_dir().join(format!("exr-{0}-{1}.exr", name, std::process::id()));
+        write_exr(path.to_str().unwrap(), width, height, channels).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(u32_at(&bytes, 0), 20000630);
+        assert_eq!(u32_at(&bytes, 4), 2);
+        let mut attributes = Vec::new();
+        let mut at = 8;
+        while bytes[at] != 0 {
+            let (name, next) = string_at(&bytes, at);
+            let (kind, next) = string_at(&bytes, next);
+            let size = u32_at(&bytes, next) as usize;
+            attributes.push(Attribute {name, kind, value: bytes[next + 4..next + 4 + size].to_vec()});
+            at = next + 4 + size;
+        }
+        (attributes, bytes, at + 1)
+    }
+
+    #[test]
+    fn writes_the_required_attributes_in_order() {
+        let mut channels = [Channel {name: "R".to_string(), values: vec![0.0; 6]}];
+        let (attributes, _, _) = write_and_read("attributes", 3, 2, &mut channels);
+        let names: Vec<(&str, &str)> = attributes.iter().map(|a| (a.name.as_str(), a.kind.as_str())).collect();
+        assert_eq!(names, [
+            ("channels", "chlist"), ("compression", "compression"), ("dataWindow", "box2i"), ("displayWindow", "box2i"),
+            ("lineOrder", "lineOrder"), ("pixelAspectRatio", "float"), ("screenWindowCenter", "v2f"),
+            ("screenWindowWidth", "float"),
+        ]);
+        assert_eq!(attributes[1].value, [0]);
+        let window: Vec<u32> = (0..4).map(|i| u32_at(&attributes[2].value, 4 * i)).collect();
+        assert_eq!(window, [0, 0, 2, 1]);
+        assert_eq!(attributes[3].value, attributes[2].value);
+        assert_eq!(f32_at(&attributes[5].value, 0), 1.0);
+    }
+
+    #[test]
+    fn sorts_the_channels_by_name() {
+        let mut channels = ["albedo.R", "R", "B", "G"].iter()
+            .map(|name| Channel {name: name.to_string(), values: vec![0.0; 1]}).collect::<Vec<Channel>>();
+        let (attributes, _, _) = write_and_read("channels", 1, 1, &mut channels);
+        let list = &attributes[0].value;
+        let mut names = Vec::new();
+        let mut at = 0;
+        while list[at] != 0 {
+            let (name, next) = string_at(list, at);
+            // 32-bit floats, linear, and not subsampled
+            assert_eq!([u32_at(list, next), u32_at(list, next + 4), u32_at(list, next + 8), u32_at(list, next + 12)], [2, 0, 1, 1]);
+            names.push(name);
+            at = next + 16;
+        }
+        assert_eq!(at, list.len() - 1);
+        assert_eq!(names, ["B", "G", "R", "albedo.R"]);
+    }
+
+    #[test]
+    fn points_the_offsets_at_scanlines_of_every_channel() {
+        let (width, height) = (3, 2);
+        let mut channels = [
+            Channel {name: "G".to_string(), values: (0..6).map(|v| 10.0 + v as f32).collect()},
+            Channel {name: "B".to_string(), values: (0..6).map(|v| v as f32).collect()},
+        ];
+        let (_, bytes, end) = write_and_read("scanlines", width, height, &mut channels);
+        let line_size = 2 * width * 4;
+        for y in 0..height {
+            let offset = u32_at(&bytes, end + 8 * y) as usize;
+            assert_eq!(u32_at(&bytes, end + 8 * y + 4), 0);
+            assert_eq!(u32_at(&bytes, offset) as usize, y);
+            assert_eq!(u32_at(&bytes, offset + 4) as usize, line_size);
+            let values: Vec<f32> = (0..2 * width).map(|i| f32_at(&bytes, offset + 8 + 4 * i)).collect();
+            let row = (y * width) as f32;
+            assert_eq!(values, [row, row + 1.0, row + 2.0, row + 10.0, row + 11.0, row + 12.0]);
+            if y == height - 1 {
+                assert_eq!(offset + 8 + line_size, bytes.len());
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::vec3::Color;
+use std::cell::RefCell;
+
+/// Pixels of the image being rendered. Besides the samples taken through each pixel, integrators that
+/// trace light towards the camera splat it onto whichever pixel it lands on.
+pub struct Film {
+    width: usize,
+    height: usize,
+    pixels: RefCell<Vec<Color>>,
+    splats: RefCell<Vec<Color>>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Film {
+        Film {
+            width,
+            height,
+            pixels: RefCell::new(vec![Color::new(0.0, 0.0, 0.0); width * height]),
+            splats: RefCell::new(vec![Color::new(0.0, 0.0, 0.0); width * height]),
+        }
+    }
+
+    pub fn pixel_count(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// Adds a sample taken through pixel (`i`, `j`), with rows counted from the bottom.
+    pub fn add_sample(&self, i: usize, j: usize, color: Color) {
+        let pixel = &mut self.pixels.borrow_mut()[j * self.width + i];
+        *pixel = *pixel + color;
+    }
+
+    /// Adds light reaching the film at (`s`, `t`), the coordinates `Camera::get_ray` takes. Light off
+    /// the film is dropped.
+    pub fn splat(&self, s: f64, t: f64, color: Color) {
+        let i = (s * self.width as f64) as usize;
+        let j = (t * self.height as f64) as usize;
+        if s < 0.0 || t < 0.0 || i >= self.width || j >= self.height {
+            return
+        }
+        let pixel = &mut self.splats.borrow_mut()[j * self.width + i];
+        *pixel = *pixel + color;
+    }
+
+    /// Average of the samples and splats of each pixel, with rows counted from the bottom.
+    pub fn average(&self, samples_per_pixel: i32) -> Vec<Color> {
+        let scale = 1.0 / samples_per_pixel.max(1) as f64;
+        let (pixels, splats) = (self.pixels.borrow(), self.splats.borrow());
+        pixels.iter().zip(splats.iter()).map(|(&pixel, &splat)| (pixel + splat) * scale).collect()
+    }
+
+    /// Writes the image to stdout as a PPM. Splats are averaged over the samples per pixel too, as a
+    /// path of light is traced for each sample.
+    pub fn write(&self, samples_per_pixel: i32) {
+        println!("P3");
+        println!("{0} {1}", self.width, self.height);
+        println!("255");
+        let (pixels, splats) = (self.pixels.borrow(), self.splats.borrow());
+        for j in (0..self.height).rev() {
+            for i in 0..self.width {
+                crate::write_color(&(pixels[j * self.width + i] + splats[j * self.width + i]), samples_per_pixel);
+            }
+        }
+    }
+}
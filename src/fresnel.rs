@@ -0,0 +1,61 @@
+use crate::vec3::{Color, Vec3};
+
+/// Unpolarized reflectance of a conductor with complex index of refraction `eta` + i`k`, per color
+/// channel, for light arriving at `cos_theta` from the normal out of a vacuum.
+pub fn fresnel_conductor(cos_theta: f64, eta: &Color, k: &Color) -> Color {
+    let e = eta.e();
+    let k = k.e();
+    let f = |i: usize| fresnel_conductor_channel(cos_theta, e[i], k[i]);
+    Color::new(f(0), f(1), f(2))
+}
+
+fn fresnel_conductor_channel(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+}
+
+/// Unpolarized reflectance of a dielectric interface with relative index of refraction `eta` (inside
+/// over outside) for light arriving at `cos_theta` from the outward normal; negative cosines arrive
+/// from inside.
+pub fn fresnel_dielectric(cos_theta: f64, eta: f64) -> f64 {
+    let (cos_i, eta) = if cos_theta < 0.0 { (-cos_theta.max(-1.0), 1.0 / eta) } else { (cos_theta.min(1.0), eta) };
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+/// Schlick's approximation of the reflectance rising from `f0` at normal incidence to one at grazing
+/// angles.
+pub fn fresnel_schlick(cos_theta: f64, f0: &Color) -> Color {
+    let weight = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    *f0 + (Color::new(1.0, 1.0, 1.0) - *f0) * weight
+}
+
+/// Direction of `wi`, pointing away from the surface, after refracting through an interface with outward
+/// normal `n` and relative index of refraction `eta` (inside over outside). `None` on total internal
+/// reflection.
+pub fn refract(wi: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = n.dot(wi);
+    let (cos_i, eta, n) = if cos_i < 0.0 { (-cos_i, 1.0 / eta, -*n) } else { (cos_i, eta, *n) };
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-*wi / eta + n * (cos_i / eta - cos_t))
+}
@@ -0,0 +1,306 @@
+use crate::aabb::Aabb;
+use crate::pdf::Pdf;
+use crate::random_double;
+use crate::vec3::{Color, Point3, Vec3};
+use std::f64::consts::PI;
+
+/// Samples a spatial leaf takes before it's split, scaled by the square root of the iteration's length.
+const SPATIAL_THRESHOLD: f64 = 12000.0;
+/// Share of a leaf's energy above which a quadrant of directions gets split.
+const ENERGY_THRESHOLD: f64 = 0.01;
+const MAX_QUADTREE_DEPTH: u32 = 20;
+
+/// Maps a unit direction onto the unit square through its cosine with the z axis and its azimuth, which
+/// keeps areas, so a density over the square is one over the sphere times 4π.
+fn to_square(direction: &Vec3) -> (f64, f64) {
+    let cos_theta = direction.z().clamp(-1.0, 1.0);
+    let phi = direction.y().atan2(direction.x());
+    let phi = if phi < 0.0 { phi + 2.0 * PI } else { phi };
+    ((cos_theta + 1.0) / 2.0, phi / (2.0 * PI))
+}
+
+fn from_square(x: f64, y: f64) -> Vec3 {
+    let cos_theta = 2.0 * x - 1.0;
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * y;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// Quadrant of a node the point falls in, and the point in the quadrant's own coordinates.
+fn quadrant(x: f64, y: f64) -> (usize, f64, f64) {
+    let (right, top) = (x >= 0.5, y >= 0.5);
+    let index = right as usize + 2 * top as usize;
+    (index, 2.0 * x - right as u8 as f64, 2.0 * y - top as u8 as f64)
+}
+
+#[derive(Clone)]
+struct QuadNode {
+    /// Energy that arrived through each quadrant.
+    sums: [f64; 4],
+    /// Node each quadrant is split into, or 0 for quadrants that aren't, as the root is no one's child.
+    children: [usize; 4],
+}
+
+/// Incident light over directions, as a quadtree over the square directions are mapped to. Quadrants are
+/// split where a lot of light comes from, so that bright directions are told apart finely.
+#[derive(Clone)]
+struct DirectionTree {
+    nodes: Vec<QuadNode>,
+}
+
+impl DirectionTree {
+    fn new() -> DirectionTree {
+        DirectionTree {nodes: vec![QuadNode {sums: [0.0; 4], children: [0; 4]}]}
+    }
+
+    fn total(&self) -> f64 {
+        self.nodes[0].sums.iter().sum()
+    }
+
+    fn record(&mut self, direction: &Vec3, energy: f64) {
+        let (mut x, mut y) = to_square(direction);
+        let mut node = 0;
+        loop {
+            let (index, qx, qy) = quadrant(x, y);
+            self.nodes[node].sums[index] += energy;
+            match self.nodes[node].children[index] {
+                0 => return,
+                child => {
+                    node = child;
+                    x = qx;
+                    y = qy;
+                }
+            }
+        }
+    }
+
+    /// Density over the square the direction maps to.
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let (mut x, mut y) = to_square(direction);
+        let mut node = 0;
+        let mut pdf = 1.0;
+        loop {
+            let QuadNode {sums, children} = &self.nodes[node];
+            let total: f64 = sums.iter().sum();
+            let (index, qx, qy) = quadrant(x, y);
+            if total <= 0.0 {
+                return 0.0
+            }
+            pdf *= 4.0 * sums[index] / total;
+            match children[index] {
+                0 => return pdf,
+                child => {
+                    node = child;
+                    x = qx;
+                    y = qy;
+                }
+            }
+        }
+    }
+
+    fn sample(&self) -> Vec3 {
+        let (mut x, mut y, mut size) = (0.0, 0.0, 1.0);
+        let mut node = 0;
+        loop {
+            let QuadNode {sums, children} = &self.nodes[node];
+            let mut target = random_double(0.0, 1.0) * sums.iter().sum::<f64>();
+            let mut index = 0;
+            while index < 3 && (target >= sums[index] || sums[index] == 0.0) {
+                target -= sums[index];
+                index += 1;
+            }
+            size /= 2.0;
+            x += size * (index % 2) as f64;
+            y += size * (index / 2) as f64;
+            match children[index] {
+                0 => return from_square(x + size * random_double(0.0, 1.0), y + size * random_double(0.0, 1.0)),
+                child => node = child,
+            }
+        }
+    }
+
+    /// Empty tree split where this one's light is: every quadrant with more than a small share of the
+    /// total is split, down to a depth limit, using the light of the quadrants it's split into if this
+    /// tree had them, and an even share of it if not.
+    fn refined(&self) -> DirectionTree {
+        let total = self.total();
+        let mut tree = DirectionTree {nodes: Vec::new()};
+        if total <= 0.0 {
+            return DirectionTree::new()
+        }
+        tree.build(self, Some(0), self.nodes[0].sums, total, 1);
+        tree
+    }
+
+    fn build(&mut self, old: &DirectionTree, node: Option<usize>, sums: [f64; 4], total: f64, depth: u32) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(QuadNode {sums: [0.0; 4], children: [0; 4]});
+        for (quadrant, &sum) in sums.iter().enumerate() {
+            if sum / total <= ENERGY_THRESHOLD || depth >= MAX_QUADTREE_DEPTH {
+                continue
+            }
+            let child = node.map_or(0, |node| old.nodes[node].children[quadrant]);
+            let (child, child_sums) = match child {
+                0 => (None, [sum / 4.0; 4]),
+                child => (Some(child), old.nodes[child].sums),
+            };
+            let built = self.build(old, child, child_sums, total, depth + 1);
+            self.nodes[index].children[quadrant] = built;
+        }
+        index
+    }
+}
+
+struct SpatialNode {
+    /// Axis the node is split along, at its middle.
+    axis: usize,
+    /// Nodes either half is split into, or `None` for a leaf.
+    children: Option<[usize; 2]>,
+    /// Light learned in earlier iterations, which paths are guided by.
+    sampling: DirectionTree,
+    /// Light being learned in this iteration.
+    building: DirectionTree,
+    samples: usize,
+}
+
+/// Spatial-directional tree (Müller et al., "Practical Path Guiding"): a binary tree over space, split
+/// where many path vertices fall, with a quadtree of the light arriving from each direction in every leaf.
+/// Rendering goes in iterations of doubling length, each guided by what the ones before learned. At the
+/// end of one, busy leaves are split and each leaf's directions are split after where its light came from.
+pub struct Guide {
+    bounds: Aabb,
+    nodes: Vec<SpatialNode>,
+    /// Iterations done so far.
+    iteration: u32,
+    /// Passes left in the current iteration.
+    passes_left: u32,
+}
+
+/// Vertex of a guided path, for learning the light that arrived at it from the direction it continued in.
+pub struct GuideVertex {
+    pub p: Point3,
+    pub direction: Vec3,
+    /// Density the direction was picked with.
+    pub pdf: f64,
+    /// Light the path picked up before it continued from here, and light it then found on lights that are
+    /// sampled directly, which the guide leaves to light sampling.
+    pub radiance: Color,
+    /// Throughput of the path past this vertex.
+    pub throughput: Color,
+}
+
+impl Guide {
+    /// Guide for the scene within `bounds`, which is grown into a cube so splits stay even.
+    pub fn new(bounds: Aabb) -> Guide {
+        let (min, max) = (bounds.min(), bounds.max());
+        let size = (max - min).x().max((max - min).y()).max((max - min).z());
+        let center = (min + max) * 0.5;
+        let half = Vec3::new(size, size, size) * 0.5;
+        let root = SpatialNode {axis: 0, children: None, sampling: DirectionTree::new(), building: DirectionTree::new(), samples: 0};
+        Guide {bounds: Aabb::new(center - half, center + half), nodes: vec![root], iteration: 0, passes_left: 1}
+    }
+
+    /// Moves on to the next pass, ending the iteration after as many passes as it's long.
+    pub fn begin_pass(&mut self) {
+        if self.passes_left == 0 {
+            self.refine();
+            self.iteration += 1;
+            self.passes_left = 1 << self.iteration.min(30);
+        }
+        self.passes_left -= 1;
+    }
+
+    fn leaf(&self, p: &Point3) -> usize {
+        let (mut min, mut max) = (self.bounds.min(), self.bounds.max());
+        let mut node = 0;
+        while let Some(children) = self.nodes[node].children {
+            let axis = self.nodes[node].axis;
+            let middle = (min.e()[axis] + max.e()[axis]) / 2.0;
+            if p.e()[axis] < middle {
+                max = with_axis(&max, axis, middle);
+                node = children[0];
+            } else {
+                min = with_axis(&min, axis, middle);
+                node = children[1];
+            }
+        }
+        node
+    }
+
+    /// Distribution of the light learned around `p`, if any was.
+    pub fn pdf_at(&self, p: &Point3) -> Option<GuidePdf<'_>> {
+        let tree = &self.nodes[self.leaf(p)].sampling;
+        if tree.total() > 0.0 { Some(GuidePdf {tree}) } else { None }
+    }
+
+    /// Learns from a path that collected `radiance` in all, through its vertices.
+    pub fn record(&mut self, vertices: &[GuideVertex], radiance: &Color) {
+        for vertex in vertices {
+            // Light found after the vertex, divided by the throughput to it, is what arrived there
+            let found = *radiance - vertex.radiance;
+            let ratio = |a: f64, b: f64| if b > 0.0 { a / b } else { 0.0 };
+            let (f, t) = (found.e(), vertex.throughput.e());
+            let incident = Color::new(ratio(f[0], t[0]), ratio(f[1], t[1]), ratio(f[2], t[2])).luminance();
+            let leaf = self.leaf(&vertex.p);
+            let node = &mut self.nodes[leaf];
+            node.samples += 1;
+            if incident.is_finite() && incident > 0.0 && vertex.pdf > 0.0 {
+                node.building.record(&vertex.direction.unit(), incident / vertex.pdf);
+            }
+        }
+    }
+
+    fn refine(&mut self) {
+        let threshold = SPATIAL_THRESHOLD * ((1u64 << self.iteration.min(30)) as f64).sqrt();
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            if let Some(children) = self.nodes[node].children {
+                stack.extend(children);
+                continue
+            }
+            if self.nodes[node].samples as f64 > threshold {
+                // Either half is taken to have seen half the samples, and keeps being split while that's
+                // still too many
+                let axis = self.nodes[node].axis;
+                let first = self.nodes.len();
+                for _ in 0..2 {
+                    let parent = &self.nodes[node];
+                    let child = SpatialNode {axis: (axis + 1) % 3, children: None, sampling: DirectionTree::new(),
+                                             building: parent.building.clone(), samples: parent.samples / 2};
+                    self.nodes.push(child);
+                }
+                let parent = &mut self.nodes[node];
+                parent.children = Some([first, first + 1]);
+                parent.building = DirectionTree::new();
+                parent.sampling = DirectionTree::new();
+                stack.extend([first, first + 1]);
+                continue
+            }
+            let leaf = &mut self.nodes[node];
+            leaf.sampling = std::mem::replace(&mut leaf.building, DirectionTree::new());
+            leaf.building = leaf.sampling.refined();
+            leaf.samples = 0;
+        }
+    }
+}
+
+fn with_axis(p: &Point3, axis: usize, value: f64) -> Point3 {
+    let mut e = p.e();
+    e[axis] = value;
+    Point3::new(e[0], e[1], e[2])
+}
+
+/// Directions following the light learned around a point.
+pub struct GuidePdf<'a> {
+    tree: &'a DirectionTree,
+}
+
+impl Pdf for GuidePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.tree.pdf(&direction.unit()) / (4.0 * PI)
+    }
+
+    fn generate(&self) -> Option<Vec3> {
+        Some(self.tree.sample())
+    }
+}
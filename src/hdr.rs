@@ -0,0 +1,156 @@
+use crate::vec3::Color;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Loads a Radiance RGBE (.hdr) image, returning its width, height and pixels in row-major order
+/// starting from the top-left corner.
+pub fn read_hdr(path: &str) -> io::Result<(usize, usize, Vec<Color>)> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid("missing Radiance signature"))
+    }
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("unexpected end of header"))
+        }
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            break
+        }
+        if trimmed.starts_with("FORMAT=") && trimmed != "FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid("only RGBE pixels are supported"))
+        }
+    }
+
+    line.clear();
+    reader.read_line(&mut line)?;
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() != 4 || fields[0] != "-Y" || fields[2] != "+X" {
+        return Err(invalid("only -Y height +X width images are supported"))
+    }
+    let height: usize = fields[1].parse().map_err(|_| invalid("bad image height"))?;
+    let width: usize = fields[3].parse().map_err(|_| invalid("bad image width"))?;
+
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        read_scanline(&mut reader, &mut scanline)?;
+        pixels.extend(scanline.iter().map(rgbe_to_color));
+    }
+    Ok((width, height, pixels))
+}
+
+fn read_scanline(reader: &mut impl Read, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+    let mut header = [0u8; 4];
+    reader.read_exact(&mut header)?;
+
+    let run_length_encoded = (8..0x8000).contains(&width) && header[0] == 2 && header[1] == 2 && header[2] & 0x80 == 0;
+    if !run_length_encoded {
+        scanline[0] = header;
+        for pixel in scanline.iter_mut().skip(1) {
+            reader.read_exact(pixel)?;
+        }
+        return Ok(())
+    }
+    if ((header[2] as usize) << 8 | header[3] as usize) != width {
+        return Err(invalid("scanline width mismatch"))
+    }
+
+    // Each channel is stored separately as a mix of runs and literal spans
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            reader.read_exact(&mut count)?;
+            let (run, count) = if count[0] > 128 { (true, count[0] as usize - 128) } else { (false, count[0] as usize) };
+            if count == 0 || x + count > width {
+                return Err(invalid("bad scanline run"))
+            }
+            if run {
+                let mut value = [0u8; 1];
+                reader.read_exact(&mut value)?;
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[channel] = value[0];
+                }
+            } else {
+                let mut values = vec![0u8; count];
+                reader.read_exact(&mut values)?;
+                for (pixel, value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
+            }
+            x += count;
+        }
+    }
+    Ok(())
+}
+
+fn rgbe_to_color(rgbe: &[u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::new(0.0, 0.0, 0.0)
+    }
+    let f = 2.0f64.powi(rgbe[3] as i32 - (128 + 8));
+    Color::new(rgbe[0] as f64 * f, rgbe[1] as f64 * f, rgbe[2] as f64 * f)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_run_length_encoded_scanlines() {
+        // Eight pixels: red as one run, green as literals, blue as a literal span and a run, and the
+        // exponent as two runs
+        let bytes: &[u8] = &[
+            2, 2, 0, 8,
+            136, 10,
+            8, 1, 2, 3, 4, 5, 6, 7, 8,
+            3, 9, 8, 7, 133, 0,
+            132, 128, 132, 129,
+        ];
+        let mut reader = bytes;
+        let mut scanline = vec![[0u8; 4]; 8];
+        read_scanline(&mut reader, &mut scanline).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(scanline[0], [10, 1, 9, 128]);
+        assert_eq!(scanline[2], [10, 3, 7, 128]);
+        assert_eq!(scanline[3], [10, 4, 0, 128]);
+        assert_eq!(scanline[7], [10, 8, 0, 129]);
+    }
+
+    #[test]
+    fn reads_flat_scanlines() {
+        let bytes: &[u8] = &[1, 2, 3, 128, 4, 5, 6, 129, 7, 8, 9, 130];
+        let mut reader = bytes;
+        let mut scanline = vec![[0u8; 4]; 3];
+        read_scanline(&mut reader, &mut scanline).unwrap();
+        assert_eq!(scanline, vec![[1, 2, 3, 128], [4, 5, 6, 129], [7, 8, 9, 130]]);
+    }
+
+    #[test]
+    fn rejects_runs_past_the_end_of_the_scanline() {
+        let bytes: &[u8] = &[2, 2, 0, 8, 137, 10];
+        let mut scanline = vec![[0u8; 4]; 8];
+        assert_eq!(read_scanline(&mut { bytes }, &mut scanline).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let bytes: &[u8] = &[2, 2, 0, 9];
+        assert!(read_scanline(&mut { bytes }, &mut scanline).is_err());
+    }
+
+    #[test]
+    fn converts_shared_exponents() {
+        let color = rgbe_to_color(&[128, 64, 0, 129]);
+        assert_eq!((color.x(), color.y(), color.z()), (1.0, 0.5, 0.0));
+        let black = rgbe_to_color(&[200, 200, 200, 0]);
+        assert_eq!((black.x(), black.y(), black.z()), (0.0, 0.0, 0.0));
+    }
+}
@@ -1,7 +1,8 @@
-#![feature(iterator_fold_self)]
 use crate::ray::{Hittable, Ray, HitRecord};
 use std::rc::Rc;
 use crate::aabb::Aabb;
+use crate::vec3::{Point3, Vec3};
+use crate::random_double;
 
 pub struct HittableList {
     pub objects: Vec<Rc<dyn Hittable>>
@@ -43,15 +44,26 @@ impl Hittable for HittableList{
         }
         let mut result = None;
         for object in &self.objects {
-            let tmp_box = object.bounding_box(time0, time1);
-            if tmp_box.is_none() {
-                return None
-            }
+            let tmp_box = object.bounding_box(time0, time1)?;
             match result {
-                None => result = tmp_box,
-                Some(r) => result = tmp_box.map(|tmp| Aabb::surrounding_box(&tmp, &r)),
+                None => result = Some(tmp_box),
+                Some(r) => result = Some(Aabb::surrounding_box(&tmp_box, &r)),
             }
         }
         result
     }
+
+    /// Picks one of the objects with equal odds.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0
+        }
+        let sum: f64 = self.objects.iter().map(|object| object.pdf_value(origin, direction)).sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let index = ((random_double(0.0, 1.0) * self.objects.len() as f64) as usize).min(self.objects.len() - 1);
+        self.objects[index].random(origin)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,39 @@
+use crate::aov::LightPaths;
+use crate::background::Background;
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::light_bvh::LightBvh;
+use crate::ray::{Hittable, Ray};
+use crate::vec3::Color;
+
+/// Everything integrators look at: the objects, the lights among them and the background, and the camera
+/// and film for those tracing light towards the camera.
+#[derive(Clone, Copy)]
+pub struct Scene<'a> {
+    pub world: &'a dyn Hittable,
+    pub lights: &'a LightBvh,
+    pub background: &'a dyn Background,
+    pub camera: &'a Camera,
+    pub film: &'a Film,
+}
+
+/// Turns camera rays into pixel values.
+pub trait Integrator {
+    /// Light arriving at the camera along `ray`, or the false color a debug integrator shows for it.
+    fn ray_color(&self, ray: Ray, scene: &Scene) -> Color;
+
+    /// Light arriving at the camera along `ray`, split by the way it came, for integrators that can tell.
+    fn light_paths(&self, _ray: Ray, _scene: &Scene) -> Option<LightPaths> {
+        None
+    }
+
+    /// Called before each pass over the image, in which every pixel takes one sample, for integrators
+    /// that prepare something per pass.
+    fn begin_pass(&self, _scene: &Scene) {}
+
+    /// Whether the integrator follows the wavelengths of rays in spectral mode, so that its result is
+    /// spectral radiance rather than a color.
+    fn is_spectral(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,253 @@
+use crate::aabb::Aabb;
+use crate::hittable_list::HittableList;
+use crate::light_bvh::LightBounds;
+use crate::onb::Onb;
+use crate::random_double;
+use crate::ray::{HitRecord, Hittable, Ray};
+use crate::vec3::{Color, Point3, Vec3};
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+// Lights are specified in watts and meters, which makes radiance the renderer's unit of W/(m² sr).
+
+/// Light arriving at a point from a light that can only be reached by sampling it.
+pub struct LightSample {
+    /// Unit direction from the point towards the light.
+    pub direction: Vec3,
+    /// Distance to the light, infinite for directional lights.
+    pub distance: f64,
+    /// Irradiance the light delivers at the point, on a surface facing it.
+    pub irradiance: Color,
+}
+
+/// Start of a path of light leaving a light.
+pub struct Emission {
+    pub origin: Point3,
+    /// Unit direction the light leaves along.
+    pub direction: Vec3,
+    /// Radiant intensity of the light in that direction.
+    pub intensity: Color,
+    /// Density per unit solid angle of having picked the direction.
+    pub pdf: f64,
+}
+
+/// Light with no extent that no ray can hit, so it's only ever found through next-event estimation.
+pub trait Light {
+    /// Light reaching `p`, or `None` when the light doesn't shine there.
+    fn sample(&self, p: &Point3) -> Option<LightSample>;
+
+    /// Bounds of the light for picking it by its contribution, or `None` for lights that reach
+    /// everywhere and are always sampled.
+    fn bounds(&self) -> Option<LightBounds>;
+
+    /// Picks a direction for light to leave along, for tracing paths from the light. Lights that
+    /// can't start paths return `None`.
+    fn emit(&self) -> Option<Emission> {
+        None
+    }
+
+    /// Density per unit solid angle with which `emit` picks `direction`.
+    fn emission_pdf(&self, _direction: &Vec3) -> f64 {
+        0.0
+    }
+}
+
+/// Bulb radiating `power` watts of light evenly in all directions.
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, color: Color, power: f64) -> PointLight {
+        PointLight {position, intensity: color * (power / (4.0 * PI))}
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: &Point3) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let distance = to_light.length();
+        if distance == 0.0 {
+            return None
+        }
+        Some(LightSample {direction: to_light / distance, distance, irradiance: self.intensity / (distance * distance)})
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds::omnidirectional(Aabb::new(self.position, self.position), 4.0 * PI * self.intensity.luminance()))
+    }
+
+    fn emit(&self) -> Option<Emission> {
+        Some(Emission {origin: self.position, direction: Vec3::random_unit_vector(), intensity: self.intensity, pdf: 1.0 / (4.0 * PI)})
+    }
+
+    fn emission_pdf(&self, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+/// Point light shining `power` watts into a cone towards `target`. It's at full intensity within
+/// `falloff_start` degrees of the axis and fades out smoothly until `cone_angle` degrees.
+pub struct SpotLight {
+    position: Point3,
+    axis: Vec3,
+    intensity: Color,
+    cos_falloff_start: f64,
+    cos_cone: f64,
+}
+
+impl SpotLight {
+    pub fn new(position: Point3, target: Point3, color: Color, power: f64, cone_angle: f64, falloff_start: f64) -> SpotLight {
+        let cos_cone = cone_angle.to_radians().cos();
+        let cos_falloff_start = falloff_start.min(cone_angle).to_radians().cos();
+        // The falloff region counts for about half, so the cone carries all the power
+        let solid_angle = 2.0 * PI * (1.0 - 0.5 * (cos_falloff_start + cos_cone));
+        SpotLight {
+            position,
+            axis: (target - position).unit(),
+            intensity: color * (power / solid_angle),
+            cos_falloff_start,
+            cos_cone,
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: &Point3) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let distance = to_light.length();
+        if distance == 0.0 {
+            return None
+        }
+        let direction = to_light / distance;
+        let cos_theta = self.axis.dot(&-direction);
+        if cos_theta <= self.cos_cone {
+            return None
+        }
+        let falloff = smoothstep(self.cos_cone, self.cos_falloff_start, cos_theta);
+        Some(LightSample {direction, distance, irradiance: self.intensity * (falloff / (distance * distance))})
+    }
+
+    // The full intensity is bounded by the inner cone, with the falloff on top as if it were emission
+    // past the normals of a surface
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds {
+            bounds: Aabb::new(self.position, self.position),
+            power: 4.0 * PI * self.intensity.luminance(),
+            axis: self.axis,
+            cos_theta_o: self.cos_falloff_start,
+            cos_theta_e: (self.cos_cone.acos() - self.cos_falloff_start.acos()).cos(),
+        })
+    }
+
+    /// Directions are picked uniformly within the cone.
+    fn emit(&self) -> Option<Emission> {
+        let z = 1.0 - random_double(0.0, 1.0) * (1.0 - self.cos_cone);
+        let phi = 2.0 * PI * random_double(0.0, 1.0);
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let direction = Onb::from_w(&self.axis).local(&Vec3::new(phi.cos() * r, phi.sin() * r, z));
+        let falloff = smoothstep(self.cos_cone, self.cos_falloff_start, z);
+        Some(Emission {origin: self.position, direction, intensity: self.intensity * falloff, pdf: self.emission_pdf(&direction)})
+    }
+
+    fn emission_pdf(&self, direction: &Vec3) -> f64 {
+        if self.axis.dot(&direction.unit()) <= self.cos_cone {
+            return 0.0
+        }
+        1.0 / (2.0 * PI * (1.0 - self.cos_cone))
+    }
+}
+
+fn smoothstep(a: f64, b: f64, x: f64) -> f64 {
+    if a == b {
+        return if x < a { 0.0 } else { 1.0 }
+    }
+    let t = ((x - a) / (b - a)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Light from very far away, like the sun or the moon, arriving along `direction` with an irradiance
+/// of `irradiance` watts per square meter on a surface facing it.
+pub struct DirectionalLight {
+    to_light: Vec3,
+    irradiance: Color,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, color: Color, irradiance: f64) -> DirectionalLight {
+        DirectionalLight {to_light: -direction.unit(), irradiance: color * irradiance}
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: &Point3) -> Option<LightSample> {
+        Some(LightSample {direction: self.to_light, distance: f64::INFINITY, irradiance: self.irradiance})
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+}
+
+/// Emissive shape among a scene's lights, whose hits carry its number so the light that was hit can be
+/// told apart from others around the same point.
+struct LightShape {
+    shape: Rc<dyn Hittable>,
+    index: usize,
+}
+
+impl Hittable for LightShape {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.shape.hit(ray, t_min, t_max).map(|rec| HitRecord {light: Some(self.index), ..rec})
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.shape.bounding_box(time0, time1)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.shape.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        self.shape.random(origin)
+    }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        self.shape.light_bounds()
+    }
+
+    fn sample_surface(&self) -> Option<(HitRecord, f64)> {
+        self.shape.sample_surface().map(|(rec, pdf)| (HitRecord {light: Some(self.index), ..rec}, pdf))
+    }
+
+    fn surface_pdf(&self, p: &Point3) -> f64 {
+        self.shape.surface_pdf(p)
+    }
+}
+
+/// Everything that lights a scene directly: emissive shapes, which also have to be in the world to be
+/// hit, and lights that can only be sampled.
+pub struct Lights {
+    pub shapes: HittableList,
+    pub delta: Vec<Box<dyn Light>>,
+}
+
+impl Lights {
+    pub fn new() -> Lights {
+        Lights {shapes: HittableList::new(), delta: Vec::new()}
+    }
+
+    /// Adds an emissive shape, and returns it as the world has to hold it for hits on it to be
+    /// recognized as hits on this light.
+    pub fn add_shape(&mut self, shape: Rc<dyn Hittable>) -> Rc<dyn Hittable> {
+        let shape: Rc<dyn Hittable> = Rc::new(LightShape {shape, index: self.shapes.objects.len()});
+        self.shapes.add(shape.clone());
+        shape
+    }
+
+    pub fn add(&mut self, light: Box<dyn Light>) {
+        self.delta.push(light)
+    }
+}
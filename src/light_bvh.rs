@@ -0,0 +1,312 @@
+use crate::aabb::Aabb;
+use crate::light::{Light, Lights};
+use crate::pdf::{HittablePdf, Pdf};
+use crate::random_double;
+use crate::ray::{HitRecord, Hittable};
+use crate::vec3::{Point3, Vec3};
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+/// Where a light is, which way it shines and how much, for guessing its contribution at a point
+/// without looking at the light itself.
+#[derive(Clone, Copy)]
+pub struct LightBounds {
+    pub bounds: Aabb,
+    /// Power of the light, in watts, or anything proportional to it.
+    pub power: f64,
+    /// Axis of the cone holding the normals of the emitting surface.
+    pub axis: Vec3,
+    /// Cosine of the half angle of that cone.
+    pub cos_theta_o: f64,
+    /// Cosine of the angle past the normals that light still leaves the surface at, which is 90° for
+    /// diffuse emitters.
+    pub cos_theta_e: f64,
+}
+
+impl LightBounds {
+    /// Bounds of a light emitting from its whole surface, e.g. a sphere, or from a point in all directions.
+    pub fn omnidirectional(bounds: Aabb, power: f64) -> LightBounds {
+        LightBounds {bounds, power, axis: Vec3::new(0.0, 0.0, 1.0), cos_theta_o: -1.0, cos_theta_e: 0.0}
+    }
+
+    fn centroid(&self) -> Point3 {
+        (self.bounds.min() + self.bounds.max()) * 0.5
+    }
+
+    fn union(&self, other: &LightBounds) -> LightBounds {
+        let (axis, cos_theta_o) = cone_union(self.axis, self.cos_theta_o, other.axis, other.cos_theta_o);
+        LightBounds {
+            bounds: Aabb::surrounding_box(&self.bounds, &other.bounds),
+            power: self.power + other.power,
+            axis,
+            cos_theta_o,
+            cos_theta_e: self.cos_theta_e.min(other.cos_theta_e),
+        }
+    }
+
+    /// Upper bound of the light reaching `p` from anything inside these bounds, up to the power. The
+    /// receiving surface's `normal` also counts where there is one.
+    fn importance(&self, p: &Point3, normal: Option<&Vec3>) -> f64 {
+        if self.power <= 0.0 {
+            return 0.0
+        }
+        let centroid = self.centroid();
+        let half_diagonal = (self.bounds.max() - self.bounds.min()).length() / 2.0;
+        // Clamped so points close to or inside the bounds don't blow up
+        let distance_squared = (*p - centroid).length_squared().max(half_diagonal);
+
+        // Angle the bounds subtend from the point, all of them when it's inside
+        let theta_b = if (*p - centroid).length_squared() <= half_diagonal * half_diagonal {
+            PI
+        } else {
+            (half_diagonal * half_diagonal / (*p - centroid).length_squared()).sqrt().asin()
+        };
+
+        // Smallest angle between a normal of the light and the direction to the point
+        let to_point = (*p - centroid).unit();
+        let theta_w = self.axis.dot(&to_point).clamp(-1.0, 1.0).acos();
+        let theta = (theta_w - self.cos_theta_o.clamp(-1.0, 1.0).acos() - theta_b).max(0.0);
+        if theta >= self.cos_theta_e.clamp(-1.0, 1.0).acos() {
+            return 0.0
+        }
+        let mut importance = self.power * theta.cos() / distance_squared;
+
+        if let Some(normal) = normal {
+            let theta_i = normal.dot(&to_point).abs().clamp(0.0, 1.0).acos();
+            importance *= (theta_i - theta_b).max(0.0).cos();
+        }
+        importance.max(0.0)
+    }
+}
+
+/// Smallest cone around the cones with axes `a` and `b` and cosines of half angles `cos_a` and `cos_b`.
+fn cone_union(a: Vec3, cos_a: f64, b: Vec3, cos_b: f64) -> (Vec3, f64) {
+    let theta_a = cos_a.clamp(-1.0, 1.0).acos();
+    let theta_b = cos_b.clamp(-1.0, 1.0).acos();
+    let theta_d = a.dot(&b).clamp(-1.0, 1.0).acos();
+    if (theta_d + theta_b).min(PI) <= theta_a {
+        return (a, cos_a)
+    }
+    if (theta_d + theta_a).min(PI) <= theta_b {
+        return (b, cos_b)
+    }
+    let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+    let rotation_axis = a.cross(&b);
+    if theta_o >= PI || rotation_axis.length_squared() == 0.0 {
+        return (a, -1.0)
+    }
+    // Turn `a` towards `b` so the new cone just touches the far sides of both
+    let theta_r = theta_o - theta_a;
+    let k = rotation_axis.unit();
+    (a * theta_r.cos() + k.cross(&a) * theta_r.sin(), theta_o.cos())
+}
+
+/// Identifies an emissive shape in a `LightBvh`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct LightId(usize);
+
+/// Light picked by `LightBvh::sample`.
+pub enum Sampled<'a> {
+    Shape(LightId, &'a dyn Hittable),
+    Delta(&'a dyn Light),
+}
+
+#[derive(Clone, Copy)]
+enum Entry {
+    Shape(usize),
+    Delta(usize),
+}
+
+enum Node {
+    Leaf {bounds: LightBounds, entry: Entry},
+    /// The first child follows the node, the second one is at `second`.
+    Interior {bounds: LightBounds, second: usize},
+}
+
+impl Node {
+    fn bounds(&self) -> &LightBounds {
+        match self {
+            Node::Leaf {bounds, ..} | Node::Interior {bounds, ..} => bounds,
+        }
+    }
+}
+
+/// Picks lights with odds following their estimated contribution at the shading point, so scenes with
+/// thousands of lights only pay for the few that matter there.
+///
+/// Each node of the tree bounds the position, emission directions and power of the lights below it.
+/// Going down from the root, a child is picked with odds following its importance. Lights that have no
+/// bounds, like directional ones, aren't in the tree and are always sampled. Emissive shapes without
+/// bounds are never sampled, only found by rays, which is still unbiased.
+pub struct LightBvh {
+    shapes: Vec<Rc<dyn Hittable>>,
+    delta: Vec<Box<dyn Light>>,
+    nodes: Vec<Node>,
+    unbounded: Vec<usize>,
+}
+
+impl LightBvh {
+    pub fn new(lights: Lights) -> LightBvh {
+        let mut leaves = Vec::new();
+        for (i, shape) in lights.shapes.objects.iter().enumerate() {
+            if let Some(bounds) = shape.light_bounds() {
+                leaves.push((bounds, Entry::Shape(i)));
+            }
+        }
+        let mut unbounded = Vec::new();
+        for (i, light) in lights.delta.iter().enumerate() {
+            match light.bounds() {
+                Some(bounds) => leaves.push((bounds, Entry::Delta(i))),
+                None => unbounded.push(i),
+            }
+        }
+        let mut bvh = LightBvh {shapes: lights.shapes.objects, delta: lights.delta, nodes: Vec::new(), unbounded};
+        if !leaves.is_empty() {
+            bvh.build(&mut leaves);
+        }
+        bvh
+    }
+
+    /// Splits at the median centroid along the widest axis, which keeps the tree balanced.
+    fn build(&mut self, leaves: &mut [(LightBounds, Entry)]) -> LightBounds {
+        if leaves.len() == 1 {
+            self.nodes.push(Node::Leaf {bounds: leaves[0].0, entry: leaves[0].1});
+            return leaves[0].0
+        }
+        let centroids = leaves.iter().map(|(bounds, _)| bounds.centroid());
+        let (min, max) = centroids.fold((Vec3::new(f64::MAX, f64::MAX, f64::MAX), Vec3::new(f64::MIN, f64::MIN, f64::MIN)), |(min, max), c| {
+            (Vec3::new(min.x().min(c.x()), min.y().min(c.y()), min.z().min(c.z())),
+             Vec3::new(max.x().max(c.x()), max.y().max(c.y()), max.z().max(c.z())))
+        });
+        let extent = (max - min).e();
+        let axis = (0..3).fold(0, |best, a| if extent[a] > extent[best] { a } else { best });
+        leaves.sort_by(|a, b| a.0.centroid().e()[axis].total_cmp(&b.0.centroid().e()[axis]));
+
+        let index = self.nodes.len();
+        self.nodes.push(Node::Leaf {bounds: leaves[0].0, entry: leaves[0].1});
+        let (left, right) = leaves.split_at_mut(leaves.len() / 2);
+        let left = self.build(left);
+        let second = self.nodes.len();
+        let right = self.build(right);
+        let bounds = left.union(&right);
+        self.nodes[index] = Node::Interior {bounds, second};
+        bounds
+    }
+
+    /// Lights no tree can bound, which every shading point samples.
+    pub fn unbounded(&self) -> impl Iterator<Item = &dyn Light> {
+        self.unbounded.iter().map(move |&i| self.delta[i].as_ref())
+    }
+
+    /// Picks one of the lights in the tree for `p`, along with the probability of having picked it.
+    pub fn sample(&self, p: &Point3, normal: Option<&Vec3>) -> Option<(Sampled<'_>, f64)> {
+        self.pick(|bounds| bounds.importance(p, normal))
+    }
+
+    /// Light in the tree that was hit at `hit`, and the probability of picking it at `p`.
+    pub fn find(&self, p: &Point3, normal: Option<&Vec3>, hit: &HitRecord) -> Option<(LightId, f64)> {
+        self.locate(hit, |bounds| bounds.importance(p, normal))
+    }
+
+    /// Total power of the lights in the tree.
+    pub fn power(&self) -> f64 {
+        self.nodes.first().map_or(0.0, |node| node.bounds().power)
+    }
+
+    /// Picks one of the lights in the tree with odds following its power, for starting paths of light
+    /// from, along with the probability of having picked it.
+    pub fn sample_emitter(&self) -> Option<(Sampled<'_>, f64)> {
+        self.pick(|bounds| bounds.power)
+    }
+
+    /// Shape in the tree that was hit at `hit`, and the probability of `sample_emitter` picking it.
+    pub fn find_emitter(&self, hit: &HitRecord) -> Option<(&dyn Hittable, f64)> {
+        let (LightId(i), pmf) = self.locate(hit, |bounds| bounds.power)?;
+        Some((self.shapes[i].as_ref(), pmf))
+    }
+
+    /// Goes down from the root picking children with odds following `importance`.
+    fn pick(&self, importance: impl Fn(&LightBounds) -> f64) -> Option<(Sampled<'_>, f64)> {
+        let mut index = 0;
+        let mut pmf = 1.0;
+        loop {
+            match self.nodes.get(index)? {
+                Node::Leaf {bounds, entry} => {
+                    if importance(bounds) <= 0.0 {
+                        return None
+                    }
+                    let light = match *entry {
+                        Entry::Shape(i) => Sampled::Shape(LightId(i), self.shapes[i].as_ref()),
+                        Entry::Delta(i) => Sampled::Delta(self.delta[i].as_ref()),
+                    };
+                    return Some((light, pmf))
+                },
+                Node::Interior {second, ..} => {
+                    let first = importance(self.nodes[index + 1].bounds());
+                    let other = importance(self.nodes[*second].bounds());
+                    if first + other <= 0.0 {
+                        return None
+                    }
+                    let odds = first / (first + other);
+                    if random_double(0.0, 1.0) < odds {
+                        index += 1;
+                        pmf *= odds;
+                    } else {
+                        index = *second;
+                        pmf *= 1.0 - odds;
+                    }
+                },
+            }
+        }
+    }
+
+    /// Shape that was hit at `hit`, and the probability of `pick` with the same `importance` ending up
+    /// with it.
+    fn locate(&self, hit: &HitRecord, importance: impl Fn(&LightBounds) -> f64) -> Option<(LightId, f64)> {
+        let light = hit.light?;
+        // Only the nodes around the point can hold the light, which is usually a single branch
+        let mut stack = vec![(0, 1.0)];
+        while let Some((index, pmf)) = stack.pop() {
+            match self.nodes.get(index)? {
+                Node::Leaf {bounds, entry: Entry::Shape(i)} => {
+                    if *i == light && importance(bounds) > 0.0 {
+                        return Some((LightId(*i), pmf))
+                    }
+                },
+                Node::Leaf {..} => {},
+                Node::Interior {second, ..} => {
+                    let first = importance(self.nodes[index + 1].bounds());
+                    let other = importance(self.nodes[*second].bounds());
+                    if first + other <= 0.0 {
+                        continue
+                    }
+                    if other > 0.0 && contains(&self.nodes[*second].bounds().bounds, &hit.p) {
+                        stack.push((*second, pmf * other / (first + other)));
+                    }
+                    if first > 0.0 && contains(&self.nodes[index + 1].bounds().bounds, &hit.p) {
+                        stack.push((index + 1, pmf * first / (first + other)));
+                    }
+                },
+            }
+        }
+        None
+    }
+
+    /// Density per unit solid angle with which picking a light for `p` and then a direction towards it
+    /// with a `HittablePdf` ends up with `direction`, which reaches a light at `hit`.
+    pub fn pdf_value(&self, p: &Point3, normal: Option<&Vec3>, direction: &Vec3, hit: &HitRecord) -> f64 {
+        match self.find(p, normal, hit) {
+            Some((LightId(i), pmf)) => pmf * HittablePdf::new(self.shapes[i].as_ref(), *p).value(direction),
+            None => 0.0,
+        }
+    }
+}
+
+/// Whether `p` is inside `bounds`, give or take rounding errors.
+fn contains(bounds: &Aabb, p: &Point3) -> bool {
+    let (min, max, p) = (bounds.min().e(), bounds.max().e(), p.e());
+    (0..3).all(|a| {
+        let tolerance = 1e-9 * (1.0 + p[a].abs());
+        min[a] - tolerance <= p[a] && p[a] <= max[a] + tolerance
+    })
+}
@@ -7,25 +7,98 @@ mod material;
 mod aabb;
 mod bvh;
 mod texture;
+mod normal_map;
+mod distribution;
+mod hdr;
+mod background;
+mod options;
+mod sky;
+mod onb;
+mod microfacet;
+mod fresnel;
+mod spectrum;
+mod conductor;
+mod principled;
+mod medium;
+mod rough_dielectric;
+mod thin_film;
+mod coated;
+mod subsurface;
+mod quad;
+mod alpha_mask;
+mod oren_nayar;
+mod sheen;
+mod pdf;
+mod light;
+mod light_bvh;
+mod integrator;
+mod path_tracer;
+mod whitted;
+mod ambient_occlusion;
+mod debug;
+mod film;
+mod bdpt;
+mod sppm;
+mod sampler;
+mod mlt;
+mod guiding;
+mod mesh;
+mod bake;
+mod exr;
+mod aov;
 
 use crate::vec3::{Vec3, Color, Point3};
-use crate::ray::{Ray, Hittable};
+use crate::ray::Hittable;
 use crate::hittable_list::HittableList;
 use std::rc::Rc;
 use crate::sphere::{Sphere, MovingSphere};
 use crate::camera::Camera;
+use crate::film::Film;
 use rand::Rng;
-use crate::material::{Lambertian, Metal, Dielectric};
+use crate::material::{Lambertian, Material, Metal, Dielectric, DiffuseLight};
+use crate::light::{DirectionalLight, Lights, PointLight, SpotLight};
+use crate::light_bvh::LightBvh;
+use crate::bvh::BvhNode;
+use crate::integrator::{Integrator, Scene};
+use crate::path_tracer::PathTracer;
+use crate::bdpt::Bdpt;
+use crate::sppm::Sppm;
+use crate::mlt::Mlt;
+use crate::whitted::Whitted;
+use crate::ambient_occlusion::AmbientOcclusion;
+use crate::debug::{Barycentrics, BvhVisits, Depth, MaterialId, Normals, Uv};
 use crate::texture::{CheckerTexture, SolidColor, Texture};
+use crate::normal_map::{BumpMap, NormalMap};
+use crate::background::{Background, EnvironmentMap, Gradient};
+use crate::options::Options;
+use crate::sky::{Sky, solar_position};
+use crate::conductor::Conductor;
+use crate::principled::{Principled, PrincipledParameters};
+use crate::rough_dielectric::RoughDielectric;
+use crate::spectrum::Wavelengths;
+use crate::thin_film::ThinFilm;
+use crate::coated::Coated;
+use crate::subsurface::Subsurface;
+use crate::quad::Quad;
+use crate::alpha_mask::AlphaMask;
+use crate::oren_nayar::OrenNayar;
+use crate::sheen::Sheen;
+use crate::mesh::Mesh;
+use crate::aov::{Aovs, Tagged};
+use crate::bake::{Baker, BakeMode};
+use std::process;
 
 fn main() {
+    let options = Options::from_args().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1)
+    });
 
     // Image
     let aspect_ratio = 16.0 / 9.0;
     let image_width: i32 = 400;
     let image_height: i32 = (image_width as f64 / aspect_ratio) as i32;
     let samples_per_pixel: i32 = 100;
-    let max_depth = 50;
 
 
     // World
@@ -43,34 +116,197 @@ fn main() {
     // world.add(Rc::new(Sphere{center: Point3::new(-1.0, 0.0, -1.0), radius: -0.45, material: material_left.clone()}));
     // world.add(Rc::new(Sphere{center: Point3::new(1.0, 0.0, -1.0), radius: 0.5, material: material_right.clone()}));
 
-    let (world, lookfrom, lookat, vfov, aperture) = match 1 {
-        0 => {
+    let measured_metal = options.metal_ior.as_ref().map(|path| -> Rc<dyn Material> {
+        let roughness = Box::new(SolidColor::new(Color::new(0.2, 0.2, 0.2)));
+        Rc::new(Conductor::from_ior_file(path, roughness).unwrap_or_else(|err| {
+            eprintln!("Could not load index of refraction {}: {}", path, err);
+            process::exit(1)
+        }))
+    });
+    let (mut world, lights, lookfrom, lookat, vfov, aperture) = match options.scene.as_str() {
+        "random" => {
             let world = random_scene();
+            let lights = Lights::new();
             let lookfrom = Point3::new(13.0,2.0, 3.0);
             let lookat = Point3::new(0.0, 0.0, 0.0);
             let aperture = 0.1;
             let vfov = 20.0;
-            (world, lookfrom, lookat, vfov, aperture)
+            (world, lights, lookfrom, lookat, vfov, aperture)
         },
-        1 => {
+        "two-spheres" => {
             let world = two_spheres();
+            let lights = Lights::new();
             let lookfrom = Point3::new(13.0,2.0, 3.0);
             let lookat = Point3::new(0.0, 0.0, 0.0);
             let aperture = 0.0;
             let vfov = 20.0;
-            (world, lookfrom, lookat, vfov, aperture)
+            (world, lights, lookfrom, lookat, vfov, aperture)
         }
-        _ => {
-            let world = random_scene();
+        "bumpy-spheres" => {
+            let world = bumpy_spheres();
+            let lights = Lights::new();
             let lookfrom = Point3::new(13.0,2.0, 3.0);
             let lookat = Point3::new(0.0, 0.0, 0.0);
-            let aperture = 0.1;
+            let aperture = 0.0;
             let vfov = 20.0;
-            (world, lookfrom, lookat, vfov, aperture)
+            (world, lights, lookfrom, lookat, vfov, aperture)
+        }
+        "metals" => {
+            let world = metals(measured_metal);
+            let lights = Lights::new();
+            let lookfrom = Point3::new(0.0, 3.0, 12.0);
+            let lookat = Point3::new(0.0, 0.8, 0.0);
+            let aperture = 0.0;
+            let vfov = 30.0;
+            (world, lights, lookfrom, lookat, vfov, aperture)
+        }
+        "principled" => {
+            let world = principled_spheres();
+            let lights = Lights::new();
+            let lookfrom = Point3::new(0.0, 3.0, 12.0);
+            let lookat = Point3::new(0.0, 0.8, 0.0);
+            let aperture = 0.0;
+            let vfov = 30.0;
+            (world, lights, lookfrom, lookat, vfov, aperture)
+        }
+        "glasses" => {
+            let world = glasses();
+            let lights = Lights::new();
+            let lookfrom = Point3::new(0.0, 3.0, 12.0);
+            let lookat = Point3::new(0.0, 0.8, 0.0);
+            let aperture = 0.0;
+            let vfov = 30.0;
+            (world, lights, lookfrom, lookat, vfov, aperture)
+        }
+        "dispersion" => {
+            let world = dispersion();
+            let lights = Lights::new();
+            let lookfrom = Point3::new(0.0, 3.0, 12.0);
+            let lookat = Point3::new(0.0, 0.8, 0.0);
+            let aperture = 0.0;
+            let vfov = 30.0;
+            (world, lights, lookfrom, lookat, vfov, aperture)
+        }
+        "iridescence" => {
+            let world = iridescence();
+            let lights = Lights::new();
+            let lookfrom = Point3::new(0.0, 3.0, 12.0);
+            let lookat = Point3::new(0.0, 0.8, 0.0);
+            let aperture = 0.0;
+            let vfov = 30.0;
+            (world, lights, lookfrom, lookat, vfov, aperture)
+        }
+        "coatings" => {
+            let world = coatings();
+            let lights = Lights::new();
+            let lookfrom = Point3::new(0.0, 3.0, 12.0);
+            let lookat = Point3::new(0.0, 0.8, 0.0);
+            let aperture = 0.0;
+            let vfov = 30.0;
+            (world, lights, lookfrom, lookat, vfov, aperture)
         }
+        "translucent" => {
+            let world = translucent_spheres();
+            let lights = Lights::new();
+            let lookfrom = Point3::new(0.0, 3.0, 12.0);
+            let lookat = Point3::new(0.0, 0.8, 0.0);
+            let aperture = 0.0;
+            let vfov = 30.0;
+            (world, lights, lookfrom, lookat, vfov, aperture)
+        }
+        "cutouts" => {
+            let world = cutouts();
+            let lights = Lights::new();
+            let lookfrom = Point3::new(0.0, 3.0, 12.0);
+            let lookat = Point3::new(0.0, 0.8, 0.0);
+            let aperture = 0.0;
+            let vfov = 30.0;
+            (world, lights, lookfrom, lookat, vfov, aperture)
+        }
+        "rough-diffuse" => {
+            let world = rough_diffuse();
+            let lights = Lights::new();
+            let lookfrom = Point3::new(0.0, 3.0, 12.0);
+            let lookat = Point3::new(0.0, 0.8, 0.0);
+            let aperture = 0.0;
+            let vfov = 30.0;
+            (world, lights, lookfrom, lookat, vfov, aperture)
+        }
+        "cornell-box" => {
+            let (world, lights) = cornell_box();
+            let lookfrom = Point3::new(278.0, 278.0, -760.0);
+            let lookat = Point3::new(278.0, 278.0, 0.0);
+            let aperture = 0.0;
+            let vfov = 40.0;
+            (world, lights, lookfrom, lookat, vfov, aperture)
+        }
+        "stage" => {
+            let (world, lights) = stage();
+            let lookfrom = Point3::new(0.0, 3.0, 12.0);
+            let lookat = Point3::new(0.0, 0.8, 0.0);
+            let aperture = 0.0;
+            let vfov = 30.0;
+            (world, lights, lookfrom, lookat, vfov, aperture)
+        }
+        "city-at-night" => {
+            let (world, lights) = city_at_night();
+            let lookfrom = Point3::new(0.0, 2.0, 8.0);
+            let lookat = Point3::new(0.0, 5.0, -40.0);
+            let aperture = 0.0;
+            let vfov = 50.0;
+            (world, lights, lookfrom, lookat, vfov, aperture)
+        }
+        "nested-media" => {
+            let world = nested_media();
+            let lights = Lights::new();
+            let lookfrom = Point3::new(0.0, 3.0, 12.0);
+            let lookat = Point3::new(0.0, 0.8, 0.0);
+            let aperture = 0.0;
+            let vfov = 20.0;
+            (world, lights, lookfrom, lookat, vfov, aperture)
+        }
+        _ => unreachable!("options only accept known scenes"),
     };
+    let mesh = options.mesh.as_ref().map(|path| {
+        let white: Rc<dyn Material> = Rc::new(Lambertian::new(Box::new(SolidColor::new(Color::new(0.73, 0.73, 0.73)))));
+        Rc::new(Mesh::load_obj(path, white).unwrap_or_else(|err| {
+            eprintln!("Could not load mesh {}: {}", path, err);
+            process::exit(1)
+        }))
+    });
+    let object_count = world.objects.len();
+    for triangle in mesh.iter().flat_map(Mesh::triangles) {
+        world.add(triangle);
+    }
+    if options.aovs.is_some() {
+        // Objects are numbered in the order they were added, with the mesh's triangles sharing the last number
+        world.objects = world.objects.drain(..).enumerate().map(|(index, object)| -> Rc<dyn Hittable> {
+            Rc::new(Tagged::new(object, index.min(object_count) + 1))
+        }).collect();
+    }
+    let lights = LightBvh::new(lights);
+    let world = BvhNode::new(&mut world.objects, 0.0, 1.0);
+
 
 
+    let background: Box<dyn Background> = match &options.environment {
+        _ if options.sky => {
+            let (elevation, azimuth) = match options.date {
+                Some((year, month, day)) => solar_position(year, month, day, options.time, options.latitude, options.longitude, options.utc_offset),
+                None => (options.sun_elevation, options.sun_azimuth),
+            };
+            let ground_albedo = Color::new(options.ground_albedo, options.ground_albedo, options.ground_albedo);
+            Box::new(Sky::new(elevation, azimuth, options.turbidity, ground_albedo, options.sky_intensity))
+        },
+        Some(path) => Box::new(EnvironmentMap::load(path, options.environment_rotation, options.environment_intensity).unwrap_or_else(|err| {
+            eprintln!("Could not load environment map {}: {}", path, err);
+            process::exit(1)
+        })),
+        None => match options.background {
+            Some((r, g, b)) => Box::new(Gradient::new(Color::new(r, g, b), Color::new(r, g, b))),
+            None => Box::new(Gradient::new(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0))),
+        },
+    };
 
     // Camera
 
@@ -83,24 +319,82 @@ fn main() {
         vup,
         /*90.0*/vfov, aspect_ratio, aperture, dist_to_focus, 0.0, 1.0);
 
+    let film = Film::new(image_width as usize, image_height as usize);
+    let scene = Scene {world: &world, lights: &lights, background: background.as_ref(), camera: &camera, film: &film};
+    let focus_distance = (lookat - lookfrom).length();
+
+    if let (Some(mode), Some(mesh)) = (&options.bake, &mesh) {
+        let mode = match mode.as_str() {
+            "irradiance" => BakeMode::Irradiance,
+            "ao" => BakeMode::AmbientOcclusion,
+            "bent-normals" => BakeMode::BentNormals,
+            "radiance" => BakeMode::Radiance,
+            _ => unreachable!("options only accept known bake modes"),
+        };
+        let size = mesh.bounding_box().map_or(1.0, |bounds| (bounds.max() - bounds.min()).length());
+        let baker = Baker::new(scene, mode, options.depth, options.ao_distance.unwrap_or(0.25 * size));
+        let mut lightmap = baker.bake(mesh, options.lightmap_size as usize, samples_per_pixel as u32);
+        lightmap.dilate(options.dilation);
+        lightmap.write();
+        eprintln!("Done");
+        return
+    }
+    let integrator: Box<dyn Integrator> = match options.integrator.as_str() {
+        "path" if options.guiding => Box::new(PathTracer::new(options.depth).with_guiding()),
+        "path" => Box::new(PathTracer::new(options.depth)),
+        "bdpt" => Box::new(Bdpt::new(options.depth.max_depth)),
+        "sppm" => Box::new(Sppm::new(options.depth.max_depth, options.photons, options.photon_radius.unwrap_or(0.01 * focus_distance))),
+        "mlt" => Box::new(Mlt::new(options.depth, options.bootstrap, options.chains, options.seed as u64)),
+        "whitted" => Box::new(Whitted::new(options.depth.max_depth)),
+        "ao" => Box::new(AmbientOcclusion::new(options.ao_distance.unwrap_or(0.25 * focus_distance))),
+        "normals" => Box::new(Normals),
+        "uv" => Box::new(Uv),
+        "depth" => Box::new(Depth::new(2.0 * focus_distance)),
+        "barycentrics" => Box::new(Barycentrics),
+        "material-id" => Box::new(MaterialId),
+        "bvh-visits" => Box::new(BvhVisits::new(256.0)),
+        _ => unreachable!("options only accept known integrators"),
+    };
+
     // Render
 
-    println!("P3");
-    println!("{0} {1}", image_width, image_height);
-    println!("255");
-    for j in (0..image_height).rev() {
-        eprintln!("Scanlines remaining {0}", j);
-        for i in 0..image_width {
-            let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-            for _ in 0..samples_per_pixel{
-                let u = (i as f64 + rand::thread_rng().gen_range(0.0..1.0)) / (image_width as f64 - 1.0);
-                let v = (j as f64 + rand::thread_rng().gen_range(0.0..1.0)) / (image_height as f64 - 1.0);
-                let r = camera.get_ray(u, v);
-                pixel_color = pixel_color + ray_color(&r, &world, max_depth);
+    let mut aovs = options.aovs.as_ref().map(|_| Aovs::new(image_width as usize, image_height as usize));
+    for pass in 0..samples_per_pixel {
+        eprintln!("Passes remaining {0}", samples_per_pixel - pass);
+        integrator.begin_pass(&scene);
+        for j in 0..image_height {
+            for i in 0..image_width {
+                let u = (i as f64 + random_double(0.0, 1.0)) / image_width as f64;
+                let v = (j as f64 + random_double(0.0, 1.0)) / image_height as f64;
+                let mut r = camera.get_ray(u, v);
+                let (i, j) = (i as usize, j as usize);
+                let spectral = options.spectral && integrator.is_spectral();
+                let wavelengths = if spectral { Some(Wavelengths::sample()) } else { None };
+                let to_rgb = |c: &Color| wavelengths.map_or(*c, |w| w.radiance_to_rgb(c));
+                if let Some(aovs) = aovs.as_mut() {
+                    aovs.add_surface(i, j, pass == 0, &r, &scene);
+                }
+                r.wavelengths = wavelengths;
+                let paths = aovs.as_ref().and_then(|_| integrator.light_paths(r, &scene));
+                let color = match (aovs.as_mut(), paths) {
+                    (Some(aovs), Some(paths)) => {
+                        let paths = paths.map(to_rgb);
+                        aovs.add_light(i, j, &paths);
+                        paths.total()
+                    },
+                    _ => to_rgb(&integrator.ray_color(r, &scene)),
+                };
+                film.add_sample(i, j, color);
             }
-            write_color(&pixel_color, samples_per_pixel);
         }
     }
+    film.write(samples_per_pixel);
+    if let (Some(path), Some(aovs)) = (&options.aovs, &aovs) {
+        aovs.write(path, &film, samples_per_pixel).unwrap_or_else(|err| {
+            eprintln!("Could not write render passes to {}: {}", path, err);
+            process::exit(1)
+        });
+    }
 
     eprintln!("Done");
 }
@@ -130,34 +424,12 @@ fn write_color(clr: &Color, samples_per_pixel: i32) {
     println!("{0} {1} {2}", ir, ig, ib);
 }
 
-fn ray_color(ray: &Ray, world: &dyn Hittable, depth: i32) -> Color {
-    if depth <= 0{
-        return Color::new(0.0, 0.0, 0.0)
-    }
-
-    let record = world.hit(ray, 0.001, f64::MAX);
-    record.map_or_else(
-        || {
-            let unit_direction: Vec3 = ray.direction.unit();
-            let t: f64 = 0.5 * (unit_direction.y() + 1.0);
-            (Color::new(1.0, 1.0, 1.0) * (1.0 - t)) + (Color::new(0.5, 0.7, 1.0) * t)
-        },
-        |rec| {
-            let scatter = rec.material.scatter(ray, &rec);
-            scatter.map_or_else(
-                || {Color::new(0.0, 0.0, 0.0)},
-                |scatter| {
-                scatter.attenuation * ray_color(&scatter.scatter, world, depth - 1)
-            })
-        })
-}
-
 fn random_double(min: f64, max: f64) -> f64 {
-    rand::thread_rng().gen_range(min..max)
+    min + (max - min) * sampler::uniform()
 }
 
 fn random_int(min: i32, max: i32) -> i32 {
-    rand::thread_rng().gen_range(min..=max)
+    (min + (sampler::uniform() * (max - min + 1) as f64) as i32).min(max)
 }
 
 fn random_scene() -> HittableList {
@@ -178,17 +450,17 @@ fn random_scene() -> HittableList {
                     let albedo = Color::random(0.0, 1.0);
                     let material = Rc::new(Lambertian::new(Box::new(SolidColor::new(albedo))));
                     let center1 = center + Vec3::new(0.0, random_double(0.0, 0.5), 0.0);
-                    Rc::new(MovingSphere {center0: center, center1: center1, time0: 0.0, time1: 1.0, radius: 0.2, material: material.clone()})
+                    Rc::new(MovingSphere {center0: center, center1, time0: 0.0, time1: 1.0, radius: 0.2, material: material.clone()})
                 } else if choose_mat < 0.95 {
                     // metal
                     let albedo = Color::random(0.0, 1.0);
                     let fuzz = rand::thread_rng().gen_range(0.0..0.5);
                     let material = Rc::new(Metal::new(albedo, fuzz));
-                    Rc::new(Sphere {center: center, radius: 0.2, material: material.clone()})
+                    Rc::new(Sphere {center, radius: 0.2, material: material.clone()})
                 } else {
                     // glass
                     let material = Rc::new(Dielectric::new(1.5));
-                    Rc::new(Sphere {center: center, radius: 0.2, material: material.clone()})
+                    Rc::new(Sphere {center, radius: 0.2, material: material.clone()})
                 };
                 world.add(sphere.clone());
             }
@@ -209,10 +481,346 @@ fn random_scene() -> HittableList {
 
 fn two_spheres() -> HittableList {
     let mut objects = HittableList::new();
-    let checker: Box<Texture> = Box::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+    let checker: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
     let lambertian = Rc::new(Lambertian::new(checker));
     objects.add(Rc::new(Sphere {center: Point3::new(0.0, -10.0, 0.0), radius: 10.0, material: lambertian.clone()}));
     objects.add(Rc::new(Sphere {center: Point3::new(0.0, 10.0, 0.0), radius: 10.0, material: lambertian.clone()}));
 
     objects
-}
\ No newline at end of file
+}
+
+fn bumpy_spheres() -> HittableList {
+    let mut objects = HittableList::new();
+    let checker: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+    let ground = Rc::new(Lambertian::new(checker));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: ground}));
+
+    let height: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0)));
+    let base = Rc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.1));
+    let bumped = Rc::new(BumpMap::new(base, height, 0.002));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, 1.0, 0.0), radius: 1.0, material: bumped}));
+
+    // Facets tilted alternately along +u and -u
+    let normals: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.7, 0.5, 0.9), Color::new(0.3, 0.5, 0.9)));
+    let base = Rc::new(Lambertian::new(Box::new(SolidColor::new(Color::new(0.4, 0.2, 0.1)))));
+    let mapped = Rc::new(NormalMap::new(base, normals));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, 1.0, -2.5), radius: 1.0, material: mapped}));
+
+    objects
+}
+
+fn metals(measured: Option<Rc<dyn Material>>) -> HittableList {
+    let mut objects = HittableList::new();
+    let checker: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: Rc::new(Lambertian::new(checker))}));
+
+    let roughness = |r: f64| -> Box<dyn Texture> { Box::new(SolidColor::new(Color::new(r, r, r))) };
+    let gold = measured.unwrap_or_else(|| Rc::new(Conductor::gold(roughness(0.2))));
+    let copper = Rc::new(Conductor::copper(roughness(0.4)));
+    let aluminium = Rc::new(Conductor::aluminium(roughness(0.05)).with_roughness_v(roughness(0.6)));
+    let silver = Rc::new(Conductor::silver(Box::new(CheckerTexture::new(Color::new(0.05, 0.05, 0.05), Color::new(0.5, 0.5, 0.5)))));
+    objects.add(Rc::new(Sphere {center: Point3::new(-3.3, 1.0, 0.0), radius: 1.0, material: gold}));
+    objects.add(Rc::new(Sphere {center: Point3::new(-1.1, 1.0, 0.0), radius: 1.0, material: copper}));
+    objects.add(Rc::new(Sphere {center: Point3::new(1.1, 1.0, 0.0), radius: 1.0, material: aluminium}));
+    objects.add(Rc::new(Sphere {center: Point3::new(3.3, 1.0, 0.0), radius: 1.0, material: silver}));
+
+    objects
+}
+
+fn principled_spheres() -> HittableList {
+    let mut objects = HittableList::new();
+    let checker: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: Rc::new(Lambertian::new(checker))}));
+
+    let constant = |v: f64| -> Box<dyn Texture> { Box::new(SolidColor::new(Color::new(v, v, v))) };
+    let color = |r: f64, g: f64, b: f64| -> Box<dyn Texture> { Box::new(SolidColor::new(Color::new(r, g, b))) };
+    let plastic = Principled::new(PrincipledParameters {
+        base_color: color(0.8, 0.1, 0.1), roughness: constant(0.3), ..Default::default()
+    });
+    let brushed_metal = Principled::new(PrincipledParameters {
+        base_color: color(0.9, 0.8, 0.6), metallic: constant(1.0), roughness: constant(0.4), anisotropic: constant(0.8), ..Default::default()
+    });
+    let car_paint = Principled::new(PrincipledParameters {
+        base_color: color(0.05, 0.1, 0.5), roughness: constant(0.6), clearcoat: constant(1.0), clearcoat_gloss: constant(0.95), ..Default::default()
+    });
+    let frosted_glass = Principled::new(PrincipledParameters {
+        base_color: color(0.9, 1.0, 0.95), roughness: constant(0.2), transmission: constant(1.0), ..Default::default()
+    });
+    let velvet = Principled::new(PrincipledParameters {
+        base_color: color(0.4, 0.05, 0.3), roughness: constant(1.0), specular: constant(0.1), sheen: constant(1.0), ..Default::default()
+    });
+    let materials = vec![plastic, brushed_metal, car_paint, frosted_glass, velvet];
+    for (i, material) in materials.into_iter().enumerate() {
+        let x = -4.4 + 2.2 * i as f64;
+        objects.add(Rc::new(Sphere {center: Point3::new(x, 1.0, 0.0), radius: 1.0, material: Rc::new(material)}));
+    }
+
+    objects
+}
+
+fn glasses() -> HittableList {
+    let mut objects = HittableList::new();
+    let checker: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: Rc::new(Lambertian::new(checker))}));
+
+    let roughness = |r: f64| -> Box<dyn Texture> { Box::new(SolidColor::new(Color::new(r, r, r))) };
+    let clear = Rc::new(Dielectric::new(1.5));
+    let tinted = Rc::new(Dielectric::new(1.5).with_absorption(Color::new(0.9, 0.4, 0.2), 1.0));
+    let frosted = Rc::new(RoughDielectric::new(1.5, roughness(0.3)));
+    let frosted_tinted = Rc::new(RoughDielectric::new(1.33, roughness(0.1)).with_absorption(Color::new(0.3, 0.6, 0.9), 1.0));
+    objects.add(Rc::new(Sphere {center: Point3::new(-3.3, 1.0, 0.0), radius: 1.0, material: clear}));
+    objects.add(Rc::new(Sphere {center: Point3::new(-1.1, 1.0, 0.0), radius: 1.0, material: tinted}));
+    objects.add(Rc::new(Sphere {center: Point3::new(1.1, 1.0, 0.0), radius: 1.0, material: frosted}));
+    objects.add(Rc::new(Sphere {center: Point3::new(3.3, 1.0, 0.0), radius: 1.0, material: frosted_tinted}));
+
+    objects
+}
+
+fn dispersion() -> HittableList {
+    let mut objects = HittableList::new();
+    let checker: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: Rc::new(Lambertian::new(checker))}));
+
+    let crown_glass = Rc::new(Dielectric::sellmeier([1.03961212, 0.231792344, 1.01046945], [0.00600069867, 0.0200179144, 103.560653]));
+    let diamond = Rc::new(Dielectric::sellmeier([0.3306, 4.3356, 0.0], [0.030625, 0.011236, 0.0]));
+    let exaggerated = Rc::new(Dielectric::cauchy(1.45, 0.08));
+    objects.add(Rc::new(Sphere {center: Point3::new(-2.2, 1.0, 0.0), radius: 1.0, material: crown_glass}));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, 1.0, 0.0), radius: 1.0, material: diamond}));
+    objects.add(Rc::new(Sphere {center: Point3::new(2.2, 1.0, 0.0), radius: 1.0, material: exaggerated}));
+
+    objects
+}
+
+fn iridescence() -> HittableList {
+    let mut objects = HittableList::new();
+    let checker: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: Rc::new(Lambertian::new(checker))}));
+
+    let thickness = |nm: f64| -> Box<dyn Texture> { Box::new(SolidColor::new(Color::new(nm, nm, nm))) };
+    let soap_bubble = Rc::new(Dielectric::new(1.0).with_thin_film(ThinFilm::new(thickness(450.0), 1.33)));
+    let coated_lens = Rc::new(Dielectric::new(1.5).with_thin_film(ThinFilm::new(thickness(100.0), 1.38)));
+    let anodized = Rc::new(Metal::new(Color::new(0.6, 0.6, 0.6), 0.05).with_thin_film(ThinFilm::new(thickness(300.0), 2.0)));
+    let oily_copper = Rc::new(Conductor::copper(Box::new(SolidColor::new(Color::new(0.2, 0.2, 0.2))))
+        .with_thin_film(ThinFilm::new(Box::new(CheckerTexture::new(Color::new(250.0, 250.0, 250.0), Color::new(500.0, 500.0, 500.0))), 1.5)));
+    objects.add(Rc::new(Sphere {center: Point3::new(-3.3, 1.0, 0.0), radius: 1.0, material: soap_bubble}));
+    objects.add(Rc::new(Sphere {center: Point3::new(-1.1, 1.0, 0.0), radius: 1.0, material: coated_lens}));
+    objects.add(Rc::new(Sphere {center: Point3::new(1.1, 1.0, 0.0), radius: 1.0, material: anodized}));
+    objects.add(Rc::new(Sphere {center: Point3::new(3.3, 1.0, 0.0), radius: 1.0, material: oily_copper}));
+
+    objects
+}
+
+fn coatings() -> HittableList {
+    let mut objects = HittableList::new();
+    let checker: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: Rc::new(Lambertian::new(checker))}));
+
+    let solid = |r: f64, g: f64, b: f64| -> Box<dyn Texture> { Box::new(SolidColor::new(Color::new(r, g, b))) };
+    let car_paint = Rc::new(Coated::new(Rc::new(Lambertian::new(solid(0.6, 0.02, 0.02))), 1.5));
+    let wood = Rc::new(Lambertian::new(Box::new(CheckerTexture::new(Color::new(0.5, 0.3, 0.15), Color::new(0.35, 0.2, 0.1)))));
+    let varnished_wood = Rc::new(Coated::new(wood, 1.5).with_roughness(solid(0.15, 0.15, 0.15)).with_absorption(Color::new(0.9, 0.7, 0.4)));
+    let glazed_ceramic = Rc::new(Coated::new(Rc::new(Lambertian::new(solid(0.9, 0.9, 0.85))), 1.55));
+    let coated_gold = Rc::new(Coated::new(Rc::new(Conductor::gold(solid(0.4, 0.4, 0.4))), 1.5));
+    objects.add(Rc::new(Sphere {center: Point3::new(-3.3, 1.0, 0.0), radius: 1.0, material: car_paint}));
+    objects.add(Rc::new(Sphere {center: Point3::new(-1.1, 1.0, 0.0), radius: 1.0, material: varnished_wood}));
+    objects.add(Rc::new(Sphere {center: Point3::new(1.1, 1.0, 0.0), radius: 1.0, material: glazed_ceramic}));
+    objects.add(Rc::new(Sphere {center: Point3::new(3.3, 1.0, 0.0), radius: 1.0, material: coated_gold}));
+
+    objects
+}
+
+fn translucent_spheres() -> HittableList {
+    let mut objects = HittableList::new();
+    let checker: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: Rc::new(Lambertian::new(checker))}));
+
+    let solid = |r: f64, g: f64, b: f64| -> Box<dyn Texture> { Box::new(SolidColor::new(Color::new(r, g, b))) };
+    let skin = Rc::new(Subsurface::new(solid(0.85, 0.55, 0.45), solid(0.15, 0.08, 0.04), 1.4));
+    let wax = Rc::new(Subsurface::new(solid(0.9, 0.75, 0.4), solid(0.3, 0.2, 0.1), 1.45).with_anisotropy(0.3));
+    let marble = Rc::new(Subsurface::new(Box::new(CheckerTexture::new(Color::new(0.9, 0.9, 0.88), Color::new(0.6, 0.6, 0.62))), solid(0.1, 0.1, 0.1), 1.5));
+    // Whole milk as measured by Jensen et al. (2001), for a scene measured in units of 4 mm
+    let milk = Rc::new(Subsurface::from_coefficients(solid(0.0044, 0.0096, 0.056), solid(10.2, 12.84, 15.08), 1.35));
+    objects.add(Rc::new(Sphere {center: Point3::new(-3.3, 1.0, 0.0), radius: 1.0, material: skin}));
+    objects.add(Rc::new(Sphere {center: Point3::new(-1.1, 1.0, 0.0), radius: 1.0, material: wax}));
+    objects.add(Rc::new(Sphere {center: Point3::new(1.1, 1.0, 0.0), radius: 1.0, material: marble}));
+    objects.add(Rc::new(Sphere {center: Point3::new(3.3, 1.0, 0.0), radius: 1.0, material: milk}));
+
+    objects
+}
+
+fn cutouts() -> HittableList {
+    let mut objects = HittableList::new();
+    let checker: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: Rc::new(Lambertian::new(checker))}));
+
+    let solid = |r: f64, g: f64, b: f64| -> Box<dyn Texture> { Box::new(SolidColor::new(Color::new(r, g, b))) };
+    objects.add(Rc::new(Sphere {center: Point3::new(-1.5, 1.0, -1.5), radius: 1.0, material: Rc::new(Lambertian::new(solid(0.7, 0.1, 0.1)))}));
+    objects.add(Rc::new(Sphere {center: Point3::new(1.5, 1.0, -1.5), radius: 1.0, material: Rc::new(Lambertian::new(solid(0.1, 0.2, 0.7)))}));
+
+    // A fence with square holes, and a screen that lets half the light through
+    let fence = Rc::new(Quad::new(Point3::new(-3.0, 0.0, 0.5), Vec3::new(2.8, 0.0, 0.0), Vec3::new(0.0, 2.2, 0.0),
+                                  Rc::new(Lambertian::new(solid(0.55, 0.4, 0.25)))));
+    let holes: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0)));
+    objects.add(Rc::new(AlphaMask::new(fence, holes)));
+    let screen = Rc::new(Quad::new(Point3::new(0.2, 0.0, 0.5), Vec3::new(2.8, 0.0, 0.0), Vec3::new(0.0, 2.2, 0.0),
+                                   Rc::new(Lambertian::new(solid(0.8, 0.8, 0.8)))));
+    objects.add(Rc::new(AlphaMask::new(screen, solid(0.5, 0.5, 0.5))));
+
+    objects
+}
+
+fn rough_diffuse() -> HittableList {
+    let mut objects = HittableList::new();
+    let checker: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: Rc::new(Lambertian::new(checker))}));
+
+    let solid = |r: f64, g: f64, b: f64| -> Box<dyn Texture> { Box::new(SolidColor::new(Color::new(r, g, b))) };
+    let lambertian = Rc::new(Lambertian::new(solid(0.7, 0.7, 0.7)));
+    let clay = Rc::new(OrenNayar::new(solid(0.7, 0.45, 0.3), solid(1.0, 1.0, 1.0)));
+    let moon = Rc::new(OrenNayar::energy_preserving(solid(0.7, 0.7, 0.7), solid(1.0, 1.0, 1.0)));
+    let velvet = Rc::new(Sheen::new(Rc::new(OrenNayar::energy_preserving(solid(0.25, 0.02, 0.06), solid(0.5, 0.5, 0.5))),
+                                    solid(1.0, 0.6, 0.7), solid(0.3, 0.3, 0.3)));
+    objects.add(Rc::new(Sphere {center: Point3::new(-3.3, 1.0, 0.0), radius: 1.0, material: lambertian}));
+    objects.add(Rc::new(Sphere {center: Point3::new(-1.1, 1.0, 0.0), radius: 1.0, material: clay}));
+    objects.add(Rc::new(Sphere {center: Point3::new(1.1, 1.0, 0.0), radius: 1.0, material: moon}));
+    objects.add(Rc::new(Sphere {center: Point3::new(3.3, 1.0, 0.0), radius: 1.0, material: velvet}));
+
+    objects
+}
+
+/// Glass bowl filled with water, with a cube of frosted ice floating in it. The water reaches into the
+/// glass, which takes precedence, so the two meet without a gap; the ice takes precedence over the water.
+fn nested_media() -> HittableList {
+    let mut objects = HittableList::new();
+    let checker: Box<dyn Texture> = Box::new(CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: Rc::new(Lambertian::new(checker))}));
+
+    let center = Point3::new(0.0, 1.2, 0.0);
+    let glass: Rc<dyn Material> = Rc::new(Dielectric::new(1.5).with_priority(2));
+    let water: Rc<dyn Material> = Rc::new(Dielectric::new(1.33).with_absorption(Color::new(0.7, 0.85, 0.9), 1.0).with_priority(1));
+    let roughness: Box<dyn Texture> = Box::new(SolidColor::new(Color::new(0.2, 0.2, 0.2)));
+    let ice: Rc<dyn Material> = Rc::new(RoughDielectric::new(1.31, roughness).with_priority(3));
+    objects.add(Rc::new(Sphere {center, radius: 1.2, material: glass.clone()}));
+    objects.add(Rc::new(Sphere {center, radius: -1.1, material: glass}));
+    objects.add(Rc::new(Sphere {center, radius: 1.15, material: water}));
+    add_box(&mut objects, center + Vec3::new(-0.3, 0.0, -0.3), center + Vec3::new(0.3, 0.6, 0.3), ice);
+
+    objects
+}
+
+/// Closed Cornell box with the camera inside, lit by a small panel in the ceiling and a small sphere.
+fn cornell_box() -> (HittableList, Lights) {
+    let mut objects = HittableList::new();
+    let mut lights = Lights::new();
+    let solid = |r: f64, g: f64, b: f64| -> Box<dyn Texture> { Box::new(SolidColor::new(Color::new(r, g, b))) };
+    let red: Rc<dyn Material> = Rc::new(Lambertian::new(solid(0.65, 0.05, 0.05)));
+    let white: Rc<dyn Material> = Rc::new(Lambertian::new(solid(0.73, 0.73, 0.73)));
+    let green: Rc<dyn Material> = Rc::new(Lambertian::new(solid(0.12, 0.45, 0.15)));
+
+    objects.add(Rc::new(Quad::new(Point3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 555.0, 0.0), Vec3::new(0.0, 0.0, 555.0), green)));
+    objects.add(Rc::new(Quad::new(Point3::new(0.0, 0.0, 555.0), Vec3::new(0.0, 555.0, 0.0), Vec3::new(0.0, 0.0, -555.0), red)));
+    objects.add(Rc::new(Quad::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 555.0), Vec3::new(555.0, 0.0, 0.0), white.clone())));
+    objects.add(Rc::new(Quad::new(Point3::new(0.0, 555.0, 0.0), Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 555.0), white.clone())));
+    objects.add(Rc::new(Quad::new(Point3::new(0.0, 0.0, 555.0), Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 555.0, 0.0), white.clone())));
+    objects.add(Rc::new(Quad::new(Point3::new(0.0, 0.0, -800.0), Vec3::new(0.0, 555.0, 0.0), Vec3::new(555.0, 0.0, 0.0), white.clone())));
+    objects.add(Rc::new(Quad::new(Point3::new(0.0, 0.0, -800.0), Vec3::new(0.0, 0.0, 800.0), Vec3::new(0.0, 555.0, 0.0), white.clone())));
+    objects.add(Rc::new(Quad::new(Point3::new(555.0, 0.0, -800.0), Vec3::new(0.0, 555.0, 0.0), Vec3::new(0.0, 0.0, 800.0), white.clone())));
+    objects.add(Rc::new(Quad::new(Point3::new(0.0, 0.0, -800.0), Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 800.0), white.clone())));
+    objects.add(Rc::new(Quad::new(Point3::new(0.0, 555.0, -800.0), Vec3::new(0.0, 0.0, 800.0), Vec3::new(555.0, 0.0, 0.0), white.clone())));
+
+    let panel = Rc::new(Quad::new(Point3::new(228.0, 554.0, 227.0), Vec3::new(100.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 100.0),
+                                  Rc::new(DiffuseLight::new(solid(40.0, 40.0, 40.0)))));
+    objects.add(lights.add_shape(panel));
+    let bulb = Rc::new(Sphere {center: Point3::new(120.0, 420.0, 380.0), radius: 15.0, material: Rc::new(DiffuseLight::new(solid(60.0, 45.0, 25.0)))});
+    objects.add(lights.add_shape(bulb));
+
+    let clay: Rc<dyn Material> = Rc::new(OrenNayar::energy_preserving(solid(0.73, 0.73, 0.73), solid(1.0, 1.0, 1.0)));
+    let brushed: Rc<dyn Material> = Rc::new(Conductor::aluminium(solid(0.5, 0.5, 0.5)));
+    objects.add(Rc::new(Sphere {center: Point3::new(180.0, 100.0, 200.0), radius: 100.0, material: clay}));
+    objects.add(Rc::new(Sphere {center: Point3::new(390.0, 100.0, 330.0), radius: 100.0, material: brushed}));
+
+    (objects, lights)
+}
+
+/// Spheres on a stage under three colored spot lights, a warm bulb and cold moonlight. Best rendered with
+/// a dark `--background`.
+fn stage() -> (HittableList, Lights) {
+    let mut objects = HittableList::new();
+    let mut lights = Lights::new();
+    let solid = |r: f64, g: f64, b: f64| -> Box<dyn Texture> { Box::new(SolidColor::new(Color::new(r, g, b))) };
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, -1000.0, 0.0), radius: 1000.0, material: Rc::new(Lambertian::new(solid(0.5, 0.5, 0.5)))}));
+    objects.add(Rc::new(Quad::new(Point3::new(-8.0, 0.0, -3.0), Vec3::new(16.0, 0.0, 0.0), Vec3::new(0.0, 8.0, 0.0),
+                                  Rc::new(Lambertian::new(solid(0.6, 0.6, 0.6))))));
+
+    objects.add(Rc::new(Sphere {center: Point3::new(-2.2, 1.0, 0.0), radius: 1.0, material: Rc::new(Lambertian::new(solid(0.8, 0.8, 0.8)))}));
+    objects.add(Rc::new(Sphere {center: Point3::new(0.0, 1.0, 0.0), radius: 1.0, material: Rc::new(Conductor::gold(solid(0.3, 0.3, 0.3)))}));
+    objects.add(Rc::new(Sphere {center: Point3::new(2.2, 1.0, 0.0), radius: 1.0, material: Rc::new(OrenNayar::energy_preserving(solid(0.8, 0.8, 0.8), solid(0.8, 0.8, 0.8)))}));
+
+    let spot = |x: f64, color: Color| -> Box<SpotLight> {
+        Box::new(SpotLight::new(Point3::new(x, 6.0, 4.0), Point3::new(x * 0.3, 0.0, 0.0), color, 400.0, 20.0, 12.0))
+    };
+    lights.add(spot(-4.0, Color::new(1.0, 0.2, 0.2)));
+    lights.add(spot(0.0, Color::new(0.2, 1.0, 0.2)));
+    lights.add(spot(4.0, Color::new(0.2, 0.3, 1.0)));
+    lights.add(Box::new(PointLight::new(Point3::new(0.0, 2.5, 3.0), Color::new(1.0, 0.8, 0.6), 60.0)));
+    lights.add(Box::new(DirectionalLight::new(Vec3::new(1.0, -1.0, -0.5), Color::new(0.6, 0.7, 1.0), 0.3)));
+
+    (objects, lights)
+}
+
+/// Street lined with office blocks, lit by hundreds of windows and street lamps. Best rendered with a
+/// dark `--background`.
+fn city_at_night() -> (HittableList, Lights) {
+    let mut objects = HittableList::new();
+    let mut lights = Lights::new();
+    let solid = |r: f64, g: f64, b: f64| -> Box<dyn Texture> { Box::new(SolidColor::new(Color::new(r, g, b))) };
+    let concrete: Rc<dyn Material> = Rc::new(Lambertian::new(solid(0.4, 0.4, 0.4)));
+    let asphalt: Rc<dyn Material> = Rc::new(Lambertian::new(solid(0.1, 0.1, 0.1)));
+    objects.add(Rc::new(Quad::new(Point3::new(-50.0, 0.0, 20.0), Vec3::new(100.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -150.0), asphalt)));
+
+    for side in [-1.0, 1.0].iter() {
+        let facade = 4.0 * side;
+        for block in 0..16 {
+            let z = 10.0 - 8.0 * block as f64;
+            let height = random_double(6.0, 20.0);
+            let (near, far) = (Point3::new(facade, 0.0, z - 7.0), Point3::new(facade + 8.0 * side, height, z));
+            add_box(&mut objects, near, far, concrete.clone());
+
+            // Lit windows sit just in front of the facade, facing the street
+            for floor in 0..((height - 1.0) / 1.5) as i32 {
+                for column in 0..5 {
+                    if random_double(0.0, 1.0) > 0.35 {
+                        continue
+                    }
+                    let corner = Point3::new(facade - 0.01 * side, 1.0 + 1.5 * floor as f64, z - 1.0 - 1.2 * column as f64);
+                    let (up, along) = (Vec3::new(0.0, 0.8, 0.0), Vec3::new(0.0, 0.0, -0.7));
+                    let (u, v) = if *side < 0.0 { (along, up) } else { (up, along) };
+                    let warmth = random_double(0.0, 1.0);
+                    let emit = Color::new(3.0, 2.0 + warmth, 1.0 + 2.0 * warmth);
+                    let window = Rc::new(Quad::new(corner, u, v, Rc::new(DiffuseLight::new(Box::new(SolidColor::new(emit))))));
+                    objects.add(lights.add_shape(window));
+                }
+            }
+
+            let lamp = Rc::new(Sphere {center: Point3::new(3.0 * side, 4.0, z - 3.5), radius: 0.15,
+                                       material: Rc::new(DiffuseLight::new(solid(200.0, 120.0, 40.0)))});
+            objects.add(lights.add_shape(lamp));
+        }
+    }
+    (objects, lights)
+}
+
+/// Adds the six sides of the axis-aligned box between the corners `a` and `b`.
+fn add_box(objects: &mut HittableList, a: Point3, b: Point3, material: Rc<dyn Material>) {
+    let min = Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+    let max = Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+    let dx = Vec3::new(max.x() - min.x(), 0.0, 0.0);
+    let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
+    let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
+    objects.add(Rc::new(Quad::new(Point3::new(min.x(), min.y(), max.z()), dx, dy, material.clone())));
+    objects.add(Rc::new(Quad::new(Point3::new(max.x(), min.y(), max.z()), -dz, dy, material.clone())));
+    objects.add(Rc::new(Quad::new(Point3::new(max.x(), min.y(), min.z()), -dx, dy, material.clone())));
+    objects.add(Rc::new(Quad::new(Point3::new(min.x(), min.y(), min.z()), dz, dy, material.clone())));
+    objects.add(Rc::new(Quad::new(Point3::new(min.x(), max.y(), max.z()), dx, -dz, material.clone())));
+    objects.add(Rc::new(Quad::new(Point3::new(min.x(), min.y(), min.z()), dx, dz, material)));
+}
@@ -1,38 +1,116 @@
 use crate::ray::{HitRecord, Ray};
-use crate::vec3::{Color, Vec3};
-use rand::Rng;
+use crate::vec3::{Color, Point3, Vec3};
 use crate::texture::Texture;
+use crate::medium::{Absorption, Interior};
+use crate::spectrum::IndexOfRefraction;
+use crate::thin_film::ThinFilm;
+use crate::pdf::{CosinePdf, Pdf};
+use std::f64::consts::PI;
 
+/// Kind of lobe a direction was sampled from. Specular lobes are delta distributions that no other
+/// direction can be evaluated for, so light sampling skips them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lobe {
+    Diffuse,
+    Glossy,
+    Specular,
+    /// Scattering off particles inside a medium.
+    Volume,
+}
+
+/// Direction picked by `Material::sample`. `attenuation` is the BSDF times the cosine over `pdf`, the
+/// density with which the direction was picked. `pdf` is zero for specular lobes and for materials that
+/// can't evaluate their BSDF, like the stochastically layered ones.
 pub struct Scatter{
     pub attenuation: Color,
     pub scatter: Ray,
+    pub pdf: f64,
+    pub lobe: Lobe,
+}
+
+impl Scatter {
+    pub fn new(attenuation: Color, scatter: Ray, pdf: f64, lobe: Lobe) -> Scatter {
+        Scatter {attenuation, scatter, pdf, lobe}
+    }
+
+    pub fn specular(attenuation: Color, scatter: Ray) -> Scatter {
+        Scatter {attenuation, scatter, pdf: 0.0, lobe: Lobe::Specular}
+    }
+}
+
+/// What a path carries: radiance along paths traced from the camera, or importance along paths traced
+/// from the lights. BSDFs that aren't symmetric, like refraction into a denser medium, differ between
+/// the two.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransportMode {
+    Radiance,
+    Importance,
 }
 
 pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter>;
+    /// Picks a direction for the light arriving along `r_in` to continue in, or `None` when it's absorbed.
+    fn sample(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter>;
+
+    /// BSDF times the cosine with the shading normal for scattering into `direction`, for transporting
+    /// `mode` from `r_in`. Materials that return a non-zero `pdf` have to implement it, as light sampling
+    /// relies on it. `sample` weighs directions for radiance.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3, _mode: TransportMode) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    /// Density per unit solid angle with which `sample` picks `direction`. Materials that can't evaluate
+    /// their BSDF return zero, which opts them out of light sampling.
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    /// Radiance emitted by the surface back along `r_in`.
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    /// Rough guess of the radiance the front of the surface emits, for estimating how much a light
+    /// made of the material contributes.
+    fn emission(&self) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    /// Whether `sample` and `eval` give values at the wavelengths of `r_in` in spectral mode, rather than
+    /// a color for integrators to upsample.
+    fn is_spectral(&self) -> bool {
+        false
+    }
+
+    /// What fills the inside of objects made of the material, for paths that track the media they're in.
+    /// Surfaces without an interior separate media wherever they are, and leave them as they were.
+    fn interior(&self) -> Option<Interior> {
+        None
+    }
 }
 
 pub struct Lambertian {
-    albedo: Box<Texture>,
+    albedo: Box<dyn Texture>,
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
-        let scatter_direction = rec.normal + Vec3::random_unit_vector();
-        let scatter = if scatter_direction.near_zero() {
-            Ray::new(rec.p, rec.normal, r_in.time)
-        }else {
-            Ray::new(rec.p, scatter_direction, r_in.time)
-        };
+    fn sample(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let pdf = CosinePdf::new(&rec.normal);
+        let direction = pdf.generate()?;
         let attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
-        Some(Scatter{
-            attenuation, scatter,
-        })
+        Some(Scatter::new(attenuation, Ray::new(rec.p, direction, r_in.time), pdf.value(&direction), Lobe::Diffuse))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3, _mode: TransportMode) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p) * self.pdf(r_in, rec, direction)
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        CosinePdf::new(&rec.normal).value(direction)
     }
 }
 
 impl Lambertian {
-    pub fn new(albedo: Box<Texture>) -> Lambertian {
+    pub fn new(albedo: Box<dyn Texture>) -> Lambertian {
         Lambertian{albedo}
     }
 }
@@ -40,44 +118,95 @@ impl Lambertian {
 pub struct Metal {
     albedo: Color,
     fuzz: f64,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Material for Metal{
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+    fn sample(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
         let reflected = r_in.direction.unit().reflect(&rec.normal);
         let scatter = Ray::new(rec.p, reflected+ Vec3::random_in_unit_sphere() * self.fuzz, r_in.time);
-        let attenuation = self.albedo;
-        if scatter.direction.dot(&rec.normal) > 0.0 {
-            Some(Scatter {
-                scatter, attenuation,
-            })
-        }else {
-            None
+        let attenuation = self.reflectance(r_in, rec);
+        if scatter.direction.dot(&rec.normal) <= 0.0 {
+            return None
+        }
+        if self.fuzz == 0.0 {
+            return Some(Scatter::specular(attenuation, scatter))
+        }
+        let pdf = self.pdf(r_in, rec, &scatter.direction);
+        Some(Scatter::new(attenuation, scatter, pdf, Lobe::Glossy))
+    }
+
+    // Directions below the surface are absorbed, so the BSDF is the reflectance times the density
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3, _mode: TransportMode) -> Color {
+        self.reflectance(r_in, rec) * self.pdf(r_in, rec, direction)
+    }
+
+    /// The end of the scattered direction is uniform in a ball of radius `fuzz` around the mirror
+    /// direction, so the density of a direction is the part of that ball along it, weighted by the
+    /// squared distance of each point.
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        if self.fuzz == 0.0 || direction.dot(&rec.normal) <= 0.0 {
+            return 0.0
+        }
+        let reflected = r_in.direction.unit().reflect(&rec.normal);
+        let b = direction.unit().dot(&reflected);
+        let disc = b * b - 1.0 + self.fuzz * self.fuzz;
+        if disc <= 0.0 {
+            return 0.0
+        }
+        let t_near = (b - disc.sqrt()).max(0.0);
+        let t_far = b + disc.sqrt();
+        if t_far <= 0.0 {
+            return 0.0
         }
+        (t_far.powi(3) - t_near.powi(3)) / (4.0 * PI * self.fuzz.powi(3))
     }
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Metal{
         if fuzz < 1.0 {
-            Metal{albedo, fuzz}
+            Metal{albedo, fuzz, thin_film: None}
         } else{
-            Metal {albedo, fuzz: 1.0}
+            Metal {albedo, fuzz: 1.0, thin_film: None}
+        }
+    }
+
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Metal {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    fn reflectance(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        match &self.thin_film {
+            // The albedo stands for the reflectance at normal incidence of a substrate under the film
+            Some(film) => {
+                let substrate_ior = |a: f64| { let r = a.clamp(0.0, 0.999).sqrt(); (1.0 + r) / (1.0 - r) };
+                let eta = Color::new(substrate_ior(self.albedo.r()), substrate_ior(self.albedo.g()), substrate_ior(self.albedo.b()));
+                film.reflectance_conductor(rec, (-r_in.direction.unit()).dot(&rec.normal), &eta, &Color::new(0.0, 0.0, 0.0))
+            },
+            None => self.albedo,
         }
     }
 }
 
 pub struct Dielectric{
-    ir: f64
+    ior: IndexOfRefraction,
+    absorption: Absorption,
+    thin_film: Option<ThinFilm>,
+    priority: i32,
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
-        let attenuation = Color::new(1.0, 1.0, 1.0);
+    fn sample(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let attenuation = self.absorption.attenuation(r_in, rec);
+        let lambda = r_in.wavelengths.map(|w| w.hero());
+        let ir = self.ior.at(lambda);
+        let outside = rec.outside_ior.unwrap_or(1.0);
         let refraction_ratio = if rec.front_face {
-            1.0/self.ir
+            outside/ir
         }else{
-            self.ir
+            ir/outside
         };
         let unit_direction = r_in.direction.unit();
 
@@ -85,22 +214,104 @@ impl Material for Dielectric {
         let sin_theta = (1.0-cos_theta.powi(2)).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction  = if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > rand::thread_rng().gen_range(0.0..1.0) {
-            unit_direction.reflect(&rec.normal)
-        }else{
-            unit_direction.refract(&rec.normal, refraction_ratio)
+        let (direction, attenuation) = match &self.thin_film {
+            // The film colors the reflected light, so pick a side by its average and reweigh per channel
+            Some(film) => {
+                let (incident, substrate) = if rec.front_face { (outside, ir) } else { (ir, outside) };
+                let reflectance = film.reflectance_dielectric(rec, cos_theta, incident, substrate);
+                let reflect_probability = if cannot_refract { 1.0 } else { (reflectance.r() + reflectance.g() + reflectance.b()) / 3.0 };
+                if reflect_probability > crate::random_double(0.0, 1.0) {
+                    (unit_direction.reflect(&rec.normal), attenuation * reflectance / reflect_probability)
+                } else {
+                    let transmittance = Color::new(1.0, 1.0, 1.0) - reflectance;
+                    (unit_direction.refract(&rec.normal, refraction_ratio), attenuation * transmittance / (1.0 - reflect_probability))
+                }
+            },
+            None => {
+                if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > crate::random_double(0.0, 1.0) {
+                    (unit_direction.reflect(&rec.normal), attenuation)
+                }else{
+                    (unit_direction.refract(&rec.normal, refraction_ratio), attenuation)
+                }
+            },
         };
-        let scatter = Ray::new(rec.p, direction, r_in.time);
-        Some(Scatter {attenuation, scatter})
+        let mut scatter = Ray::new(rec.p, direction, r_in.time);
+        // Other wavelengths would have bent differently, so only the hero wavelength goes on
+        if self.ior.is_dispersive() {
+            scatter.wavelengths = r_in.wavelengths.map(|w| w.terminate_secondary());
+        }
+        Some(Scatter::specular(attenuation, scatter))
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        Some(Interior {ior: self.ior, absorption: self.absorption, priority: self.priority})
     }
 }
 
 impl Dielectric {
     pub fn new(ir: f64) -> Dielectric{
-        Dielectric{ir}
+        Dielectric{ior: IndexOfRefraction::Constant(ir), absorption: Absorption::none(), thin_film: None, priority: 0}
+    }
+
+    /// Dispersive dielectric following Cauchy's equation n = a + b / lambda^2, with lambda in micrometers.
+    pub fn cauchy(a: f64, b: f64) -> Dielectric {
+        Dielectric{ior: IndexOfRefraction::Cauchy {a, b}, absorption: Absorption::none(), thin_film: None, priority: 0}
+    }
+
+    /// Dispersive dielectric following the Sellmeier equation n^2 = 1 + sum(b_i lambda^2 / (lambda^2 - c_i)),
+    /// with lambda in micrometers.
+    pub fn sellmeier(b: [f64; 3], c: [f64; 3]) -> Dielectric {
+        Dielectric{ior: IndexOfRefraction::Sellmeier {b, c}, absorption: Absorption::none(), thin_film: None, priority: 0}
+    }
+
+    /// Tints the inside so that `transmittance` of the light is left after traveling `distance` through it.
+    pub fn with_absorption(mut self, transmittance: Color, distance: f64) -> Dielectric {
+        self.absorption = Absorption::from_transmittance(transmittance, distance);
+        self
+    }
+
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Dielectric {
+        self.thin_film = Some(thin_film);
+        self
     }
+
+    /// Lets the object fill where it overlaps objects of a lower priority; see `Interior`.
+    pub fn with_priority(mut self, priority: i32) -> Dielectric {
+        self.priority = priority;
+        self
+    }
+
     fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
         let r0 = ((1.0-ref_idx) / (1.0+ref_idx)).powi(2);
         r0 + (1.0-r0) * (1.0-cosine).powi(5)
     }
-}
\ No newline at end of file
+}
+
+/// Emits light from its front face and doesn't reflect any.
+pub struct DiffuseLight {
+    emit: Box<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Box<dyn Texture>) -> DiffuseLight {
+        DiffuseLight {emit}
+    }
+}
+
+impl Material for DiffuseLight {
+    fn sample(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<Scatter> {
+        None
+    }
+
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            self.emit.value(rec.u, rec.v, &rec.p)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    fn emission(&self) -> Color {
+        self.emit.value(0.5, 0.5, &Point3::new(0.0, 0.0, 0.0))
+    }
+}
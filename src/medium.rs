@@ -0,0 +1,207 @@
+use crate::material::{Lobe, Scatter};
+use crate::onb::Onb;
+use crate::random_double;
+use crate::ray::{HitRecord, Ray};
+use crate::spectrum::IndexOfRefraction;
+use crate::vec3::{Color, Vec3};
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+/// Media a path can be inside of at once, beyond which entering more is ignored.
+const MAX_NESTING: usize = 8;
+
+/// Beer-Lambert absorption by the material filling the inside of an object.
+#[derive(Clone, Copy)]
+pub struct Absorption {
+    coefficient: Color,
+}
+
+impl Absorption {
+    pub fn none() -> Absorption {
+        Absorption {coefficient: Color::new(0.0, 0.0, 0.0)}
+    }
+
+    /// Absorption that leaves `transmittance` of the light, per channel, after it has traveled `distance`
+    /// through the medium.
+    pub fn from_transmittance(transmittance: Color, distance: f64) -> Absorption {
+        let coefficient = |t: f64| -t.clamp(1e-6, 1.0).ln() / distance;
+        Absorption {coefficient: Color::new(coefficient(transmittance.r()), coefficient(transmittance.g()), coefficient(transmittance.b()))}
+    }
+
+    pub fn transmittance(&self, distance: f64) -> Color {
+        beer_lambert(&self.coefficient, distance)
+    }
+
+    /// Attenuation of `r_in` on its way to `rec`, which is only absorbed when it traveled through the
+    /// inside of the object. Integrators that track the media paths are in absorb light themselves.
+    pub fn attenuation(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        if rec.front_face || rec.outside_ior.is_some() {
+            Color::new(1.0, 1.0, 1.0)
+        } else {
+            self.transmittance((rec.p - r_in.origin).length())
+        }
+    }
+}
+
+/// What fills the inside of an object. Where objects overlap, the one with the highest `priority` fills the
+/// overlap, so that water can be modeled reaching into the walls of the glass it's in, and ice floating in
+/// it left as a whole object.
+#[derive(Clone, Copy)]
+pub struct Interior {
+    pub ior: IndexOfRefraction,
+    pub absorption: Absorption,
+    pub priority: i32,
+}
+
+/// Objects a path is inside of, in the order it entered them (Schmidt and Budge, "Simple Nested Dielectrics
+/// in Ray Traced Images"). Objects are told apart by their material, so the surfaces that make up one
+/// object, like the inside and outside of a glass, share it.
+#[derive(Clone, Copy, Default)]
+pub struct MediumStack {
+    entries: [Option<(usize, Interior)>; MAX_NESTING],
+    len: usize,
+}
+
+impl MediumStack {
+    fn id(rec: &HitRecord) -> usize {
+        Rc::as_ptr(&rec.material) as *const u8 as usize
+    }
+
+    /// Object filling the space the path is in: the one with the highest priority, and of those the one
+    /// entered last.
+    fn current_entry(&self) -> Option<&(usize, Interior)> {
+        self.entries[..self.len].iter().flatten().fold(None, |best: Option<&(usize, Interior)>, entry| match best {
+            Some(best) if best.1.priority > entry.1.priority => Some(best),
+            _ => Some(entry),
+        })
+    }
+
+    pub fn current(&self) -> Option<&Interior> {
+        self.current_entry().map(|(_, interior)| interior)
+    }
+
+    /// Whether the surface at `rec` separates two media, rather than lying inside an object that takes
+    /// precedence over its own. Surfaces that don't fill their inside always do.
+    pub fn is_interface(&self, rec: &HitRecord) -> bool {
+        let interior = match rec.material.interior() {
+            Some(interior) => interior,
+            None => return true,
+        };
+        let current = self.current_entry();
+        if rec.front_face {
+            current.is_none_or(|(_, current)| interior.priority >= current.priority)
+        } else {
+            // Leaving an object the path wasn't known to be in, like one the camera is in, is taken as real
+            let id = MediumStack::id(rec);
+            current.is_none_or(|(current, _)| *current == id) || !self.entries[..self.len].iter().flatten().any(|(entry, _)| *entry == id)
+        }
+    }
+
+    /// Media on the other side of the surface at `rec`.
+    pub fn across(&self, rec: &HitRecord) -> MediumStack {
+        let interior = match rec.material.interior() {
+            Some(interior) => interior,
+            None => return *self,
+        };
+        let id = MediumStack::id(rec);
+        let mut media = *self;
+        if rec.front_face {
+            if media.len < MAX_NESTING {
+                media.entries[media.len] = Some((id, interior));
+                media.len += 1;
+            }
+        } else if let Some(index) = media.entries[..media.len].iter().rposition(|entry| matches!(entry, Some((entry, _)) if *entry == id)) {
+            media.entries.copy_within(index + 1..media.len, index);
+            media.len -= 1;
+            media.entries[media.len] = None;
+        }
+        media
+    }
+
+    /// Index of refraction on the outside of the object whose surface is at `rec`, at `lambda` nanometers:
+    /// of what the path is in when it's entering the object, and of what it goes into when leaving it.
+    pub fn outside_ior(&self, rec: &HitRecord, lambda: Option<f64>) -> f64 {
+        let outside = if rec.front_face { self.current().copied() } else { self.across(rec).current().copied() };
+        outside.map_or(1.0, |interior| interior.ior.at(lambda))
+    }
+
+    /// Fraction of light, per channel, left after `distance` through the medium the path is in.
+    pub fn transmittance(&self, distance: f64) -> Color {
+        self.current().map_or(Color::new(1.0, 1.0, 1.0), |interior| interior.absorption.transmittance(distance))
+    }
+}
+
+/// Fraction of light, per channel, left after `distance` through a medium with extinction `coefficient`.
+fn beer_lambert(coefficient: &Color, distance: f64) -> Color {
+    Color::new((-coefficient.r() * distance).exp(), (-coefficient.g() * distance).exp(), (-coefficient.b() * distance).exp())
+}
+
+fn mean(c: &Color) -> f64 {
+    (c.r() + c.g() + c.b()) / 3.0
+}
+
+/// What happened to a ray on its way through a medium.
+pub enum MediumEvent {
+    /// It scattered off a particle before getting anywhere.
+    Scattered(Scatter),
+    /// It made it to the end of the segment, weighted by this throughput.
+    Reached(Color),
+}
+
+/// Medium with the same absorption and scattering coefficients everywhere, whose particles scatter
+/// light according to a Henyey-Greenstein phase function.
+pub struct HomogeneousMedium {
+    absorption: Color,
+    scattering: Color,
+    anisotropy: f64,
+}
+
+impl HomogeneousMedium {
+    /// `anisotropy` is the mean cosine of the scattering angle: positive values scatter forwards, negative
+    /// ones back and zero evenly in all directions.
+    pub fn new(absorption: Color, scattering: Color, anisotropy: f64) -> HomogeneousMedium {
+        HomogeneousMedium {absorption, scattering, anisotropy: anisotropy.clamp(-0.99, 0.99)}
+    }
+
+    /// Follows `r_in` through the medium up to `rec`. The free-flight distance is sampled for a channel
+    /// picked at random and weighted by the pdf averaged over all three, so colored media stay unbiased.
+    pub fn interact(&self, r_in: &Ray, rec: &HitRecord) -> MediumEvent {
+        let extinction = self.absorption + self.scattering;
+        let distance = (rec.p - r_in.origin).length();
+        let channel = extinction.e()[((random_double(0.0, 1.0) * 3.0) as usize).min(2)];
+        let t = if channel > 0.0 { -(1.0 - random_double(0.0, 1.0)).ln() / channel } else { f64::INFINITY };
+
+        if t < distance {
+            let transmittance = beer_lambert(&extinction, t);
+            let pdf = mean(&(extinction * transmittance));
+            if pdf <= 0.0 {
+                return MediumEvent::Reached(Color::new(0.0, 0.0, 0.0))
+            }
+            let direction = r_in.direction.unit();
+            let scatter = Ray::new(r_in.origin + direction * t, self.sample_phase(&direction), r_in.time);
+            MediumEvent::Scattered(Scatter::new(self.scattering * transmittance / pdf, scatter, 0.0, Lobe::Volume))
+        } else {
+            let transmittance = beer_lambert(&extinction, distance);
+            let pdf = mean(&transmittance);
+            if pdf <= 0.0 {
+                return MediumEvent::Reached(Color::new(0.0, 0.0, 0.0))
+            }
+            MediumEvent::Reached(transmittance / pdf)
+        }
+    }
+
+    /// New direction for light that was traveling along `direction`, drawn from the phase function.
+    fn sample_phase(&self, direction: &Vec3) -> Vec3 {
+        let g = self.anisotropy;
+        let u = random_double(0.0, 1.0);
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_double(0.0, 1.0);
+        Onb::from_w(direction).local(&Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+    }
+}
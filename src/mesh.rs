@@ -0,0 +1,289 @@
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::{HitRecord, Hittable, Ray};
+use crate::vec3::{Point3, Vec3};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::rc::Rc;
+
+/// Corner of a triangle, as indices into the positions, texture coordinates and normals of its mesh.
+#[derive(Clone, Copy)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// Triangle mesh with texture coordinates, as loaded from a Wavefront OBJ file.
+pub struct Mesh {
+    positions: Vec<Point3>,
+    uvs: Vec<(f64, f64)>,
+    normals: Vec<Vec3>,
+    triangles: Vec<[Corner; 3]>,
+    material: Rc<dyn Material>,
+}
+
+impl Mesh {
+    /// Loads the vertices, texture coordinates, normals and faces of an OBJ file, splitting polygons into
+    /// fans of triangles. Everything else in the file is ignored.
+    pub fn load_obj(path: &str, material: Rc<dyn Material>) -> io::Result<Mesh> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut mesh = Mesh {positions: Vec::new(), uvs: Vec::new(), normals: Vec::new(), triangles: Vec::new(), material};
+        for line in reader.lines() {
+            let line = line?;
+            let mut fields = line.split_whitespace();
+            let keyword = fields.next();
+            let fields: Vec<&str> = fields.collect();
+            let numbers = |count: usize| -> io::Result<Vec<f64>> {
+                if fields.len() < count {
+                    return Err(invalid("too few numbers"))
+                }
+                fields[..count].iter().map(|field| field.parse().map_err(|_| invalid("expected a number"))).collect()
+            };
+            match keyword {
+                Some("v") => {
+                    let v = numbers(3)?;
+                    mesh.positions.push(Point3::new(v[0], v[1], v[2]));
+                },
+                Some("vt") => {
+                    let vt = numbers(2)?;
+                    mesh.uvs.push((vt[0], vt[1]));
+                },
+                Some("vn") => {
+                    let vn = numbers(3)?;
+                    mesh.normals.push(Vec3::new(vn[0], vn[1], vn[2]).unit());
+                },
+                Some("f") => {
+                    let corners = fields.iter().map(|corner| mesh.corner(corner)).collect::<io::Result<Vec<Corner>>>()?;
+                    if corners.len() < 3 {
+                        return Err(invalid("faces need at least three corners"))
+                    }
+                    for i in 1..corners.len() - 1 {
+                        mesh.triangles.push([corners[0], corners[i], corners[i + 1]]);
+                    }
+                },
+                _ => {},
+            }
+        }
+        Ok(mesh)
+    }
+
+    /// Parses a face corner like `1`, `1/2`, `1//3` or `1/2/3`, whose indices count from one, or back
+    /// from the last element read when negative.
+    fn corner(&self, corner: &str) -> io::Result<Corner> {
+        let mut indices = corner.split('/');
+        let index = |field: Option<&str>, count: usize| -> io::Result<Option<usize>> {
+            match field {
+                None | Some("") => Ok(None),
+                Some(field) => {
+                    let index: i64 = field.parse().map_err(|_| invalid("expected an index"))?;
+                    let index = if index < 0 { count as i64 + index } else { index - 1 };
+                    if index < 0 || index >= count as i64 {
+                        return Err(invalid("index out of range"))
+                    }
+                    Ok(Some(index as usize))
+                },
+            }
+        };
+        let position = index(indices.next(), self.positions.len())?.ok_or_else(|| invalid("missing vertex index"))?;
+        let uv = index(indices.next(), self.uvs.len())?;
+        let normal = index(indices.next(), self.normals.len())?;
+        Ok(Corner {position, uv, normal})
+    }
+
+    /// The mesh's triangles, to add to the world one by one so the BVH can split them up.
+    pub fn triangles(mesh: &Rc<Mesh>) -> Vec<Rc<dyn Hittable>> {
+        (0..mesh.triangles.len()).map(|index| -> Rc<dyn Hittable> { Rc::new(Triangle {mesh: mesh.clone(), index}) }).collect()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    /// Texture coordinates of the corners of a triangle, if it has them.
+    pub fn uvs(&self, triangle: usize) -> Option<[(f64, f64); 3]> {
+        let [a, b, c] = self.triangles[triangle];
+        Some([self.uvs[a.uv?], self.uvs[b.uv?], self.uvs[c.uv?]])
+    }
+
+    /// Point on a triangle at barycentric coordinates `b1` and `b2` of its second and third corner, and
+    /// the shading normal there, facing the side the corners wind counterclockwise around.
+    pub fn surface(&self, triangle: usize, b1: f64, b2: f64) -> (Point3, Vec3) {
+        let [a, b, c] = self.triangles[triangle];
+        let [pa, pb, pc] = [self.positions[a.position], self.positions[b.position], self.positions[c.position]];
+        let p = pa * (1.0 - b1 - b2) + pb * b1 + pc * b2;
+        let geometric = (pb - pa).cross(&(pc - pa)).unit();
+        (p, self.shading_normal(triangle, b1, b2).unwrap_or(geometric))
+    }
+
+    fn shading_normal(&self, triangle: usize, b1: f64, b2: f64) -> Option<Vec3> {
+        let [a, b, c] = self.triangles[triangle];
+        let normal = self.normals[a.normal?] * (1.0 - b1 - b2) + self.normals[b.normal?] * b1 + self.normals[c.normal?] * b2;
+        if normal.near_zero() { None } else { Some(normal.unit()) }
+    }
+
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        let first = *self.positions.first()?;
+        let (min, max) = self.positions.iter().fold((first, first), |(min, max), p| (
+            Point3::new(min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z())),
+            Point3::new(max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z())),
+        ));
+        Some(Aabb::new(min, max))
+    }
+}
+
+/// One triangle of a mesh. Its front face is the side its corners wind counterclockwise around.
+pub struct Triangle {
+    mesh: Rc<Mesh>,
+    index: usize,
+}
+
+impl Triangle {
+    fn corners(&self) -> [Point3; 3] {
+        let [a, b, c] = self.mesh.triangles[self.index];
+        [self.mesh.positions[a.position], self.mesh.positions[b.position], self.mesh.positions[c.position]]
+    }
+}
+
+impl Hittable for Triangle {
+    /// Möller and Trumbore's intersection, which finds the barycentric coordinates along the way.
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let [a, b, c] = self.corners();
+        let (e1, e2) = (b - a, c - a);
+        let pvec = ray.direction.cross(&e2);
+        let det = e1.dot(&pvec);
+        if det.abs() < 1e-12 {
+            return None
+        }
+        let tvec = ray.origin - a;
+        let b1 = tvec.dot(&pvec) / det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None
+        }
+        let qvec = tvec.cross(&e1);
+        let b2 = ray.direction.dot(&qvec) / det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None
+        }
+        let t = e2.dot(&qvec) / det;
+        if t < t_min || t_max < t {
+            return None
+        }
+
+        let outward_normal = e1.cross(&e2).unit();
+        let front_face = ray.direction.dot(&outward_normal) < 0.0;
+        let facing = |n: Vec3| if front_face { n } else { -n };
+        let shading = self.mesh.shading_normal(self.index, b1, b2).unwrap_or(outward_normal);
+
+        // Partial derivatives along the texture coordinates, or along the edges where there are none
+        let (u, v, dpdu, dpdv) = match self.mesh.uvs(self.index) {
+            Some([uva, uvb, uvc]) => {
+                let (du1, dv1, du2, dv2) = (uvb.0 - uva.0, uvb.1 - uva.1, uvc.0 - uva.0, uvc.1 - uva.1);
+                let det = du1 * dv2 - dv1 * du2;
+                let u = uva.0 * (1.0 - b1 - b2) + uvb.0 * b1 + uvc.0 * b2;
+                let v = uva.1 * (1.0 - b1 - b2) + uvb.1 * b1 + uvc.1 * b2;
+                if det.abs() < 1e-12 {
+                    (u, v, e1, e2)
+                } else {
+                    (u, v, (e1 * dv2 - e2 * dv1) / det, (e2 * du1 - e1 * du2) / det)
+                }
+            },
+            None => (b1, b2, e1, e2),
+        };
+        Some(HitRecord {p: ray.at(t), normal: facing(shading), geometric_normal: facing(outward_normal), dpdu, dpdv, t, u, v,
+                        front_face, material: self.mesh.material.clone(), outside_ior: None, object: None, light: None})
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        // Padded so triangles lying in an axis plane don't get a flat box
+        let corners = self.corners();
+        let padding = Vec3::new(1e-4, 1e-4, 1e-4);
+        let min = corners.iter().fold(corners[0], |m, c| Vec3::new(m.x().min(c.x()), m.y().min(c.y()), m.z().min(c.z())));
+        let max = corners.iter().fold(corners[0], |m, c| Vec3::new(m.x().max(c.x()), m.y().max(c.y()), m.z().max(c.z())));
+        Some(Aabb::new(min - padding, max + padding))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+    use crate::vec3::Color;
+    use std::fs;
+
+    fn material() -> Rc<dyn Material> {
+        Rc::new(Lambertian::new(Box::new(SolidColor::new(Color::new(0.5, 0.5, 0.5)))))
+    }
+
+    /// Loads `obj` through a file of its own, named after the test so tests running at once don't collide.
+    fn load(name: &str, obj: &str) -> io::Result<Mesh> {
+        let path = std::env::temp_dir().join(format!("mesh-{0}-{1}.obj", name, std::process::id()));
+        fs::write(&path, obj)?;
+        let mesh = Mesh::load_obj(path.to_str().unwrap(), material());
+        fs::remove_file(&path)?;
+        mesh
+    }
+
+    fn sizes(positions: usize, uvs: usize, normals: usize) -> Mesh {
+        Mesh {
+            positions: vec![Point3::new(0.0, 0.0, 0.0); positions],
+            uvs: vec![(0.0, 0.0); uvs],
+            normals: vec![Vec3::new(0.0, 0.0, 1.0); normals],
+            triangles: Vec::new(),
+            material: material(),
+        }
+    }
+
+    fn indices(corner: Corner) -> (usize, Option<usize>, Option<usize>) {
+        (corner.position, corner.uv, corner.normal)
+    }
+
+    #[test]
+    fn parses_every_form_of_corner() {
+        let mesh = sizes(4, 3, 2);
+        assert_eq!(indices(mesh.corner("2").unwrap()), (1, None, None));
+        assert_eq!(indices(mesh.corner("2/3").unwrap()), (1, Some(2), None));
+        assert_eq!(indices(mesh.corner("2//1").unwrap()), (1, None, Some(0)));
+        assert_eq!(indices(mesh.corner("4/1/2").unwrap()), (3, Some(0), Some(1)));
+    }
+
+    #[test]
+    fn counts_negative_indices_back_from_the_end() {
+        let mesh = sizes(4, 3, 2);
+        assert_eq!(indices(mesh.corner("-1/-3/-2").unwrap()), (3, Some(0), Some(0)));
+        assert_eq!(indices(mesh.corner("-4").unwrap()), (0, None, None));
+    }
+
+    #[test]
+    fn rejects_bad_corners() {
+        let mesh = sizes(4, 3, 2);
+        for corner in ["0", "5", "-5", "1/4", "1//3", "/1", "x", "1/x"] {
+            assert!(mesh.corner(corner).is_err(), "{0}", corner);
+        }
+    }
+
+    #[test]
+    fn splits_polygons_into_fans() {
+        let obj = "# a unit square\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                   o square\nf 1/1 2/2 3/3 4/4\nf -4/-4 -2/-2 -1/-1\n";
+        let mesh = load("fans", obj).unwrap();
+        assert_eq!(mesh.triangle_count(), 3);
+        assert_eq!(mesh.uvs(0), Some([(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]));
+        assert_eq!(mesh.uvs(1), Some([(0.0, 0.0), (1.0, 1.0), (0.0, 1.0)]));
+        assert_eq!(mesh.uvs(2), mesh.uvs(1));
+        let (p, normal) = mesh.surface(0, 0.5, 0.25);
+        assert!((p - Point3::new(0.75, 0.25, 0.0)).length() < 1e-12);
+        assert!((normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn rejects_faces_with_too_few_corners() {
+        assert!(load("degenerate", "v 0 0 0\nv 1 0 0\nf 1 2\n").is_err());
+        assert!(load("short", "v 0 0\n").is_err());
+    }
+}
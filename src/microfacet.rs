@@ -0,0 +1,209 @@
+use crate::fresnel::{fresnel_dielectric, refract};
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+// Roughest and smoothest alphas the distribution is evaluated with.
+const MIN_ALPHA: f64 = 1e-3;
+const MAX_ALPHA: f64 = 1.0;
+// Resolution of the directional albedo table, over cos(theta) and alpha.
+const ALBEDO_TABLE_SIZE: usize = 32;
+const ALBEDO_TABLE_SAMPLES: usize = 16;
+
+/// Trowbridge-Reitz (GGX) distribution of microfacet normals, with separate roughnesses along the x and
+/// y axes of the local shading frame. All directions are in that frame, with the normal along +z.
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> TrowbridgeReitz {
+        TrowbridgeReitz {alpha_x: alpha_x.clamp(MIN_ALPHA, MAX_ALPHA), alpha_y: alpha_y.clamp(MIN_ALPHA, MAX_ALPHA)}
+    }
+
+    /// Maps a perceptual roughness in [0, 1] to the alpha of the distribution.
+    pub fn roughness_to_alpha(roughness: f64) -> f64 {
+        roughness * roughness
+    }
+
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        if wm.z() <= 0.0 {
+            return 0.0
+        }
+        let x = wm.x() / self.alpha_x;
+        let y = wm.y() / self.alpha_y;
+        let t = x * x + y * y + wm.z() * wm.z();
+        1.0 / (PI * self.alpha_x * self.alpha_y * t * t)
+    }
+
+    pub fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 == 0.0 {
+            return f64::INFINITY
+        }
+        let tan2_alpha2 = (self.alpha_x * self.alpha_x * w.x() * w.x() + self.alpha_y * self.alpha_y * w.y() * w.y()) / cos2;
+        ((1.0 + tan2_alpha2).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Height-correlated masking-shadowing.
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of microfacet normals visible from `wo`.
+    pub fn visible_d(&self, wo: &Vec3, wm: &Vec3) -> f64 {
+        let cos_o = wo.z().abs();
+        if cos_o == 0.0 {
+            return 0.0
+        }
+        self.g1(wo) * wo.dot(wm).abs() * self.d(wm) / cos_o
+    }
+
+    /// Reflectance, without Fresnel, and cosine-weighted sampling density of a reflection from `wo`
+    /// into `wi`, both above the surface.
+    pub fn reflection(&self, wo: &Vec3, wi: &Vec3) -> (f64, f64) {
+        let wm = *wo + *wi;
+        if wo.z() <= 0.0 || wi.z() <= 0.0 || wm.near_zero() {
+            return (0.0, 0.0)
+        }
+        let wm = wm.unit();
+        let f = self.d(&wm) * self.g(wo, wi) / (4.0 * wo.z() * wi.z());
+        let pdf = self.visible_d(wo, &wm) / (4.0 * wo.dot(&wm).abs());
+        (f, pdf)
+    }
+
+    /// Samples a microfacet normal visible from `wo` (Heitz 2018).
+    pub fn sample_visible_normal(&self, wo: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        let flip = wo.z() < 0.0;
+        let wo = if flip { -*wo } else { *wo };
+        let vh = Vec3::new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).unit();
+        let len2 = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if len2 > 0.0 { Vec3::new(-vh.y(), vh.x(), 0.0) / len2.sqrt() } else { Vec3::new(1.0, 0.0, 0.0) };
+        let t2 = vh.cross(&t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        Vec3::new(self.alpha_x * nh.x(), self.alpha_y * nh.y(), nh.z().max(1e-6)).unit()
+    }
+
+    /// Fraction of light reflected from `wo` by a perfectly reflective surface with this distribution,
+    /// counting only a single bounce between microfacets. Anisotropic surfaces use the mean roughness.
+    pub fn directional_albedo(&self, wo: &Vec3) -> f64 {
+        let table = ALBEDO_TABLE.get_or_init(albedo_table);
+        let alpha = (self.alpha_x * self.alpha_y).sqrt();
+        let n = ALBEDO_TABLE_SIZE as f64;
+        let x = (wo.z().abs().clamp(0.0, 1.0) * (n - 1.0)).min(n - 1.0);
+        let y = ((alpha - MIN_ALPHA) / (MAX_ALPHA - MIN_ALPHA) * (n - 1.0)).clamp(0.0, n - 1.0);
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(ALBEDO_TABLE_SIZE - 1), (y0 + 1).min(ALBEDO_TABLE_SIZE - 1));
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+        let at = |i: usize, j: usize| table[j * ALBEDO_TABLE_SIZE + i];
+        (at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx) * (1.0 - fy) + (at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx) * fy
+    }
+
+    /// Factor that restores the energy lost to light bouncing more than once between microfacets, to be
+    /// applied on top of the single scattering reflectance (Turquin 2019). `f0` is the reflectance at
+    /// normal incidence.
+    pub fn multiple_scattering_compensation(&self, wo: &Vec3, f0: f64) -> f64 {
+        let albedo = self.directional_albedo(wo).max(1e-3);
+        1.0 + f0 * (1.0 - albedo) / albedo
+    }
+}
+
+static ALBEDO_TABLE: OnceLock<Vec<f64>> = OnceLock::new();
+
+fn albedo_table() -> Vec<f64> {
+    let n = ALBEDO_TABLE_SIZE;
+    let mut table = vec![0.0; n * n];
+    for j in 0..n {
+        let alpha = MIN_ALPHA + (MAX_ALPHA - MIN_ALPHA) * j as f64 / (n - 1) as f64;
+        let distribution = TrowbridgeReitz::new(alpha, alpha);
+        for i in 0..n {
+            let cos_theta = (i as f64 / (n - 1) as f64).max(1e-3);
+            let wo = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, cos_theta);
+            let mut sum = 0.0;
+            for a in 0..ALBEDO_TABLE_SAMPLES {
+                for b in 0..ALBEDO_TABLE_SAMPLES {
+                    let u1 = (a as f64 + 0.5) / ALBEDO_TABLE_SAMPLES as f64;
+                    let u2 = (b as f64 + 0.5) / ALBEDO_TABLE_SAMPLES as f64;
+                    let wm = distribution.sample_visible_normal(&wo, u1, u2);
+                    let wi = reflect(&wo, &wm);
+                    if wi.z() > 0.0 {
+                        sum += distribution.g(&wo, &wi) / distribution.g1(&wo);
+                    }
+                }
+            }
+            table[j * n + i] = sum / (ALBEDO_TABLE_SAMPLES * ALBEDO_TABLE_SAMPLES) as f64;
+        }
+    }
+    table
+}
+
+/// Mirror of `wo` around `n`, with both pointing away from the surface.
+pub fn reflect(wo: &Vec3, n: &Vec3) -> Vec3 {
+    -*wo + *n * (2.0 * wo.dot(n))
+}
+
+/// Rough dielectric interface with relative index of refraction `eta` (inside over outside), seen from
+/// `wo` above the surface. Directions below the surface are transmitted through it.
+pub struct RoughInterface<'a> {
+    pub distribution: &'a TrowbridgeReitz,
+    pub eta: f64,
+}
+
+impl<'a> RoughInterface<'a> {
+    /// Returns the BSDF value and the density with which `sample` picks `wi`. Transmission only
+    /// scales by `transmittance`.
+    pub fn evaluate(&self, wo: &Vec3, wi: &Vec3) -> (f64, f64) {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return (0.0, 0.0)
+        }
+        let reflect = wi.z() > 0.0;
+        let etap = if reflect { 1.0 } else { self.eta };
+        let wm = *wi * etap + *wo;
+        if wm.near_zero() {
+            return (0.0, 0.0)
+        }
+        let wm = wm.unit();
+        let wm = if wm.z() < 0.0 { -wm } else { wm };
+        // Microfacets facing away from either direction can't take part
+        if wm.dot(wi) * wi.z() < 0.0 || wm.dot(wo) * wo.z() < 0.0 {
+            return (0.0, 0.0)
+        }
+        let r = fresnel_dielectric(wo.dot(&wm), self.eta);
+        let t = 1.0 - r;
+        let d = self.distribution.d(&wm);
+        let g = self.distribution.g(wo, wi);
+        if reflect {
+            let f = d * g * r / (4.0 * wo.z() * wi.z()).abs();
+            let pdf = self.distribution.visible_d(wo, &wm) / (4.0 * wo.dot(&wm).abs()) * r;
+            (f, pdf)
+        } else {
+            let denom = (wi.dot(&wm) + wo.dot(&wm) / etap).powi(2);
+            let f = t * d * g * (wi.dot(&wm) * wo.dot(&wm) / (wi.z() * wo.z() * denom)).abs();
+            let pdf = self.distribution.visible_d(wo, &wm) * wi.dot(&wm).abs() / denom * t;
+            (f, pdf)
+        }
+    }
+
+    /// Picks a visible microfacet and then reflection or refraction off it according to its Fresnel
+    /// reflectance.
+    pub fn sample(&self, wo: &Vec3, u1: f64, u2: f64, u3: f64) -> Option<Vec3> {
+        let wm = self.distribution.sample_visible_normal(wo, u1, u2);
+        let r = fresnel_dielectric(wo.dot(&wm), self.eta);
+        if u3 < r {
+            Some(reflect(wo, &wm)).filter(|wi| wi.z() > 0.0)
+        } else {
+            refract(wo, &wm, self.eta).filter(|wi| wi.z() < 0.0)
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use crate::integrator::{Integrator, Scene};
+use crate::options::DepthLimits;
+use crate::path_tracer::PathTracer;
+use crate::ray::Ray;
+use crate::sampler::{self, PrimarySamples, SplitMix64};
+use crate::vec3::Color;
+use std::cell::RefCell;
+
+/// Standard deviation of small steps, as a share of the unit interval.
+const SIGMA: f64 = 0.01;
+/// Odds of a mutation being a large step, which replaces the whole path.
+const LARGE_STEP_PROBABILITY: f64 = 0.3;
+
+/// Path a chain is at: the light it carries and where it reaches the film.
+#[derive(Clone, Copy)]
+struct Sample {
+    color: Color,
+    s: f64,
+    t: f64,
+}
+
+impl Sample {
+    /// What the chain's distribution of paths follows.
+    fn importance(&self) -> f64 {
+        self.color.luminance().max(0.0)
+    }
+}
+
+struct Chain {
+    samples: PrimarySamples,
+    current: Sample,
+}
+
+/// Chains and the brightness of the image, found by the bootstrap.
+struct State {
+    chains: Vec<Chain>,
+    /// Average importance of paths over the image.
+    normalization: f64,
+}
+
+/// Primary sample space Metropolis light transport (Kelemen et al.). The path tracer is handed random
+/// numbers of our choosing, and Markov chains of these numbers wander over the paths they lead to, visiting
+/// each about as often as the light it carries. Paths are mutated by large steps, which start over, and
+/// small steps, which nudge every number a little so that paths found to carry light are explored around.
+///
+/// Chains only give the relative brightness of pixels, so a bootstrap of independent paths first estimates
+/// the brightness of the whole image. The chains start from bootstrap paths picked by the light they carry,
+/// and are seeded from the bootstrap seeds, so a run is repeated exactly by giving it the same seed.
+///
+/// Every pass mutates the chains as many times as there are pixels, and splats both the current and the
+/// proposed path, weighted by the odds of accepting the proposal.
+pub struct Mlt {
+    path_tracer: PathTracer,
+    bootstrap: u32,
+    chains: u32,
+    seed: u64,
+    state: RefCell<Option<State>>,
+}
+
+impl Mlt {
+    pub fn new(limits: DepthLimits, bootstrap: u32, chains: u32, seed: u64) -> Mlt {
+        Mlt {path_tracer: PathTracer::new(limits), bootstrap: bootstrap.max(1), chains: chains.max(1), seed, state: RefCell::new(None)}
+    }
+
+    /// Seed of the `index`th bootstrap path, and of the chain started from it.
+    fn bootstrap_seed(&self, index: u32) -> u64 {
+        SplitMix64::new(self.seed ^ ((index as u64) << 32)).next_u64()
+    }
+
+    /// Traces the path `samples` lead to, the first two numbers picking where on the film it starts.
+    fn trace(&self, scene: &Scene, samples: &mut PrimarySamples) -> Sample {
+        sampler::replay(samples, || {
+            let (s, t) = (crate::random_double(0.0, 1.0), crate::random_double(0.0, 1.0));
+            let ray: Ray = scene.camera.get_ray(s, t);
+            let color = self.path_tracer.ray_color(ray, scene);
+            let color = if color.e().iter().all(|c| c.is_finite()) { color } else { Color::new(0.0, 0.0, 0.0) };
+            Sample {color, s, t}
+        })
+    }
+
+    fn start(&self, scene: &Scene) -> State {
+        let new_samples = |index| PrimarySamples::new(self.bootstrap_seed(index), SIGMA, LARGE_STEP_PROBABILITY);
+        let mut cdf = Vec::with_capacity(self.bootstrap as usize);
+        let mut total = 0.0;
+        for index in 0..self.bootstrap {
+            total += self.trace(scene, &mut new_samples(index)).importance();
+            cdf.push(total);
+        }
+
+        let mut rng = SplitMix64::new(self.seed);
+        let chains = if total > 0.0 {
+            (0..self.chains).map(|_| {
+                let target = rng.uniform() * total;
+                let index = cdf.partition_point(|&c| c <= target).min(cdf.len() - 1) as u32;
+                // Replays the bootstrap path, as its numbers come from the same seed
+                let mut samples = new_samples(index);
+                let current = self.trace(scene, &mut samples);
+                Chain {samples, current}
+            }).collect()
+        } else {
+            Vec::new()
+        };
+        State {chains, normalization: total / self.bootstrap as f64}
+    }
+
+    fn mutate(&self, scene: &Scene, chain: &mut Chain, normalization: f64) {
+        chain.samples.start_iteration();
+        let proposed = self.trace(scene, &mut chain.samples);
+        let current = chain.current;
+        let accept = if current.importance() > 0.0 {
+            (proposed.importance() / current.importance()).min(1.0)
+        } else {
+            1.0
+        };
+
+        if accept > 0.0 && proposed.importance() > 0.0 {
+            scene.film.splat(proposed.s, proposed.t, proposed.color * (accept * normalization / proposed.importance()));
+        }
+        if accept < 1.0 && current.importance() > 0.0 {
+            scene.film.splat(current.s, current.t, current.color * ((1.0 - accept) * normalization / current.importance()));
+        }
+
+        if chain.samples.random() < accept {
+            chain.current = proposed;
+            chain.samples.accept();
+        } else {
+            chain.samples.reject();
+        }
+    }
+}
+
+impl Integrator for Mlt {
+    /// Light only reaches the film through splats.
+    fn ray_color(&self, _ray: Ray, _scene: &Scene) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn begin_pass(&self, scene: &Scene) {
+        let mut state = self.state.borrow_mut();
+        let State {chains, normalization} = state.get_or_insert_with(|| self.start(scene));
+        if chains.is_empty() {
+            return
+        }
+        let mutations = scene.film.pixel_count();
+        let count = chains.len();
+        for (index, chain) in chains.iter_mut().enumerate() {
+            for _ in index * mutations / count..(index + 1) * mutations / count {
+                self.mutate(scene, chain, *normalization);
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::material::{Material, Scatter, TransportMode};
+use crate::medium::Interior;
+use crate::ray::{HitRecord, Ray};
+use crate::texture::Texture;
+use crate::vec3::{Color, Vec3};
+use std::rc::Rc;
+
+// Step in texture space used to take finite differences of the height texture.
+const BUMP_DELTA: f64 = 0.0005;
+
+/// Perturbs the shading normal of `base` with a tangent-space normal map, where the red, green and blue
+/// channels of `normals` map to the dp/du tangent, the bitangent and the surface normal.
+pub struct NormalMap {
+    base: Rc<dyn Material>,
+    normals: Box<dyn Texture>,
+}
+
+impl NormalMap {
+    pub fn new(base: Rc<dyn Material>, normals: Box<dyn Texture>) -> NormalMap {
+        NormalMap {base, normals}
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Option<Vec3> {
+        let n = rec.normal;
+        let tangent = rec.dpdu - n * rec.dpdu.dot(&n);
+        if tangent.near_zero() {
+            return None
+        }
+        let tangent = tangent.unit();
+        let bitangent = n.cross(&tangent);
+
+        let c = self.normals.value(rec.u, rec.v, &rec.p);
+        Some(tangent * (2.0 * c.x() - 1.0) + bitangent * (2.0 * c.y() - 1.0) + n * (2.0 * c.z() - 1.0))
+    }
+}
+
+impl Material for NormalMap {
+    fn sample(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        sample_with_normal(self.base.as_ref(), r_in, rec, self.shading_normal(rec))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3, mode: TransportMode) -> Color {
+        eval_with_normal(self.base.as_ref(), r_in, rec, self.shading_normal(rec), direction, mode)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        pdf_with_normal(self.base.as_ref(), r_in, rec, self.shading_normal(rec), direction)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        self.base.emitted(r_in, rec)
+    }
+
+    fn emission(&self) -> Color {
+        self.base.emission()
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.base.is_spectral()
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        self.base.interior()
+    }
+}
+
+/// Displaces the surface of `base` along its normal by the luminance of `height` times `scale`, and
+/// shades with the normal of the displaced surface. The geometry itself is left untouched.
+pub struct BumpMap {
+    base: Rc<dyn Material>,
+    height: Box<dyn Texture>,
+    scale: f64,
+}
+
+impl BumpMap {
+    pub fn new(base: Rc<dyn Material>, height: Box<dyn Texture>, scale: f64) -> BumpMap {
+        BumpMap {base, height, scale}
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Option<Vec3> {
+        let outward_normal = if rec.front_face { rec.normal } else { -rec.normal };
+        let height = |u: f64, v: f64, p: &Vec3| self.height.value(u, v, p).luminance() * self.scale;
+
+        let d = height(rec.u, rec.v, &rec.p);
+        let d_u = height(rec.u + BUMP_DELTA, rec.v, &(rec.p + rec.dpdu * BUMP_DELTA));
+        let d_v = height(rec.u, rec.v + BUMP_DELTA, &(rec.p + rec.dpdv * BUMP_DELTA));
+
+        let dpdu = rec.dpdu + outward_normal * ((d_u - d) / BUMP_DELTA);
+        let dpdv = rec.dpdv + outward_normal * ((d_v - d) / BUMP_DELTA);
+        let bumped = dpdu.cross(&dpdv);
+        if bumped.near_zero() {
+            return None
+        }
+        Some(if bumped.dot(&rec.normal) < 0.0 { -bumped } else { bumped })
+    }
+}
+
+impl Material for BumpMap {
+    fn sample(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        sample_with_normal(self.base.as_ref(), r_in, rec, self.shading_normal(rec))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3, mode: TransportMode) -> Color {
+        eval_with_normal(self.base.as_ref(), r_in, rec, self.shading_normal(rec), direction, mode)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        pdf_with_normal(self.base.as_ref(), r_in, rec, self.shading_normal(rec), direction)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        self.base.emitted(r_in, rec)
+    }
+
+    fn emission(&self) -> Color {
+        self.base.emission()
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.base.is_spectral()
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        self.base.interior()
+    }
+}
+
+/// Copy of `rec` shaded with `shading_normal`, or `None` when the perturbed normal points away from the
+/// side of the surface that was hit and can't be used.
+fn shaded_record(rec: &HitRecord, shading_normal: Option<Vec3>) -> Option<HitRecord> {
+    let shading_normal = shading_normal?.unit();
+    if shading_normal.dot(&rec.geometric_normal) <= 0.0 {
+        return None
+    }
+    let mut shaded = rec.clone();
+    shaded.normal = shading_normal;
+    Some(shaded)
+}
+
+/// Directions on different sides of the shading and geometric surfaces would let light leak through
+/// the real surface, so they are absorbed.
+fn leaks(shaded: &HitRecord, direction: &Vec3) -> bool {
+    direction.dot(&shaded.geometric_normal) * direction.dot(&shaded.normal) <= 0.0
+}
+
+fn sample_with_normal(base: &dyn Material, r_in: &Ray, rec: &HitRecord, shading_normal: Option<Vec3>) -> Option<Scatter> {
+    match shaded_record(rec, shading_normal) {
+        Some(shaded) => base.sample(r_in, &shaded).filter(|s| !leaks(&shaded, &s.scatter.direction)),
+        None => base.sample(r_in, rec),
+    }
+}
+
+fn pdf_with_normal(base: &dyn Material, r_in: &Ray, rec: &HitRecord, shading_normal: Option<Vec3>, direction: &Vec3) -> f64 {
+    match shaded_record(rec, shading_normal) {
+        Some(shaded) if leaks(&shaded, direction) => 0.0,
+        Some(shaded) => base.pdf(r_in, &shaded, direction),
+        None => base.pdf(r_in, rec, direction),
+    }
+}
+
+/// Importance doesn't follow the cosines with a perturbed normal the way radiance does, so it's
+/// corrected for the change from the normal of `rec` to the one it's shaded with (Veach 1997, 5.3).
+fn eval_with_normal(base: &dyn Material, r_in: &Ray, rec: &HitRecord, shading_normal: Option<Vec3>, direction: &Vec3, mode: TransportMode) -> Color {
+    match shaded_record(rec, shading_normal) {
+        Some(shaded) if leaks(&shaded, direction) => Color::new(0.0, 0.0, 0.0),
+        Some(shaded) if mode == TransportMode::Importance => {
+            let wo = -r_in.direction.unit();
+            let wi = direction.unit();
+            let denominator = wo.dot(&rec.normal).abs() * wi.dot(&shaded.normal).abs();
+            if denominator == 0.0 {
+                return Color::new(0.0, 0.0, 0.0)
+            }
+            base.eval(r_in, &shaded, direction, mode) * (wo.dot(&shaded.normal).abs() * wi.dot(&rec.normal).abs() / denominator)
+        },
+        Some(shaded) => base.eval(r_in, &shaded, direction, mode),
+        None => base.eval(r_in, rec, direction, mode),
+    }
+}
@@ -0,0 +1,38 @@
+use crate::vec3::Vec3;
+
+/// Orthonormal basis with `w` as its z axis, for moving directions between world and local space.
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    /// Basis around the unit vector `w` with an arbitrary orientation of the other two axes.
+    pub fn from_w(w: &Vec3) -> Onb {
+        let a = if w.x().abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let v = w.cross(&a).unit();
+        let u = w.cross(&v);
+        Onb {u, v, w: *w}
+    }
+
+    /// Basis around the unit vector `w` with `u` following `tangent` as closely as possible.
+    pub fn from_w_tangent(w: &Vec3, tangent: &Vec3) -> Onb {
+        let u = *tangent - *w * tangent.dot(w);
+        if u.near_zero() {
+            return Onb::from_w(w)
+        }
+        let u = u.unit();
+        Onb {u, v: w.cross(&u), w: *w}
+    }
+
+    /// World space direction of the local coordinates `a`.
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        self.u * a.x() + self.v * a.y() + self.w * a.z()
+    }
+
+    /// Local coordinates of the world space direction `a`.
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+}
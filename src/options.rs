@@ -0,0 +1,307 @@
+use std::env;
+use std::fs;
+
+/// Settings taken from the command line, and from the scene files it names.
+pub struct Options {
+    pub scene: String,
+    pub environment: Option<String>,
+    pub environment_rotation: f64,
+    pub environment_intensity: f64,
+    pub sky: bool,
+    pub sun_elevation: f64,
+    pub sun_azimuth: f64,
+    pub turbidity: f64,
+    pub ground_albedo: f64,
+    pub sky_intensity: f64,
+    pub date: Option<(i32, u32, u32)>,
+    pub time: f64,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub utc_offset: f64,
+    pub spectral: bool,
+    /// File with the measured index of refraction of a metal, which the first of the metal spheres is
+    /// made of instead of gold.
+    pub metal_ior: Option<String>,
+    pub background: Option<(f64, f64, f64)>,
+    pub depth: DepthLimits,
+    pub integrator: String,
+    /// Whether the path tracer learns where light comes from and guides paths towards it.
+    pub guiding: bool,
+    /// How far ambient occlusion looks for occluders, by default a fraction of the distance to what the
+    /// camera looks at.
+    pub ao_distance: Option<f64>,
+    /// Paths traced from the lights per pass of photon mapping.
+    pub photons: u32,
+    /// Initial gather radius of photon mapping, by default a fraction of the distance to what the camera
+    /// looks at.
+    pub photon_radius: Option<f64>,
+    /// Independent paths Metropolis light transport estimates the brightness of the image from.
+    pub bootstrap: u32,
+    /// Markov chains of Metropolis light transport.
+    pub chains: u32,
+    /// Seed of Metropolis light transport, which renders the same image for the same seed.
+    pub seed: u32,
+    /// OBJ file of a mesh added to the scene.
+    pub mesh: Option<String>,
+    /// What to bake onto the texture coordinates of the mesh, instead of rendering the image.
+    pub bake: Option<String>,
+    /// Width and height of baked lightmaps, in texels.
+    pub lightmap_size: u32,
+    /// Texels lightmaps are grown by past the edges of their UV charts.
+    pub dilation: u32,
+    /// EXR file the render passes compositors work with are written to, besides the image.
+    pub aovs: Option<String>,
+}
+
+/// Names of the scenes `--scene` picks from.
+pub const SCENES: [&str; 16] = ["random", "two-spheres", "bumpy-spheres", "metals", "principled", "glasses", "dispersion", "iridescence",
+                                "coatings", "translucent", "cutouts", "rough-diffuse", "cornell-box", "stage", "city-at-night", "nested-media"];
+
+/// Names of the integrators `--integrator` picks from.
+pub const INTEGRATORS: [&str; 12] = ["path", "bdpt", "sppm", "mlt", "whitted", "ao", "normals", "uv", "depth", "barycentrics", "material-id", "bvh-visits"];
+
+/// Integrators that follow the wavelengths of rays, the only ones `--spectral` works with.
+pub const SPECTRAL_INTEGRATORS: [&str; 2] = ["path", "whitted"];
+
+/// Names of what `--bake` bakes.
+pub const BAKE_MODES: [&str; 4] = ["irradiance", "ao", "bent-normals", "radiance"];
+
+/// How long paths get. Past `min_depth` bounces, paths are ended at random with odds following how
+/// little light they still carry, and each kind of bounce has a limit of its own.
+#[derive(Clone, Copy)]
+pub struct DepthLimits {
+    pub min_depth: u32,
+    pub diffuse: u32,
+    pub glossy: u32,
+    /// Bounces through a surface, to its other side.
+    pub transmission: u32,
+    /// Scattering events inside media, which subsurface scattering takes many of.
+    pub volume: u32,
+    /// Bounces of any kind, for integrators that join paths from both ends and so can't keep count
+    /// by kind.
+    pub max_depth: u32,
+}
+
+impl Options {
+    pub fn from_args() -> Result<Options, String> {
+        let mut options = Options {
+            scene: "two-spheres".to_string(),
+            environment: None,
+            environment_rotation: 0.0,
+            environment_intensity: 1.0,
+            sky: false,
+            sun_elevation: 45.0,
+            sun_azimuth: 180.0,
+            turbidity: 3.0,
+            ground_albedo: 0.3,
+            sky_intensity: 1.0,
+            date: None,
+            time: 12.0,
+            latitude: 0.0,
+            longitude: 0.0,
+            utc_offset: 0.0,
+            spectral: false,
+            metal_ior: None,
+            background: None,
+            depth: DepthLimits {min_depth: 3, diffuse: 16, glossy: 16, transmission: 32, volume: 512, max_depth: 8},
+            integrator: "path".to_string(),
+            guiding: false,
+            ao_distance: None,
+            photons: 100_000,
+            photon_radius: None,
+            bootstrap: 100_000,
+            chains: 1000,
+            seed: 0,
+            mesh: None,
+            bake: None,
+            lightmap_size: 256,
+            dilation: 4,
+            aovs: None,
+        };
+        // Taken from the back, so scene files can put their settings where they were named
+        let mut args: Vec<String> = env::args().skip(1).collect();
+        args.reverse();
+        while let Some(arg) = args.pop() {
+            if arg == "--scene-file" {
+                let path = args.pop().ok_or(format!("missing value for {}", arg))?;
+                args.extend(read_scene_file(&path)?.into_iter().rev());
+                continue
+            }
+            let mut value = || args.pop().ok_or(format!("missing value for {}", arg));
+            match arg.as_str() {
+                "--scene" => options.scene = value()?,
+                "--env" => options.environment = Some(value()?),
+                "--env-rotation" => options.environment_rotation = parse_number(&value()?)?,
+                "--env-intensity" => options.environment_intensity = parse_number(&value()?)?,
+                "--sky" => options.sky = true,
+                "--sun-elevation" => options.sun_elevation = parse_number(&value()?)?,
+                "--sun-azimuth" => options.sun_azimuth = parse_number(&value()?)?,
+                "--turbidity" => options.turbidity = parse_number(&value()?)?,
+                "--ground-albedo" => options.ground_albedo = parse_number(&value()?)?,
+                "--sky-intensity" => options.sky_intensity = parse_number(&value()?)?,
+                "--date" => options.date = Some(parse_date(&value()?)?),
+                "--time" => options.time = parse_time(&value()?)?,
+                "--latitude" => options.latitude = parse_number(&value()?)?,
+                "--longitude" => options.longitude = parse_number(&value()?)?,
+                "--utc-offset" => options.utc_offset = parse_number(&value()?)?,
+                "--spectral" => options.spectral = true,
+                "--metal-ior" => options.metal_ior = Some(value()?),
+                "--background" => options.background = Some(parse_color(&value()?)?),
+                "--min-depth" => options.depth.min_depth = parse_count(&value()?)?,
+                "--diffuse-depth" => options.depth.diffuse = parse_count(&value()?)?,
+                "--glossy-depth" => options.depth.glossy = parse_count(&value()?)?,
+                "--transmission-depth" => options.depth.transmission = parse_count(&value()?)?,
+                "--volume-depth" => options.depth.volume = parse_count(&value()?)?,
+                "--max-depth" => options.depth.max_depth = parse_count(&value()?)?,
+                "--integrator" => options.integrator = value()?,
+                "--guiding" => options.guiding = true,
+                "--ao-distance" => options.ao_distance = Some(parse_number(&value()?)?),
+                "--photons" => options.photons = parse_count(&value()?)?,
+                "--photon-radius" => options.photon_radius = Some(parse_number(&value()?)?),
+                "--bootstrap" => options.bootstrap = parse_count(&value()?)?,
+                "--chains" => options.chains = parse_count(&value()?)?,
+                "--seed" => options.seed = parse_count(&value()?)?,
+                "--mesh" => options.mesh = Some(value()?),
+                "--bake" => options.bake = Some(value()?),
+                "--lightmap-size" => options.lightmap_size = parse_count(&value()?)?,
+                "--dilation" => options.dilation = parse_count(&value()?)?,
+                "--aovs" => options.aovs = Some(value()?),
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+        if [options.sky, options.environment.is_some(), options.background.is_some()].iter().filter(|&&set| set).count() > 1 {
+            return Err("only one of --sky, --env and --background can be used".to_string())
+        }
+        if !SCENES.contains(&options.scene.as_str()) {
+            return Err(format!("unknown scene {}, expected one of {}", options.scene, SCENES.join(", ")))
+        }
+        if !INTEGRATORS.contains(&options.integrator.as_str()) {
+            return Err(format!("unknown integrator {}, expected one of {}", options.integrator, INTEGRATORS.join(", ")))
+        }
+        if options.spectral && !SPECTRAL_INTEGRATORS.contains(&options.integrator.as_str()) {
+            return Err(format!("--spectral only works with the {} integrators", SPECTRAL_INTEGRATORS.join(" and ")))
+        }
+        if options.guiding && options.integrator != "path" {
+            return Err("--guiding only works with the path integrator".to_string())
+        }
+        if let Some(bake) = &options.bake {
+            if !BAKE_MODES.contains(&bake.as_str()) {
+                return Err(format!("unknown bake mode {}, expected one of {}", bake, BAKE_MODES.join(", ")))
+            }
+            if options.mesh.is_none() {
+                return Err("--bake needs a --mesh to bake onto".to_string())
+            }
+            if options.lightmap_size == 0 {
+                return Err("lightmaps need at least one texel".to_string())
+            }
+        }
+        if !(-90.0..=90.0).contains(&options.latitude) {
+            return Err("latitudes go from -90 to 90 degrees".to_string())
+        }
+        if options.sky && (options.turbidity < 1.7 || options.turbidity > 10.0) {
+            return Err("the sky model only holds for turbidities between 1.7 and 10".to_string())
+        }
+        Ok(options)
+    }
+}
+
+/// Reads a scene file into the command line arguments it stands for. Each line sets an option the way
+/// its flag would, without the dashes, like `integrator = bdpt`, or just `sky` for flags without a
+/// value. Empty lines and lines starting with `#` are skipped.
+fn read_scene_file(path: &str) -> Result<Vec<String>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| format!("could not read scene file {}: {}", path, err))?;
+    let mut args = Vec::new();
+    for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), Some(value.trim())),
+            None => (line, None),
+        };
+        if key == "scene-file" {
+            return Err(format!("scene file {} can't include other scene files", path))
+        }
+        args.push(format!("--{}", key));
+        args.extend(value.map(str::to_string));
+    }
+    Ok(args)
+}
+
+fn parse_number(value: &str) -> Result<f64, String> {
+    value.parse().map_err(|_| format!("expected a number, got {}", value))
+}
+
+fn parse_count(value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("expected a whole number, got {}", value))
+}
+
+/// Parses an R,G,B color.
+fn parse_color(value: &str) -> Result<(f64, f64, f64), String> {
+    let error = || format!("expected a color like 0.1,0.2,0.3, got {}", value);
+    let fields: Vec<&str> = value.split(',').collect();
+    if fields.len() != 3 {
+        return Err(error())
+    }
+    let channel = |field: &str| field.trim().parse::<f64>().map_err(|_| error());
+    Ok((channel(fields[0])?, channel(fields[1])?, channel(fields[2])?))
+}
+
+/// Parses a YYYY-MM-DD date.
+fn parse_date(value: &str) -> Result<(i32, u32, u32), String> {
+    let error = || format!("expected a date like 2021-06-21, got {}", value);
+    let fields: Vec<&str> = value.split('-').collect();
+    if fields.len() != 3 {
+        return Err(error())
+    }
+    let year: i32 = fields[0].parse().map_err(|_| error())?;
+    let month: u32 = fields[1].parse().map_err(|_| error())?;
+    let day: u32 = fields[2].parse().map_err(|_| error())?;
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return Err(error())
+    }
+    Ok((year, month, day))
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parses an HH:MM clock time into hours.
+fn parse_time(value: &str) -> Result<f64, String> {
+    let error = || format!("expected a time like 14:30, got {}", value);
+    let (hours, minutes) = value.split_once(':').ok_or_else(error)?;
+    let hours: f64 = hours.parse().map_err(|_| error())?;
+    let minutes: f64 = minutes.parse().map_err(|_| error())?;
+    Ok(hours + minutes / 60.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_date("2021-06-21"), Ok((2021, 6, 21)));
+        assert_eq!(parse_date("2024-02-29"), Ok((2024, 2, 29)));
+        assert_eq!(parse_date("2000-02-29"), Ok((2000, 2, 29)));
+        assert_eq!(parse_date("2021-12-31"), Ok((2021, 12, 31)));
+    }
+
+    #[test]
+    fn rejects_days_past_the_end_of_the_month() {
+        for date in ["2023-02-29", "1900-02-29", "2021-04-31", "2021-06-00", "2021-13-01", "2021-00-10", "2021-06", "21 June", "2021-06-21-1"] {
+            assert!(parse_date(date).is_err(), "{} should be rejected", date);
+        }
+    }
+
+    #[test]
+    fn parses_times_into_hours() {
+        assert_eq!(parse_time("14:30"), Ok(14.5));
+        assert_eq!(parse_time("06:15"), Ok(6.25));
+        assert!(parse_time("14").is_err());
+        assert!(parse_time("noon:00").is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use crate::material::{Lobe, Material, Scatter, TransportMode};
+use crate::pdf::{CosinePdf, Pdf};
+use crate::onb::Onb;
+use crate::ray::{HitRecord, Ray};
+use crate::texture::Texture;
+use crate::vec3::{Color, Vec3};
+use std::f64::consts::PI;
+
+// Constants of Fujii's Oren-Nayar and of its average albedo
+const FON_A: f64 = 0.5 - 2.0 / (3.0 * PI);
+const FON_AVERAGE: f64 = 2.0 / 3.0 - 28.0 / (15.0 * PI);
+
+#[derive(Clone, Copy)]
+enum Variant {
+    Qualitative,
+    EnergyPreserving,
+}
+
+/// Rough diffuse surface made of tiny Lambertian V-grooves, which reflects light back towards where it
+/// came from and looks flatter than `Lambertian`: clay, plaster, the Moon. Roughness in [0, 1] is read
+/// from the luminance of its texture; zero is plain Lambertian.
+pub struct OrenNayar {
+    albedo: Box<dyn Texture>,
+    roughness: Box<dyn Texture>,
+    variant: Variant,
+}
+
+impl OrenNayar {
+    /// The classic qualitative model, with roughness as the standard deviation of the groove slopes in
+    /// radians. It ignores interreflections between grooves, so rough surfaces lose some energy.
+    pub fn new(albedo: Box<dyn Texture>, roughness: Box<dyn Texture>) -> OrenNayar {
+        OrenNayar {albedo, roughness, variant: Variant::Qualitative}
+    }
+
+    /// Energy-preserving Oren-Nayar (Portsmouth et al. 2024): Fujii's improved model plus a multiple
+    /// scattering term, so a white surface reflects all light at any roughness.
+    pub fn energy_preserving(albedo: Box<dyn Texture>, roughness: Box<dyn Texture>) -> OrenNayar {
+        OrenNayar {albedo, roughness, variant: Variant::EnergyPreserving}
+    }
+
+    /// BRDF for the local directions `wo` and `wi`, both above the surface.
+    fn evaluate(&self, albedo: &Color, roughness: f64, wo: &Vec3, wi: &Vec3) -> Color {
+        match self.variant {
+            Variant::Qualitative => {
+                let sigma2 = roughness * roughness;
+                let a = 1.0 - sigma2 / (2.0 * (sigma2 + 0.33));
+                let b = 0.45 * sigma2 / (sigma2 + 0.09);
+                let sin_i = (1.0 - wi.z() * wi.z()).max(0.0).sqrt();
+                let sin_o = (1.0 - wo.z() * wo.z()).max(0.0).sqrt();
+                let cos_phi = if sin_i > 1e-4 && sin_o > 1e-4 {
+                    ((wi.x() * wo.x() + wi.y() * wo.y()) / (sin_i * sin_o)).max(0.0)
+                } else {
+                    0.0
+                };
+                let (sin_alpha, tan_beta) = if wi.z() > wo.z() { (sin_o, sin_i / wi.z()) } else { (sin_i, sin_o / wo.z()) };
+                *albedo * ((a + b * cos_phi * sin_alpha * tan_beta) / PI)
+            },
+            Variant::EnergyPreserving => {
+                let s = wi.dot(wo) - wi.z() * wo.z();
+                let s_over_t = if s > 0.0 { s / wi.z().max(wo.z()) } else { s };
+                let a = 1.0 / (1.0 + FON_A * roughness);
+                let single = *albedo * (a * (1.0 + roughness * s_over_t) / PI);
+
+                let average = a * (1.0 + FON_AVERAGE * roughness);
+                let multiple_albedo = |rho: f64| rho * rho * average / (1.0 - rho * (1.0 - average));
+                let albedo_ms = Color::new(multiple_albedo(albedo.r()), multiple_albedo(albedo.g()), multiple_albedo(albedo.b()));
+                let missing = |mu: f64| (1.0 - fon_albedo(mu, roughness)).max(1e-7);
+                single + albedo_ms * (missing(wo.z()) * missing(wi.z()) / (PI * (1.0 - average).max(1e-7)))
+            },
+        }
+    }
+}
+
+/// Directional albedo of Fujii's Oren-Nayar for a white surface, from the fit of Portsmouth et al.
+fn fon_albedo(mu: f64, roughness: f64) -> f64 {
+    let m = 1.0 - mu;
+    let g_over_pi = m * (0.0571085289 + m * (0.491881867 + m * (-0.332181442 + m * 0.0714429953)));
+    (1.0 + roughness * g_over_pi) / (1.0 + FON_A * roughness)
+}
+
+impl Material for OrenNayar {
+    fn sample(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let frame = Onb::from_w(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        if wo.z() <= 0.0 {
+            return None
+        }
+        let albedo = self.albedo.value(rec.u, rec.v, &rec.p);
+        let roughness = self.roughness.value(rec.u, rec.v, &rec.p).luminance().clamp(0.0, 1.0);
+
+        // Cosine weighted, so the BRDF times cosine over the pdf is the BRDF times pi
+        let wi = Vec3::random_cosine_direction();
+        let attenuation = self.evaluate(&albedo, roughness, &wo, &wi) * PI;
+        Some(Scatter::new(attenuation, Ray::new(rec.p, frame.local(&wi), r_in.time), wi.z() / PI, Lobe::Diffuse))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3, _mode: TransportMode) -> Color {
+        let frame = Onb::from_w(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        let wi = frame.to_local(&direction.unit());
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0)
+        }
+        let albedo = self.albedo.value(rec.u, rec.v, &rec.p);
+        let roughness = self.roughness.value(rec.u, rec.v, &rec.p).luminance().clamp(0.0, 1.0);
+        self.evaluate(&albedo, roughness, &wo, &wi) * wi.z()
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        CosinePdf::new(&rec.normal).value(direction)
+    }
+}
@@ -0,0 +1,336 @@
+use crate::aov::LightPaths;
+use crate::guiding::{Guide, GuideVertex};
+use crate::integrator::{Integrator, Scene};
+use crate::light::Light;
+use crate::light_bvh::{LightBvh, LightId, Sampled};
+use crate::material::{Lobe, Scatter, TransportMode};
+use crate::medium::MediumStack;
+use crate::options::DepthLimits;
+use crate::pdf::{BackgroundPdf, HittablePdf, MaterialPdf, MixturePdf, Pdf};
+use crate::random_double;
+use crate::ray::{HitRecord, Hittable, Ray};
+use crate::vec3::{Color, Vec3};
+use std::cell::RefCell;
+
+/// Vertex that picked a ray while also sampling the lights directly, so that light found by the ray gets
+/// its multiple importance sampling weight.
+#[derive(Clone, Copy)]
+struct PreviousVertex {
+    /// Density with which the ray was picked.
+    pdf: f64,
+    /// Shading normal the lights were picked for, if it was on a surface.
+    normal: Option<Vec3>,
+    /// Whether the guide learns from the vertex.
+    learns: bool,
+}
+
+/// Bounces a path took so far, by kind.
+#[derive(Default)]
+struct Bounces {
+    diffuse: u32,
+    glossy: u32,
+    transmission: u32,
+    volume: u32,
+}
+
+impl Bounces {
+    /// Counts a bounce of the given lobe into `direction`, unless it's over its limit. Light going through
+    /// the surface counts as transmission whatever the lobe.
+    fn take(&mut self, lobe: Lobe, direction: &Vec3, rec: &HitRecord, limits: &DepthLimits) -> bool {
+        let (count, limit) = match lobe {
+            Lobe::Volume => (&mut self.volume, limits.volume),
+            _ if direction.dot(&rec.geometric_normal) < 0.0 => (&mut self.transmission, limits.transmission),
+            Lobe::Diffuse => (&mut self.diffuse, limits.diffuse),
+            Lobe::Glossy | Lobe::Specular => (&mut self.glossy, limits.glossy),
+        };
+        *count += 1;
+        *count <= limit
+    }
+}
+
+/// Unidirectional path tracer. Paths are followed through the scene while keeping track of the share of
+/// light their throughput still carries back to the camera, and pick up light at every vertex by sampling
+/// the lights directly. Paths keep track of the objects they're inside of, so that light is refracted and
+/// absorbed by whichever of them fills the space it travels through.
+///
+/// With guiding, the path tracer learns where light comes from as it renders, and continues paths from
+/// surfaces towards it as often as it follows the material.
+pub struct PathTracer {
+    limits: DepthLimits,
+    guiding: bool,
+    guide: RefCell<Option<Guide>>,
+}
+
+impl PathTracer {
+    pub fn new(limits: DepthLimits) -> PathTracer {
+        PathTracer {limits, guiding: false, guide: RefCell::new(None)}
+    }
+
+    pub fn with_guiding(mut self) -> PathTracer {
+        self.guiding = true;
+        self
+    }
+
+    /// Light arriving along `ray`, keeping the vertices `guide` picked directions at, if there's one.
+    fn trace(&self, mut ray: Ray, scene: &Scene, guide: Option<&Guide>, vertices: &mut Vec<GuideVertex>) -> LightPaths {
+        let Scene {world, lights, background, ..} = *scene;
+        let limits = &self.limits;
+        let mut paths = LightPaths::new();
+        // Lobe of the first bounce, which tells diffuse light from specular
+        let mut first_lobe = Lobe::Diffuse;
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut previous: Option<PreviousVertex> = None;
+        let mut bounces = Bounces::default();
+        let mut depth = 0;
+        let mut media = MediumStack::default();
+        loop {
+            let upsample = |c: &Color| ray.wavelengths.map_or(*c, |w| w.upsample(c));
+            let mut rec = match world.hit(&ray, 0.001, f64::MAX) {
+                Some(rec) => rec,
+                None => {
+                    paths.add(depth, first_lobe, throughput * upsample(&background.value(&ray.direction)));
+                    return paths
+                },
+            };
+            throughput = throughput * upsample(&media.transmittance((rec.p - ray.origin).length()));
+            if !media.is_interface(&rec) {
+                // The surface is inside an object that takes precedence, so the path goes on as if it weren't
+                // there. Light past it wasn't sampled directly, as shadow rays stop at it
+                media = media.across(&rec);
+                ray = Ray {origin: rec.p, ..ray};
+                previous = None;
+                continue
+            }
+            rec.outside_ior = Some(media.outside_ior(&rec, ray.wavelengths.map(|w| w.hero())));
+            let mut emitted = upsample(&rec.material.emitted(&ray, &rec));
+            if let Some(previous) = previous {
+                let light_pdf = lights.pdf_value(&ray.origin, previous.normal.as_ref(), &ray.direction, &rec);
+                emitted = emitted * power_heuristic(previous.pdf, light_pdf);
+                // Sampling the lights already finds their light, so the guide only learns what it doesn't
+                if let (true, true, Some(vertex)) = (previous.learns, light_pdf > 0.0, vertices.last_mut()) {
+                    vertex.radiance = vertex.radiance + throughput * emitted;
+                }
+            }
+            paths.add(depth, first_lobe, throughput * emitted);
+
+            let sampled = rec.material.sample(&ray, &rec);
+            // In spectral mode the path keeps its wavelengths unless the material changed them
+            let follow = |scatter: &Scatter| {
+                let mut scattered = scatter.scatter;
+                scattered.wavelengths = scattered.wavelengths.or(ray.wavelengths);
+                let attenuation = match (ray.wavelengths, scattered.wavelengths) {
+                    (Some(w), Some(next)) => at_wavelengths(&ray, &rec, &scatter.attenuation) * w.transition(&next),
+                    _ => scatter.attenuation,
+                };
+                (scattered, attenuation)
+            };
+            let scattered = match &sampled {
+                Some(scatter) if scatter.lobe == Lobe::Specular || scatter.pdf == 0.0 => {
+                    let (scattered, attenuation) = follow(scatter);
+                    if !bounces.take(scatter.lobe, &scattered.direction, &rec, limits) {
+                        return paths
+                    }
+                    throughput = throughput * attenuation;
+                    if depth == 0 {
+                        first_lobe = scatter.lobe;
+                    }
+                    previous = None;
+                    scattered
+                },
+                _ => {
+                    // A sample that failed still tells the vertex isn't specular
+                    let lobe = sampled.as_ref().map_or(Lobe::Diffuse, |scatter| scatter.lobe);
+                    let material_pdf = MaterialPdf::new(rec.material.as_ref(), &ray, &rec);
+                    let background_pdf = BackgroundPdf::new(background);
+                    let guide_pdf = guide.filter(|_| lobe != Lobe::Volume).and_then(|guide| guide.pdf_at(&rec.p));
+                    let background_and_guide = guide_pdf.as_ref().map(|guide_pdf| MixturePdf::new(&background_pdf, guide_pdf));
+                    // Strategies besides the material's: towards the bright parts of the background, and
+                    // where the guide learned light comes from
+                    let other: Option<&dyn Pdf> = match (background.sample().is_some(), &background_and_guide, &guide_pdf) {
+                        (true, Some(both), _) => Some(both),
+                        (true, None, _) => Some(&background_pdf),
+                        (false, _, Some(guide_pdf)) => Some(guide_pdf),
+                        (false, _, None) => None,
+                    };
+                    let mixture = other.map(|other| MixturePdf::new(&material_pdf, other));
+                    let continuation: &dyn Pdf = match &mixture {
+                        Some(mixture) => mixture,
+                        None => &material_pdf,
+                    };
+
+                    let normal = if lobe == Lobe::Volume { None } else { Some(rec.normal) };
+                    if depth == 0 {
+                        first_lobe = lobe;
+                    }
+                    paths.add(depth + 1, first_lobe, throughput * sample_lights(&ray, &rec, world, lights, normal.as_ref(), Some(continuation)));
+
+                    let (scattered, weight) = match (&sampled, other) {
+                        (Some(scatter), None) => follow(scatter),
+                        (_, Some(other)) => {
+                            // Continue in the material's direction or the other strategies' with equal odds,
+                            // and weigh by the density of the combined strategy. The material's sample is
+                            // the one drawn above, so that its failures count as the paths it ends
+                            let direction = if random_double(0.0, 1.0) < 0.5 {
+                                sampled.as_ref().map(|scatter| scatter.scatter.direction)
+                            } else {
+                                other.generate()
+                            };
+                            let direction = match direction {
+                                Some(direction) => direction,
+                                None => return paths,
+                            };
+                            let pdf = continuation.value(&direction);
+                            let f = rec.material.eval(&ray, &rec, &direction, TransportMode::Radiance);
+                            if pdf == 0.0 || f.near_zero() {
+                                return paths
+                            }
+                            let mut scattered = sampled.as_ref().map_or(Ray::new(rec.p, direction, ray.time), |scatter| scatter.scatter);
+                            scattered.direction = direction;
+                            scattered.wavelengths = scattered.wavelengths.or(ray.wavelengths);
+                            (scattered, at_wavelengths(&ray, &rec, &f) / pdf)
+                        },
+                        // The material's sample failed, and there's no other way to continue
+                        (None, None) => return paths,
+                    };
+                    if !bounces.take(lobe, &scattered.direction, &rec, limits) {
+                        return paths
+                    }
+                    throughput = throughput * weight;
+                    let pdf = continuation.value(&scattered.direction);
+                    let learns = guide.is_some() && lobe != Lobe::Volume;
+                    if learns {
+                        vertices.push(GuideVertex {p: rec.p, direction: scattered.direction, pdf, radiance: paths.total(), throughput});
+                    }
+                    previous = Some(PreviousVertex {pdf, normal, learns});
+                    scattered
+                },
+            };
+            if scattered.direction.dot(&rec.geometric_normal) < 0.0 {
+                media = media.across(&rec);
+            }
+            ray = scattered;
+
+            // Paths that carry little light are ended early, and the ones that go on make up for them
+            depth += 1;
+            if depth >= limits.min_depth {
+                let survival = throughput.x().max(throughput.y()).max(throughput.z()).min(0.95);
+                if random_double(0.0, 1.0) >= survival {
+                    return paths
+                }
+                throughput = throughput / survival;
+            }
+        }
+    }
+}
+
+impl Integrator for PathTracer {
+    fn ray_color(&self, ray: Ray, scene: &Scene) -> Color {
+        self.light_paths(ray, scene).map_or(Color::new(0.0, 0.0, 0.0), |paths| paths.total())
+    }
+
+    fn light_paths(&self, ray: Ray, scene: &Scene) -> Option<LightPaths> {
+        let mut vertices = Vec::new();
+        let paths = self.trace(ray, scene, self.guide.borrow().as_ref(), &mut vertices);
+        if let Some(guide) = self.guide.borrow_mut().as_mut() {
+            guide.record(&vertices, &paths.total());
+        }
+        Some(paths)
+    }
+
+    fn begin_pass(&self, scene: &Scene) {
+        if !self.guiding {
+            return
+        }
+        let mut guide = self.guide.borrow_mut();
+        if guide.is_none() {
+            *guide = scene.world.bounding_box(0.0, 1.0).map(Guide::new);
+        }
+        if let Some(guide) = guide.as_mut() {
+            guide.begin_pass();
+        }
+    }
+
+    fn is_spectral(&self) -> bool {
+        true
+    }
+}
+
+/// Light arriving at `rec` straight from the lights that reach everywhere and from one light picked by
+/// its contribution. Light from shapes is weighed against finding it by continuing the path with
+/// `continuation`, if the path goes on.
+pub fn sample_lights(ray: &Ray, rec: &HitRecord, world: &dyn Hittable, lights: &LightBvh, normal: Option<&Vec3>,
+                     continuation: Option<&dyn Pdf>) -> Color {
+    let mut direct = Color::new(0.0, 0.0, 0.0);
+    for light in lights.unbounded() {
+        direct = direct + sample_delta_light(ray, rec, world, light);
+    }
+    match lights.sample(&rec.p, normal) {
+        Some((Sampled::Delta(light), pmf)) => direct + sample_delta_light(ray, rec, world, light) / pmf,
+        Some((Sampled::Shape(id, shape), pmf)) => direct + sample_shape(ray, rec, world, id, shape, pmf, lights, normal, continuation),
+        None => direct,
+    }
+}
+
+/// Light arriving at `rec` straight from a point picked on `shape`, which was picked with probability
+/// `pmf`. The sample only counts
+/// if nothing, not even another light, is in the way, so that its density is that of this light alone.
+#[allow(clippy::too_many_arguments)]
+fn sample_shape(ray: &Ray, rec: &HitRecord, world: &dyn Hittable, id: LightId, shape: &dyn Hittable, pmf: f64, lights: &LightBvh,
+                normal: Option<&Vec3>, continuation: Option<&dyn Pdf>) -> Color {
+    let shape_pdf = HittablePdf::new(shape, rec.p);
+    let direction = match shape_pdf.generate() {
+        Some(direction) => direction,
+        None => return Color::new(0.0, 0.0, 0.0),
+    };
+    let pdf = pmf * shape_pdf.value(&direction);
+    if pdf == 0.0 {
+        return Color::new(0.0, 0.0, 0.0)
+    }
+    let f = rec.material.eval(ray, rec, &direction, TransportMode::Radiance);
+    if f.near_zero() {
+        return Color::new(0.0, 0.0, 0.0)
+    }
+    let shadow = Ray {origin: rec.p, direction, time: ray.time, wavelengths: ray.wavelengths};
+    let hit = match world.hit(&shadow, 0.001, f64::MAX) {
+        Some(hit) => hit,
+        None => return Color::new(0.0, 0.0, 0.0),
+    };
+    match lights.find(&rec.p, normal, &hit) {
+        Some((found, _)) if found == id => {},
+        _ => return Color::new(0.0, 0.0, 0.0),
+    }
+    let light = hit.material.emitted(&shadow, &hit);
+    let weight = power_heuristic(pdf, continuation.map_or(0.0, |c| c.value(&direction))) / pdf;
+    ray.wavelengths.map_or(f * light * weight, |w| at_wavelengths(ray, rec, &f) * w.upsample(&light) * weight)
+}
+
+/// Light arriving at `rec` from a light that can only be sampled.
+fn sample_delta_light(ray: &Ray, rec: &HitRecord, world: &dyn Hittable, light: &dyn Light) -> Color {
+    let sample = match light.sample(&rec.p) {
+        Some(sample) => sample,
+        None => return Color::new(0.0, 0.0, 0.0),
+    };
+    let f = rec.material.eval(ray, rec, &sample.direction, TransportMode::Radiance);
+    if f.near_zero() {
+        return Color::new(0.0, 0.0, 0.0)
+    }
+    let shadow = Ray {origin: rec.p, direction: sample.direction, time: ray.time, wavelengths: ray.wavelengths};
+    if world.hit(&shadow, 0.001, sample.distance * (1.0 - 1e-6)).is_some() {
+        return Color::new(0.0, 0.0, 0.0)
+    }
+    ray.wavelengths.map_or(f * sample.irradiance, |w| at_wavelengths(ray, rec, &f) * w.upsample(&sample.irradiance))
+}
+
+/// Value `f` of the material at `rec` at the wavelengths of `ray`, upsampled unless the material gave it
+/// at those wavelengths already.
+pub fn at_wavelengths(ray: &Ray, rec: &HitRecord, f: &Color) -> Color {
+    match ray.wavelengths {
+        Some(w) if !rec.material.is_spectral() => w.upsample(f),
+        _ => *f,
+    }
+}
+
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 { 0.0 } else { a / (a + b) }
+}
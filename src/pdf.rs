@@ -0,0 +1,129 @@
+use crate::background::Background;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::random_double;
+use crate::ray::{HitRecord, Hittable, Ray};
+use crate::vec3::{Point3, Vec3};
+use std::f64::consts::PI;
+
+/// Distribution of directions that can be sampled and evaluated, for importance sampling.
+pub trait Pdf {
+    /// Density per unit solid angle of `direction`.
+    fn value(&self, direction: &Vec3) -> f64;
+
+    /// Random direction following the distribution, or `None` when it can't produce one.
+    fn generate(&self) -> Option<Vec3>;
+}
+
+/// Directions in the hemisphere around `w`, with a density proportional to their cosine with it.
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(w: &Vec3) -> CosinePdf {
+        CosinePdf {uvw: Onb::from_w(&w.unit())}
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cosine = self.uvw.to_local(&direction.unit()).z();
+        if cosine <= 0.0 { 0.0 } else { cosine / PI }
+    }
+
+    fn generate(&self) -> Option<Vec3> {
+        Some(self.uvw.local(&Vec3::random_cosine_direction()))
+    }
+}
+
+/// Directions from `origin` towards `objects`, as sampled by `Hittable::random`.
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(objects: &'a dyn Hittable, origin: Point3) -> HittablePdf<'a> {
+        HittablePdf {objects, origin}
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.objects.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self) -> Option<Vec3> {
+        Some(self.objects.random(&self.origin))
+    }
+}
+
+/// Directions picked by a material scattering `r_in` at `rec`.
+pub struct MaterialPdf<'a> {
+    material: &'a dyn Material,
+    r_in: &'a Ray,
+    rec: &'a HitRecord,
+}
+
+impl<'a> MaterialPdf<'a> {
+    pub fn new(material: &'a dyn Material, r_in: &'a Ray, rec: &'a HitRecord) -> MaterialPdf<'a> {
+        MaterialPdf {material, r_in, rec}
+    }
+}
+
+impl Pdf for MaterialPdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.material.pdf(self.r_in, self.rec, direction)
+    }
+
+    fn generate(&self) -> Option<Vec3> {
+        self.material.sample(self.r_in, self.rec).map(|s| s.scatter.direction)
+    }
+}
+
+/// Directions towards the bright parts of the background.
+pub struct BackgroundPdf<'a> {
+    background: &'a dyn Background,
+}
+
+impl<'a> BackgroundPdf<'a> {
+    pub fn new(background: &'a dyn Background) -> BackgroundPdf<'a> {
+        BackgroundPdf {background}
+    }
+}
+
+impl Pdf for BackgroundPdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.background.pdf_value(direction)
+    }
+
+    fn generate(&self) -> Option<Vec3> {
+        self.background.sample()
+    }
+}
+
+/// Picks one of two distributions with equal odds.
+pub struct MixturePdf<'a> {
+    p: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> MixturePdf<'a> {
+        MixturePdf {p: [p0, p1]}
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+    }
+
+    fn generate(&self) -> Option<Vec3> {
+        if random_double(0.0, 1.0) < 0.5 {
+            self.p[0].generate()
+        } else {
+            self.p[1].generate()
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::fresnel::fresnel_schlick;
+use crate::material::{Lobe, Material, Scatter, TransportMode};
+use crate::microfacet::{reflect, RoughInterface, TrowbridgeReitz};
+use crate::onb::Onb;
+use crate::random_double;
+use crate::ray::{HitRecord, Ray};
+use crate::texture::{SolidColor, Texture};
+use crate::vec3::{Color, Vec3};
+use std::f64::consts::PI;
+
+/// Inputs of the `Principled` material. All but `base_color` are scalars read from the luminance of
+/// their texture, and all but `ior` are in [0, 1].
+pub struct PrincipledParameters {
+    pub base_color: Box<dyn Texture>,
+    pub metallic: Box<dyn Texture>,
+    pub roughness: Box<dyn Texture>,
+    /// Reflectance of non-metals at normal incidence, where 0.5 maps to 4%.
+    pub specular: Box<dyn Texture>,
+    /// How much the specular reflection of non-metals takes on the hue of the base color.
+    pub specular_tint: Box<dyn Texture>,
+    /// Strength of the white retro-reflective rim seen on cloth.
+    pub sheen: Box<dyn Texture>,
+    /// Strength of a second, white and glossy specular layer.
+    pub clearcoat: Box<dyn Texture>,
+    pub clearcoat_gloss: Box<dyn Texture>,
+    /// Fraction of non-metallic light that's refracted into the surface instead of diffused.
+    pub transmission: Box<dyn Texture>,
+    pub ior: Box<dyn Texture>,
+    /// Stretches highlights along dp/du.
+    pub anisotropic: Box<dyn Texture>,
+}
+
+impl Default for PrincipledParameters {
+    fn default() -> PrincipledParameters {
+        PrincipledParameters {
+            base_color: constant(0.8),
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            sheen: constant(0.0),
+            clearcoat: constant(0.0),
+            clearcoat_gloss: constant(1.0),
+            transmission: constant(0.0),
+            ior: constant(1.5),
+            anisotropic: constant(0.0),
+        }
+    }
+}
+
+fn constant(value: f64) -> Box<dyn Texture> {
+    Box::new(SolidColor::new(Color::new(value, value, value)))
+}
+
+/// Disney-style "principled" material that blends a diffuse base with sheen, metallic and dielectric
+/// specular reflection, rough transmission and a clear coat, all driven by artist friendly parameters.
+///
+/// One lobe is picked per scatter, with odds following its estimated contribution, and the result is
+/// weighted by the combined density of all lobes.
+pub struct Principled {
+    parameters: PrincipledParameters,
+}
+
+impl Principled {
+    pub fn new(parameters: PrincipledParameters) -> Principled {
+        Principled {parameters}
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> Lobes {
+        let p = &self.parameters;
+        let scalar = |t: &dyn Texture| t.value(rec.u, rec.v, &rec.p).luminance();
+        let base = p.base_color.value(rec.u, rec.v, &rec.p);
+        let metallic = scalar(p.metallic.as_ref()).clamp(0.0, 1.0);
+        let roughness = scalar(p.roughness.as_ref()).clamp(0.0, 1.0);
+        let transmission = scalar(p.transmission.as_ref()).clamp(0.0, 1.0);
+        let ior = scalar(p.ior.as_ref()).max(1.0);
+
+        let luminance = base.luminance();
+        let tint = if luminance > 0.0 { base / luminance } else { Color::new(1.0, 1.0, 1.0) };
+        let specular_tint = scalar(p.specular_tint.as_ref()).clamp(0.0, 1.0);
+        let dielectric_f0 = (Color::new(1.0, 1.0, 1.0) * (1.0 - specular_tint) + tint * specular_tint) * (0.08 * scalar(p.specular.as_ref()));
+
+        let aspect = (1.0 - 0.9 * scalar(p.anisotropic.as_ref()).clamp(0.0, 1.0)).sqrt();
+        let alpha = TrowbridgeReitz::roughness_to_alpha(roughness);
+        let clearcoat_alpha = 0.1 + (0.001 - 0.1) * scalar(p.clearcoat_gloss.as_ref()).clamp(0.0, 1.0);
+
+        // From the inside only the dielectric interface is left
+        let (diffuse_weight, glass_weight, clearcoat) = if rec.front_face {
+            ((1.0 - metallic) * (1.0 - transmission), (1.0 - metallic) * transmission, scalar(p.clearcoat.as_ref()).max(0.0))
+        } else {
+            (0.0, 1.0, 0.0)
+        };
+        Lobes {
+            base, metallic, roughness, dielectric_f0, diffuse_weight, glass_weight, clearcoat,
+            sheen: scalar(p.sheen.as_ref()).max(0.0),
+            eta: if rec.front_face { ior } else { 1.0 / ior },
+            specular: TrowbridgeReitz::new(alpha / aspect, alpha * aspect),
+            clearcoat_distribution: TrowbridgeReitz::new(clearcoat_alpha, clearcoat_alpha),
+        }
+    }
+}
+
+impl Material for Principled {
+    fn sample(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let frame = Onb::from_w_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        if wo.z() <= 0.0 {
+            return None
+        }
+        let lobes = self.lobes(rec);
+        let odds = lobes.odds(&wo)?;
+
+        let u = random_double(0.0, 1.0);
+        let (u1, u2) = (random_double(0.0, 1.0), random_double(0.0, 1.0));
+        let lobe = if u < odds[0] { Lobe::Diffuse } else { Lobe::Glossy };
+        let wi = if u < odds[0] {
+            Vec3::random_cosine_direction()
+        } else if u < odds[0] + odds[1] {
+            reflect(&wo, &lobes.specular.sample_visible_normal(&wo, u1, u2))
+        } else if u < odds[0] + odds[1] + odds[2] {
+            reflect(&wo, &lobes.clearcoat_distribution.sample_visible_normal(&wo, u1, u2))
+        } else {
+            lobes.interface().sample(&wo, u1, u2, random_double(0.0, 1.0))?
+        };
+
+        let (f, pdf) = lobes.evaluate(&wo, &wi, &odds);
+        if pdf == 0.0 {
+            return None
+        }
+        Some(Scatter::new(f * (wi.z().abs() / pdf), Ray::new(rec.p, frame.local(&wi), r_in.time), pdf, lobe))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3, _mode: TransportMode) -> Color {
+        let frame = Onb::from_w_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        let wi = frame.to_local(&direction.unit());
+        let lobes = self.lobes(rec);
+        match lobes.odds(&wo) {
+            Some(odds) if wo.z() > 0.0 => lobes.evaluate(&wo, &wi, &odds).0 * wi.z().abs(),
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let frame = Onb::from_w_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        let wi = frame.to_local(&direction.unit());
+        let lobes = self.lobes(rec);
+        match lobes.odds(&wo) {
+            Some(odds) if wo.z() > 0.0 => lobes.evaluate(&wo, &wi, &odds).1,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Parameters of the material evaluated at a hit point.
+struct Lobes {
+    base: Color,
+    metallic: f64,
+    roughness: f64,
+    dielectric_f0: Color,
+    diffuse_weight: f64,
+    glass_weight: f64,
+    sheen: f64,
+    clearcoat: f64,
+    eta: f64,
+    specular: TrowbridgeReitz,
+    clearcoat_distribution: TrowbridgeReitz,
+}
+
+impl Lobes {
+    fn interface(&self) -> RoughInterface<'_> {
+        RoughInterface {distribution: &self.specular, eta: self.eta}
+    }
+
+    /// Reflectance of the opaque specular lobe, blending between dielectric and metallic Fresnel.
+    fn specular_fresnel(&self, cos_theta: f64) -> Color {
+        fresnel_schlick(cos_theta, &self.dielectric_f0) * self.diffuse_weight
+            + fresnel_schlick(cos_theta, &self.base) * self.metallic
+    }
+
+    /// Odds of sampling the diffuse, specular, clearcoat and glass lobes, or `None` if the surface
+    /// doesn't scatter at all.
+    fn odds(&self, wo: &Vec3) -> Option<[f64; 4]> {
+        let odds = [
+            self.diffuse_weight * (self.base.luminance() + 0.25 * self.sheen),
+            self.specular_fresnel(wo.z()).luminance(),
+            0.25 * self.clearcoat * fresnel_schlick(wo.z(), &Color::new(0.04, 0.04, 0.04)).r(),
+            self.glass_weight,
+        ];
+        let total: f64 = odds.iter().sum();
+        if total <= 0.0 {
+            return None
+        }
+        Some([odds[0] / total, odds[1] / total, odds[2] / total, odds[3] / total])
+    }
+
+    /// BSDF value and combined sampling density of scattering from `wo` into `wi`.
+    fn evaluate(&self, wo: &Vec3, wi: &Vec3, odds: &[f64; 4]) -> (Color, f64) {
+        let mut f = Color::new(0.0, 0.0, 0.0);
+        let mut pdf = 0.0;
+
+        if wi.z() > 0.0 {
+            let wh = (*wo + *wi).unit();
+            let cos_d = wi.dot(&wh);
+
+            if self.diffuse_weight > 0.0 {
+                let fl = (1.0 - wi.z()).powi(5);
+                let fv = (1.0 - wo.z()).powi(5);
+                let fd90 = 0.5 + 2.0 * cos_d * cos_d * self.roughness;
+                let retro_reflection = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+                let sheen = self.sheen * (1.0 - cos_d).powi(5);
+                f = f + (self.base * (retro_reflection / PI) + Color::new(sheen, sheen, sheen)) * self.diffuse_weight;
+            }
+            pdf += odds[0] * wi.z() / PI;
+
+            let (specular, specular_pdf) = self.specular.reflection(wo, wi);
+            f = f + self.specular_fresnel(wo.dot(&wh)) * specular;
+            pdf += odds[1] * specular_pdf;
+
+            if self.clearcoat > 0.0 {
+                let (clearcoat, clearcoat_pdf) = self.clearcoat_distribution.reflection(wo, wi);
+                let fresnel = fresnel_schlick(wo.dot(&wh), &Color::new(0.04, 0.04, 0.04));
+                f = f + fresnel * (0.25 * self.clearcoat * clearcoat);
+                pdf += odds[2] * clearcoat_pdf;
+            }
+        }
+
+        if self.glass_weight > 0.0 {
+            let (glass, glass_pdf) = self.interface().evaluate(wo, wi);
+            let tint = if wi.z() < 0.0 { self.base } else { Color::new(1.0, 1.0, 1.0) };
+            f = f + tint * (self.glass_weight * glass);
+            pdf += odds[3] * glass_pdf;
+        }
+        (f, pdf)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::vec3::{Point3, Vec3};
+use crate::ray::{Hittable, Ray, HitRecord};
+use std::rc::Rc;
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::random_double;
+use crate::light_bvh::LightBounds;
+use std::f64::consts::PI;
+
+/// Parallelogram with a corner at `q` and edges `u` and `v`. Texture coordinates run from 0 to 1 along
+/// the edges, and the front face is the side `u × v` points to.
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    d: f64,
+    w: Vec3,
+    area: f64,
+    material: Rc<dyn Material>,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: Rc<dyn Material>) -> Quad {
+        let n = u.cross(&v);
+        let normal = n.unit();
+        let d = normal.dot(&q);
+        let w = n / n.dot(&n);
+        Quad {q, u, v, normal, d, w, area: n.length(), material}
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let denom = self.normal.dot(&ray.direction);
+        if denom.abs() < 1e-8 {
+            return None
+        }
+        let t = (self.d - self.normal.dot(&ray.origin)) / denom;
+        if t < t_min || t_max < t {
+            return None
+        }
+
+        // Coordinates of the hit along the edges
+        let p = ray.at(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None
+        }
+
+        let front_face = denom < 0.0;
+        let normal = if front_face {
+            self.normal
+        }else{
+            -self.normal
+        };
+        Some(HitRecord{p, normal, geometric_normal: normal, dpdu: self.u, dpdv: self.v, t, u: alpha, v: beta, front_face, material: self.material.clone(), outside_ior: None, object: None, light: None})
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        // Padded so quads lying in an axis plane don't get a flat box
+        let corners = [self.q, self.q + self.u, self.q + self.v, self.q + self.u + self.v];
+        let padding = Vec3::new(1e-4, 1e-4, 1e-4);
+        let min = corners.iter().fold(corners[0], |m, c| Vec3::new(m.x().min(c.x()), m.y().min(c.y()), m.z().min(c.z())));
+        let max = corners.iter().fold(corners[0], |m, c| Vec3::new(m.x().max(c.x()), m.y().max(c.y()), m.z().max(c.z())));
+        Some(Aabb::new(min - padding, max + padding))
+    }
+
+    /// Points are sampled uniformly over the area, converted to a density per solid angle.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        match self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f64::INFINITY) {
+            Some(rec) => {
+                let distance_squared = rec.t * rec.t * direction.length_squared();
+                let cosine = (direction.dot(&self.normal) / direction.length()).abs();
+                if cosine == 0.0 { 0.0 } else { distance_squared / (cosine * self.area) }
+            },
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        self.q + self.u * random_double(0.0, 1.0) + self.v * random_double(0.0, 1.0) - *origin
+    }
+
+    /// Emits from the front face only, all around the normal.
+    fn light_bounds(&self) -> Option<LightBounds> {
+        let radiance = self.material.emission().luminance();
+        if radiance <= 0.0 {
+            return None
+        }
+        Some(LightBounds {
+            bounds: self.bounding_box(0.0, 0.0)?,
+            power: PI * radiance * self.area,
+            axis: self.normal,
+            cos_theta_o: 1.0,
+            cos_theta_e: 0.0,
+        })
+    }
+
+    fn sample_surface(&self) -> Option<(HitRecord, f64)> {
+        let (alpha, beta) = (random_double(0.0, 1.0), random_double(0.0, 1.0));
+        let p = self.q + self.u * alpha + self.v * beta;
+        let rec = HitRecord {
+            p, normal: self.normal, geometric_normal: self.normal, dpdu: self.u, dpdv: self.v,
+            t: 0.0, u: alpha, v: beta, front_face: true, material: self.material.clone(), outside_ior: None, object: None, light: None,
+        };
+        Some((rec, 1.0 / self.area))
+    }
+
+    fn surface_pdf(&self, _p: &Point3) -> f64 {
+        1.0 / self.area
+    }
+}
@@ -2,11 +2,16 @@ use crate::vec3::{Point3, Vec3};
 use std::rc::Rc;
 use crate::material::Material;
 use crate::aabb::Aabb;
+use crate::spectrum::Wavelengths;
+use crate::light_bvh::LightBounds;
 
+/// `wavelengths` is only set in spectral mode.
+#[derive(Clone, Copy)]
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
-    pub time: f64
+    pub time: f64,
+    pub wavelengths: Option<Wavelengths>,
 }
 
 impl Ray {
@@ -14,15 +19,54 @@ impl Ray {
         self.origin + (self.direction * t)
     }
     pub fn new(origin: Point3, direction: Vec3, time: f64) -> Ray {
-        Ray{origin, direction, time}
+        Ray{origin, direction, time, wavelengths: None}
     }
 }
 
+/// `normal` is the shading normal that materials scatter around; it may be perturbed by normal or
+/// bump maps. `geometric_normal` is the true surface normal and always faces the incoming ray.
+/// `dpdu` and `dpdv` are the partial derivatives of the surface position along the texture coordinates.
+/// `outside_ior` is the index of refraction on the outside of the object, set by integrators that track
+/// the media paths are in; materials take hits without it to have air outside every object.
+/// `object` is the number of the object that was hit, for objects that were given one, and `light` the
+/// number of the emissive shape that was, among a scene's `Lights`.
+#[derive(Clone)]
 pub struct HitRecord {
-    pub p: Point3, pub normal: Vec3, pub t: f64, pub u: f64, pub v: f64, pub front_face: bool, pub material: Rc<dyn Material>,
+    pub p: Point3, pub normal: Vec3, pub geometric_normal: Vec3, pub dpdu: Vec3, pub dpdv: Vec3,
+    pub t: f64, pub u: f64, pub v: f64, pub front_face: bool, pub material: Rc<dyn Material>, pub outside_ior: Option<f64>,
+    pub object: Option<usize>, pub light: Option<usize>,
 }
 
 pub trait Hittable {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb>;
+
+    /// Density per unit solid angle with which `random` picks `direction` from `origin`. Shapes that
+    /// can't be sampled return zero.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    /// Random direction from `origin` towards the shape.
+    fn random(&self, _origin: &Point3) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    /// Bounds of the light the shape emits, for picking lights by their contribution. Shapes that
+    /// don't emit or can't be sampled return `None`.
+    fn light_bounds(&self) -> Option<LightBounds> {
+        None
+    }
+
+    /// Random point on the surface, as seen from the front, and the density per unit area of having
+    /// picked it. This is where light paths leave emissive shapes. Shapes that can't be sampled return
+    /// `None`.
+    fn sample_surface(&self) -> Option<(HitRecord, f64)> {
+        None
+    }
+
+    /// Density per unit area with which `sample_surface` picks `p`.
+    fn surface_pdf(&self, _p: &Point3) -> f64 {
+        0.0
+    }
 }
\ No newline at end of file
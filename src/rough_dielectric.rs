@@ -0,0 +1,100 @@
+use crate::material::{Lobe, Material, Scatter, TransportMode};
+use crate::medium::{Absorption, Interior};
+use crate::microfacet::{RoughInterface, TrowbridgeReitz};
+use crate::onb::Onb;
+use crate::random_double;
+use crate::spectrum::IndexOfRefraction;
+use crate::ray::{HitRecord, Ray};
+use crate::texture::Texture;
+use crate::vec3::{Color, Vec3};
+
+/// Frosted glass: a dielectric whose surface is a GGX distribution of microfacets that each reflect or
+/// refract according to their exact Fresnel reflectance. Roughness is perceptual and read from the
+/// luminance of its texture.
+pub struct RoughDielectric {
+    ir: f64,
+    roughness: Box<dyn Texture>,
+    absorption: Absorption,
+    priority: i32,
+}
+
+impl RoughDielectric {
+    pub fn new(ir: f64, roughness: Box<dyn Texture>) -> RoughDielectric {
+        RoughDielectric {ir, roughness, absorption: Absorption::none(), priority: 0}
+    }
+
+    /// Tints the inside so that `transmittance` of the light is left after traveling `distance` through it.
+    pub fn with_absorption(mut self, transmittance: Color, distance: f64) -> RoughDielectric {
+        self.absorption = Absorption::from_transmittance(transmittance, distance);
+        self
+    }
+
+    /// Lets the object fill where it overlaps objects of a lower priority; see `Interior`.
+    pub fn with_priority(mut self, priority: i32) -> RoughDielectric {
+        self.priority = priority;
+        self
+    }
+
+    fn distribution(&self, rec: &HitRecord) -> TrowbridgeReitz {
+        let alpha = TrowbridgeReitz::roughness_to_alpha(self.roughness.value(rec.u, rec.v, &rec.p).luminance());
+        TrowbridgeReitz::new(alpha, alpha)
+    }
+
+    /// Ratio of the index of refraction past the surface to the one before it.
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        let outside = rec.outside_ior.unwrap_or(1.0);
+        if rec.front_face { self.ir / outside } else { outside / self.ir }
+    }
+
+    /// Scale of the interface's BSDF between `wi` and the side it was hit from, for `mode`. Radiance
+    /// coming through from the other side changes by the squared ratio of the indices, as it spreads over
+    /// a wider solid angle where the index is lower, which importance doesn't.
+    fn scale(&self, rec: &HitRecord, wi: &Vec3, mode: TransportMode) -> f64 {
+        if wi.z() < 0.0 && mode == TransportMode::Radiance {
+            1.0 / (self.eta(rec) * self.eta(rec))
+        } else {
+            1.0
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn sample(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let frame = Onb::from_w_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        if wo.z() <= 0.0 {
+            return None
+        }
+        let distribution = self.distribution(rec);
+        let interface = RoughInterface {distribution: &distribution, eta: self.eta(rec)};
+
+        let wi = interface.sample(&wo, random_double(0.0, 1.0), random_double(0.0, 1.0), random_double(0.0, 1.0))?;
+        let (f, pdf) = interface.evaluate(&wo, &wi);
+        if pdf == 0.0 {
+            return None
+        }
+        let attenuation = self.absorption.attenuation(r_in, rec) * (f * self.scale(rec, &wi, TransportMode::Radiance) * wi.z().abs() / pdf);
+        Some(Scatter::new(attenuation, Ray::new(rec.p, frame.local(&wi), r_in.time), pdf, Lobe::Glossy))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3, mode: TransportMode) -> Color {
+        let frame = Onb::from_w_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        let wi = frame.to_local(&direction.unit());
+        let distribution = self.distribution(rec);
+        let (f, _) = RoughInterface {distribution: &distribution, eta: self.eta(rec)}.evaluate(&wo, &wi);
+        self.absorption.attenuation(r_in, rec) * (f * self.scale(rec, &wi, mode) * wi.z().abs())
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let frame = Onb::from_w_tangent(&rec.normal, &rec.dpdu);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        let wi = frame.to_local(&direction.unit());
+        let distribution = self.distribution(rec);
+        RoughInterface {distribution: &distribution, eta: self.eta(rec)}.evaluate(&wo, &wi).1
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        Some(Interior {ior: IndexOfRefraction::Constant(self.ir), absorption: self.absorption, priority: self.priority})
+    }
+}
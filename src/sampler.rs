@@ -0,0 +1,150 @@
+use rand::Rng;
+use std::cell::RefCell;
+
+// Rendering takes all its random numbers from `uniform`, so that Metropolis light transport can hand
+// out numbers of its own choosing and replay or perturb the path they lead to.
+
+thread_local! {
+    static STREAM: RefCell<Option<PrimarySamples>> = const { RefCell::new(None) };
+}
+
+/// Uniform random number in [0, 1), from the stream `replay` installed if there is one.
+pub fn uniform() -> f64 {
+    STREAM.with(|stream| match stream.borrow_mut().as_mut() {
+        Some(samples) => samples.next(),
+        None => rand::thread_rng().gen_range(0.0..1.0),
+    })
+}
+
+/// Runs `f` with `uniform` reading from `samples`.
+pub fn replay<R>(samples: &mut PrimarySamples, f: impl FnOnce() -> R) -> R {
+    STREAM.with(|stream| *stream.borrow_mut() = Some(std::mem::take(samples)));
+    let result = f();
+    *samples = STREAM.with(|stream| stream.borrow_mut().take()).unwrap_or_default();
+    result
+}
+
+/// SplitMix64, a small generator whose sequence only depends on its seed, so runs can be repeated.
+#[derive(Clone, Default)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 {state: seed}
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform number in [0, 1).
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Normally distributed number with a mean of zero and a standard deviation of one.
+    fn normal(&mut self) -> f64 {
+        let u = 1.0 - self.uniform();
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * self.uniform()).cos()
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct PrimarySample {
+    value: f64,
+    /// Iteration the value was last changed in.
+    modified: u64,
+    backup: f64,
+    modified_backup: u64,
+}
+
+/// Point in primary sample space: the sequence of uniform numbers a path was made from, grown as the
+/// path asks for more. Each iteration either replaces every number (a large step) or moves each by a
+/// small normally distributed offset (a small step). Numbers are only perturbed when asked for, and
+/// catch up on the small steps they missed, so paths that use few numbers stay cheap. Following
+/// Kelemen et al., and the way pbrt lays it out.
+#[derive(Clone, Default)]
+pub struct PrimarySamples {
+    rng: SplitMix64,
+    /// Standard deviation of small steps.
+    sigma: f64,
+    large_step_probability: f64,
+    samples: Vec<PrimarySample>,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64,
+    index: usize,
+}
+
+impl PrimarySamples {
+    /// Starts out with a large step, so the first path is an independent one.
+    pub fn new(seed: u64, sigma: f64, large_step_probability: f64) -> PrimarySamples {
+        PrimarySamples {rng: SplitMix64::new(seed), sigma, large_step_probability, large_step: true, ..PrimarySamples::default()}
+    }
+
+    /// Moves to the next iteration, proposing a mutation of the current point.
+    pub fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.uniform() < self.large_step_probability;
+        self.index = 0;
+    }
+
+    /// Keeps the proposed point.
+    pub fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    /// Goes back to the point before the proposal.
+    pub fn reject(&mut self) {
+        for sample in &mut self.samples {
+            if sample.modified == self.iteration {
+                sample.value = sample.backup;
+                sample.modified = sample.modified_backup;
+            }
+        }
+        self.iteration -= 1;
+    }
+
+    /// Random number for decisions about the chain itself, which aren't part of the path.
+    pub fn random(&mut self) -> f64 {
+        self.rng.uniform()
+    }
+
+    fn next(&mut self) -> f64 {
+        if self.index == self.samples.len() {
+            // A number the path never asked for before is as good as any uniform one. Starting it off
+            // anywhere else would keep rejection sampling stuck where that start is rejected
+            let value = self.rng.uniform();
+            self.samples.push(PrimarySample {value, modified: self.last_large_step, ..PrimarySample::default()});
+        }
+        let i = self.index;
+        self.index += 1;
+
+        let mut sample = self.samples[i];
+        // Numbers left alone since the last large step would have been replaced by it
+        if sample.modified < self.last_large_step {
+            sample.value = self.rng.uniform();
+            sample.modified = self.last_large_step;
+        }
+        sample.backup = sample.value;
+        sample.modified_backup = sample.modified;
+        if self.large_step {
+            sample.value = self.rng.uniform();
+        } else {
+            let steps = (self.iteration - sample.modified) as f64;
+            sample.value += self.rng.normal() * self.sigma * steps.sqrt();
+            sample.value -= sample.value.floor();
+        }
+        sample.modified = self.iteration;
+        self.samples[i] = sample;
+        sample.value
+    }
+}
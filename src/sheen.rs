@@ -0,0 +1,171 @@
+use crate::material::{Lobe, Material, Scatter, TransportMode};
+use crate::medium::Interior;
+use crate::onb::Onb;
+use crate::random_double;
+use crate::ray::{HitRecord, Ray};
+use crate::texture::Texture;
+use crate::vec3::{Color, Vec3};
+use std::f64::consts::PI;
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+const MIN_ROUGHNESS: f64 = 0.07;
+const ALBEDO_TABLE_SIZE: usize = 32;
+const ALBEDO_TABLE_SAMPLES: usize = 32;
+
+/// Soft sheen of fibers standing up from a surface, like on velvet, satin or peach skin, layered over
+/// any base material. The lobe is the "Charlie" distribution (Estevez and Kulla 2017) with Neubelt and
+/// Pettineo's visibility term, and it's brightest at grazing angles. The base is dimmed by what the
+/// sheen reflects, and one of the two is picked at random on each bounce, in proportion to that.
+pub struct Sheen {
+    base: Rc<dyn Material>,
+    color: Box<dyn Texture>,
+    roughness: Box<dyn Texture>,
+}
+
+impl Sheen {
+    /// Roughness in [0, 1] is read from the luminance of its texture; low values make a tight rim of
+    /// light at the silhouette, high ones a broad haze.
+    pub fn new(base: Rc<dyn Material>, color: Box<dyn Texture>, roughness: Box<dyn Texture>) -> Sheen {
+        Sheen {base, color, roughness}
+    }
+
+    /// Color and roughness of the sheen at `rec`, and the share of the light arriving from `wo` it
+    /// reflects, which the base is dimmed by.
+    fn lobe(&self, rec: &HitRecord, wo: &Vec3) -> (Color, f64, f64) {
+        let color = self.color.value(rec.u, rec.v, &rec.p);
+        let roughness = self.roughness.value(rec.u, rec.v, &rec.p).luminance().clamp(MIN_ROUGHNESS, 1.0);
+        let sheen_albedo = (color.r().max(color.g()).max(color.b()) * directional_albedo(wo.z(), roughness)).clamp(0.0, 1.0);
+        (color, roughness, sheen_albedo)
+    }
+}
+
+/// BRDF of a white sheen lobe for the local directions `wo` and `wi`.
+fn charlie(roughness: f64, wo: &Vec3, wi: &Vec3) -> f64 {
+    if wo.z() <= 0.0 || wi.z() <= 0.0 {
+        return 0.0
+    }
+    let alpha = roughness * roughness;
+    let wm = (*wo + *wi).unit();
+    let sin_m = (1.0 - wm.z() * wm.z()).max(0.0).sqrt();
+    let d = (2.0 + 1.0 / alpha) * sin_m.powf(1.0 / alpha) / (2.0 * PI);
+    let v = 1.0 / (4.0 * (wo.z() + wi.z() - wo.z() * wi.z()));
+    d * v
+}
+
+/// Fraction of light the white sheen lobe reflects for light arriving at `cos_theta`.
+fn directional_albedo(cos_theta: f64, roughness: f64) -> f64 {
+    let table = ALBEDO_TABLE.get_or_init(albedo_table);
+    let n = ALBEDO_TABLE_SIZE as f64;
+    let x = (cos_theta.clamp(0.0, 1.0) * (n - 1.0)).min(n - 1.0);
+    let y = ((roughness - MIN_ROUGHNESS) / (1.0 - MIN_ROUGHNESS) * (n - 1.0)).clamp(0.0, n - 1.0);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(ALBEDO_TABLE_SIZE - 1), (y0 + 1).min(ALBEDO_TABLE_SIZE - 1));
+    let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+    let at = |i: usize, j: usize| table[j * ALBEDO_TABLE_SIZE + i];
+    (at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx) * (1.0 - fy) + (at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx) * fy
+}
+
+static ALBEDO_TABLE: OnceLock<Vec<f64>> = OnceLock::new();
+
+fn albedo_table() -> Vec<f64> {
+    let n = ALBEDO_TABLE_SIZE;
+    let mut table = vec![0.0; n * n];
+    for j in 0..n {
+        let roughness = MIN_ROUGHNESS + (1.0 - MIN_ROUGHNESS) * j as f64 / (n - 1) as f64;
+        for i in 0..n {
+            let cos_theta = (i as f64 / (n - 1) as f64).max(1e-3);
+            let wo = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, cos_theta);
+            let mut sum = 0.0;
+            // Cosine weighted quadrature over the hemisphere
+            for a in 0..ALBEDO_TABLE_SAMPLES {
+                for b in 0..ALBEDO_TABLE_SAMPLES {
+                    let u1 = (a as f64 + 0.5) / ALBEDO_TABLE_SAMPLES as f64;
+                    let u2 = (b as f64 + 0.5) / ALBEDO_TABLE_SAMPLES as f64;
+                    let phi = 2.0 * PI * u1;
+                    let wi = Vec3::new(phi.cos() * u2.sqrt(), phi.sin() * u2.sqrt(), (1.0 - u2).sqrt());
+                    sum += charlie(roughness, &wo, &wi) * PI;
+                }
+            }
+            table[j * n + i] = sum / (ALBEDO_TABLE_SAMPLES * ALBEDO_TABLE_SAMPLES) as f64;
+        }
+    }
+    table
+}
+
+impl Material for Sheen {
+    fn sample(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let frame = Onb::from_w(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        if wo.z() <= 0.0 {
+            return self.base.sample(r_in, rec)
+        }
+        let (_, _, sheen_albedo) = self.lobe(rec, &wo);
+
+        // The sheen gets picked as often as it reflects, and either sample is weighed by the density of
+        // both lobes together
+        let scatter = if random_double(0.0, 1.0) < sheen_albedo {
+            let direction = frame.local(&Vec3::random_cosine_direction());
+            Scatter::new(Color::new(0.0, 0.0, 0.0), Ray::new(rec.p, direction, r_in.time), 0.0, Lobe::Glossy)
+        } else {
+            let scatter = self.base.sample(r_in, rec)?;
+            // Specular bases can't be evaluated, so their samples stand on their own
+            if scatter.pdf == 0.0 {
+                return Some(scatter)
+            }
+            scatter
+        };
+        let pdf = self.pdf(r_in, rec, &scatter.scatter.direction);
+        if pdf == 0.0 {
+            return None
+        }
+        let attenuation = self.eval(r_in, rec, &scatter.scatter.direction, TransportMode::Radiance) / pdf;
+        Some(Scatter {attenuation, pdf, ..scatter})
+    }
+
+    /// The sheen lobe on top of the base, which is dimmed by what the sheen reflects.
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3, mode: TransportMode) -> Color {
+        let base = self.base.eval(r_in, rec, direction, mode);
+        let frame = Onb::from_w(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        let wi = frame.to_local(&direction.unit());
+        if wo.z() <= 0.0 {
+            return base
+        }
+        let (color, roughness, sheen_albedo) = self.lobe(rec, &wo);
+        // Next to a base that gives its values at the path's wavelengths, the color has to be too
+        let color = match r_in.wavelengths {
+            Some(wavelengths) if self.base.is_spectral() => wavelengths.upsample(&color),
+            _ => color,
+        };
+        color * (charlie(roughness, &wo, &wi) * wi.z().max(0.0)) + base * (1.0 - sheen_albedo)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let base = self.base.pdf(r_in, rec, direction);
+        let frame = Onb::from_w(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction.unit());
+        let wi = frame.to_local(&direction.unit());
+        if wo.z() <= 0.0 {
+            return base
+        }
+        let (_, _, sheen_albedo) = self.lobe(rec, &wo);
+        sheen_albedo * wi.z().max(0.0) / PI + (1.0 - sheen_albedo) * base
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        self.base.emitted(r_in, rec)
+    }
+
+    fn emission(&self) -> Color {
+        self.base.emission()
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.base.is_spectral()
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        self.base.interior()
+    }
+}
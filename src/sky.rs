@@ -0,0 +1,249 @@
+use crate::background::Background;
+use crate::onb::Onb;
+use crate::random_double;
+use crate::vec3::{Color, Vec3};
+use std::f64::consts::PI;
+
+// Angular radius of the sun as seen from the earth, in radians.
+const SUN_ANGULAR_RADIUS: f64 = 0.00465;
+// Luminance of the sun outside the atmosphere, in kcd/m^2 like the sky model.
+const SUN_LUMINANCE: f64 = 1.6e6;
+// Brings luminances in kcd/m^2 to the range of the rest of the renderer, where 1 is a bright white.
+const LUMINANCE_SCALE: f64 = 0.05;
+
+/// Preetham et al. analytic daylight model, with a sun disk that can be sampled directly and a uniform
+/// ground below the horizon lit by the sun and sky.
+///
+/// Directions follow the convention that +y is up, -z is north and +x is east. Azimuths are measured in
+/// degrees clockwise from north, elevations in degrees above the horizon.
+pub struct Sky {
+    sun_direction: Vec3,
+    sun_radiance: Color,
+    cos_sun_radius: f64,
+    zenith: [f64; 3],
+    perez: [[f64; 5]; 3],
+    sun_theta: f64,
+    ground: Color,
+    intensity: f64,
+}
+
+impl Sky {
+    pub fn new(sun_elevation: f64, sun_azimuth: f64, turbidity: f64, ground_albedo: Color, intensity: f64) -> Sky {
+        let elevation = sun_elevation.to_radians();
+        let azimuth = sun_azimuth.to_radians();
+        let sun_direction = Vec3::new(azimuth.sin() * elevation.cos(), elevation.sin(), -azimuth.cos() * elevation.cos());
+
+        // The model breaks down below the horizon, where the sky is evaluated as if the sun had just set
+        let sun_theta = (PI / 2.0 - elevation).clamp(0.0, PI / 2.0 - 1e-3);
+        let t = turbidity;
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * sun_theta);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let (th, th2, th3) = (sun_theta, sun_theta * sun_theta, sun_theta * sun_theta * sun_theta);
+        let zenith_x = t * t * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let zenith_y = t * t * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        let sun_radiance = if sun_elevation > 0.0 {
+            sun_transmittance(PI / 2.0 - elevation, turbidity) * (SUN_LUMINANCE * LUMINANCE_SCALE)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        };
+
+        let mut sky = Sky {
+            sun_direction, sun_radiance, cos_sun_radius: SUN_ANGULAR_RADIUS.cos(),
+            zenith: [zenith_luminance.max(0.0), zenith_x, zenith_y], perez, sun_theta,
+            ground: Color::new(0.0, 0.0, 0.0), intensity,
+        };
+        sky.ground = ground_albedo * sky.horizontal_irradiance() / PI;
+        sky
+    }
+
+    /// Radiance of the sky dome alone, without the sun disk, for a direction above the horizon.
+    fn sky_radiance(&self, direction: &Vec3) -> Color {
+        let cos_theta = direction.y().max(1e-4);
+        let gamma = direction.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+        let channel = |i: usize| {
+            self.zenith[i] * perez(&self.perez[i], cos_theta, gamma) / perez(&self.perez[i], 1.0, self.sun_theta)
+        };
+        xyy_to_rgb(channel(1), channel(2), channel(0)) * LUMINANCE_SCALE
+    }
+
+    /// Irradiance on an upward facing surface from the sun and the sky dome.
+    fn horizontal_irradiance(&self) -> Color {
+        let solid_angle = 2.0 * PI * (1.0 - self.cos_sun_radius);
+        let mut irradiance = self.sun_radiance * (solid_angle * self.sun_direction.y().max(0.0));
+
+        let (n_theta, n_phi) = (32, 64);
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) / n_theta as f64 * PI / 2.0;
+            let weight = theta.cos() * theta.sin() * (PI / 2.0 / n_theta as f64) * (2.0 * PI / n_phi as f64);
+            for j in 0..n_phi {
+                let phi = (j as f64 + 0.5) / n_phi as f64 * 2.0 * PI;
+                let direction = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                irradiance = irradiance + self.sky_radiance(&direction) * weight;
+            }
+        }
+        irradiance
+    }
+}
+
+impl Background for Sky {
+    fn value(&self, direction: &Vec3) -> Color {
+        let direction = direction.unit();
+        if direction.y() <= 0.0 {
+            return self.ground * self.intensity
+        }
+        let sky = self.sky_radiance(&direction);
+        if direction.dot(&self.sun_direction) >= self.cos_sun_radius {
+            (sky + self.sun_radiance) * self.intensity
+        } else {
+            sky * self.intensity
+        }
+    }
+
+    fn sample(&self) -> Option<Vec3> {
+        if self.sun_radiance.near_zero() {
+            return None
+        }
+        // Uniform direction in the cone subtended by the sun
+        let cos_theta = 1.0 - random_double(0.0, 1.0) * (1.0 - self.cos_sun_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = random_double(0.0, 2.0 * PI);
+
+        let frame = Onb::from_w(&self.sun_direction);
+        Some(frame.local(&Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)))
+    }
+
+    fn pdf_value(&self, direction: &Vec3) -> f64 {
+        if self.sun_radiance.near_zero() || direction.unit().dot(&self.sun_direction) < self.cos_sun_radius {
+            0.0
+        } else {
+            1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+        }
+    }
+}
+
+/// Perez et al. luminance distribution for a view direction at `cos_theta` from the zenith and `gamma`
+/// radians from the sun.
+fn perez(c: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    (1.0 + c[0] * (c[1] / cos_theta).exp()) * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+}
+
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0)
+    }
+    let cx = x / y * luminance;
+    let cz = (1.0 - x - y) / y * luminance;
+    Color::new(
+        (3.2406 * cx - 1.5372 * luminance - 0.4986 * cz).max(0.0),
+        (-0.9689 * cx + 1.8758 * luminance + 0.0415 * cz).max(0.0),
+        (0.0557 * cx - 0.2040 * luminance + 1.0570 * cz).max(0.0),
+    )
+}
+
+/// Fraction of sunlight that makes it through Rayleigh and aerosol scattering, evaluated at typical
+/// red, green and blue wavelengths, for the sun at `theta` radians from the zenith.
+fn sun_transmittance(theta: f64, turbidity: f64) -> Color {
+    let degrees = theta.to_degrees();
+    let relative_air_mass = 1.0 / (theta.cos() + 0.15 * (93.885 - degrees).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let alpha = 1.3;
+    let channel = |lambda_um: f64| {
+        let rayleigh = (-0.008735 * lambda_um.powf(-4.08) * relative_air_mass).exp();
+        let aerosol = (-beta * lambda_um.powf(-alpha) * relative_air_mass).exp();
+        rayleigh * aerosol
+    };
+    Color::new(channel(0.68), channel(0.55), channel(0.44))
+}
+
+/// Elevation and azimuth of the sun, in degrees, at a clock time in hours on a calendar date, for an
+/// observer at `latitude` and `longitude` degrees (north and east positive) in a timezone `utc_offset`
+/// hours ahead of UTC.
+pub fn solar_position(year: i32, month: u32, day: u32, hours: f64, latitude: f64, longitude: f64, utc_offset: f64) -> (f64, f64) {
+    let n = day_of_year(year, month, day) as f64;
+    let b = (360.0 / 365.0 * (n - 81.0)).to_radians();
+    let equation_of_time = 9.87 * (2.0 * b).sin() - 7.53 * b.cos() - 1.5 * b.sin();
+    let solar_time = hours + (4.0 * (longitude - 15.0 * utc_offset) + equation_of_time) / 60.0;
+
+    let declination = (23.44 * (360.0 / 365.0 * (n + 284.0)).to_radians().sin()).to_radians();
+    let hour_angle = (15.0 * (solar_time - 12.0)).to_radians();
+    let latitude = latitude.to_radians();
+
+    let sin_elevation = latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos();
+    let elevation = sin_elevation.clamp(-1.0, 1.0).asin();
+    // Clockwise from north. Without dividing by the cosines of the elevation and latitude, it holds at
+    // the poles and with the sun overhead too, where any azimuth will do
+    let azimuth = (-hour_angle.sin() * declination.cos())
+        .atan2(latitude.cos() * declination.sin() - latitude.sin() * declination.cos() * hour_angle.cos());
+    (elevation.to_degrees(), azimuth.to_degrees().rem_euclid(360.0))
+}
+
+fn day_of_year(year: i32, month: u32, day: u32) -> u32 {
+    const CUMULATIVE_DAYS: [u32; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let month = month.clamp(1, 12);
+    CUMULATIVE_DAYS[month as usize - 1] + day + if leap && month > 2 { 1 } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_days_of_leap_years() {
+        assert_eq!(day_of_year(2023, 1, 1), 1);
+        assert_eq!(day_of_year(2023, 3, 1), 60);
+        assert_eq!(day_of_year(2024, 3, 1), 61);
+        assert_eq!(day_of_year(2024, 12, 31), 366);
+        assert_eq!(day_of_year(2100, 12, 31), 365);
+    }
+
+    #[test]
+    fn sun_is_overhead_at_the_equator_at_noon_on_the_equinox() {
+        // Solar noon is a few minutes after noon on the clock then
+        let (elevation, _) = solar_position(2021, 3, 20, 12.0, 0.0, 0.0, 0.0);
+        assert!(elevation > 87.0, "elevation {}", elevation);
+    }
+
+    #[test]
+    fn sun_is_due_south_and_highest_at_solar_noon_on_the_solstice() {
+        // London, on summer time
+        let (elevation, azimuth) = solar_position(2021, 6, 21, 13.0, 51.5, -0.13, 1.0);
+        assert!((elevation - (90.0 - 51.5 + 23.44)).abs() < 1.0, "elevation {}", elevation);
+        assert!((azimuth - 180.0).abs() < 5.0, "azimuth {}", azimuth);
+    }
+
+    #[test]
+    fn sun_rises_in_the_east_and_sets_in_the_west() {
+        let (morning_elevation, morning) = solar_position(2021, 6, 21, 7.0, 51.5, -0.13, 1.0);
+        let (evening_elevation, evening) = solar_position(2021, 6, 21, 19.0, 51.5, -0.13, 1.0);
+        assert!(morning_elevation > 0.0 && evening_elevation > 0.0);
+        assert!(morning > 45.0 && morning < 135.0, "morning azimuth {}", morning);
+        assert!(evening > 225.0 && evening < 315.0, "evening azimuth {}", evening);
+        let (night, _) = solar_position(2021, 12, 21, 0.0, 51.5, -0.13, 0.0);
+        assert!(night < -40.0, "midnight elevation {}", night);
+    }
+
+    #[test]
+    fn sun_has_a_direction_at_the_poles_and_overhead() {
+        for latitude in [90.0, -90.0, 23.44] {
+            for hours in [0.0, 6.0, 12.0, 18.0] {
+                let (elevation, azimuth) = solar_position(2021, 6, 21, hours, latitude, 0.0, 0.0);
+                assert!(elevation.is_finite() && azimuth.is_finite(), "latitude {} at {}h", latitude, hours);
+                assert!((0.0..360.0).contains(&azimuth), "azimuth {}", azimuth);
+            }
+        }
+        // Midsummer at the north pole, where the sun circles at the height of its declination
+        let (elevation, _) = solar_position(2021, 6, 21, 3.0, 90.0, 0.0, 0.0);
+        assert!((elevation - 23.44).abs() < 0.5, "elevation {}", elevation);
+    }
+}
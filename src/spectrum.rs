@@ -0,0 +1,260 @@
+use crate::random_double;
+use crate::vec3::Color;
+use std::fs;
+use std::io;
+use std::sync::OnceLock;
+
+/// Wavelengths, in nanometers, at which spectral data is evaluated for the red, green and blue channels.
+pub const RGB_WAVELENGTHS: [f64; 3] = [650.0, 550.0, 450.0];
+
+/// Piecewise linear function of wavelength in nanometers, constant beyond its first and last samples.
+pub struct Spectrum {
+    wavelengths: Vec<f64>,
+    values: Vec<f64>,
+}
+
+impl Spectrum {
+    /// `wavelengths` must be sorted in increasing order.
+    pub fn new(wavelengths: Vec<f64>, values: Vec<f64>) -> Spectrum {
+        Spectrum {wavelengths, values}
+    }
+
+    pub fn evaluate(&self, lambda: f64) -> f64 {
+        let n = self.wavelengths.len();
+        if lambda <= self.wavelengths[0] {
+            return self.values[0]
+        }
+        if lambda >= self.wavelengths[n - 1] {
+            return self.values[n - 1]
+        }
+        let i = self.wavelengths.partition_point(|&w| w <= lambda) - 1;
+        let t = (lambda - self.wavelengths[i]) / (self.wavelengths[i + 1] - self.wavelengths[i]);
+        self.values[i] * (1.0 - t) + self.values[i + 1] * t
+    }
+
+    pub fn to_rgb(&self) -> Color {
+        Color::new(self.evaluate(RGB_WAVELENGTHS[0]), self.evaluate(RGB_WAVELENGTHS[1]), self.evaluate(RGB_WAVELENGTHS[2]))
+    }
+}
+
+/// Loads a measured complex index of refraction as its real part `eta` and extinction coefficient `k`.
+///
+/// Each line holds a wavelength, eta and k separated by whitespace or commas, as exported by
+/// refractiveindex.info. Wavelengths below 10 are taken to be in micrometers, others in nanometers.
+/// Empty lines, lines starting with `#` and lines that don't start with a number are skipped.
+pub fn read_ior(path: &str) -> io::Result<(Spectrum, Spectrum)> {
+    let mut samples = Vec::new();
+    for line in fs::read_to_string(path)?.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+        let fields: Vec<f64> = match line.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|f| !f.is_empty())
+            .map(|f| f.parse::<f64>())
+            .collect() {
+            Ok(fields) => fields,
+            Err(_) => continue,
+        };
+        if fields.len() < 3 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("expected wavelength, eta and k on line '{}'", line)))
+        }
+        if fields.iter().any(|f| !f.is_finite()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("expected finite numbers on line '{}'", line)))
+        }
+        let lambda = if fields[0] < 10.0 { fields[0] * 1000.0 } else { fields[0] };
+        samples.push((lambda, fields[1], fields[2]));
+    }
+    if samples.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no samples in index of refraction file"))
+    }
+    samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let wavelengths: Vec<f64> = samples.iter().map(|s| s.0).collect();
+    let eta = Spectrum::new(wavelengths.clone(), samples.iter().map(|s| s.1).collect());
+    let k = Spectrum::new(wavelengths, samples.iter().map(|s| s.2).collect());
+    Ok((eta, k))
+}
+
+// Range of wavelengths, in nanometers, traced in spectral mode.
+const LAMBDA_MIN: f64 = 380.0;
+const LAMBDA_MAX: f64 = 780.0;
+
+/// Wavelengths, in nanometers, carried by a path in spectral mode. The hero wavelength is sampled
+/// uniformly and the other two are spread evenly around the range from it, so every `Color` along the
+/// path holds the values at these three wavelengths instead of red, green and blue.
+#[derive(Clone, Copy)]
+pub struct Wavelengths {
+    lambda: [f64; 3],
+    secondary_terminated: bool,
+}
+
+impl Wavelengths {
+    pub fn sample() -> Wavelengths {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = random_double(0.0, range);
+        let rotated = |i: f64| LAMBDA_MIN + (hero + i * range / 3.0) % range;
+        Wavelengths {lambda: [rotated(0.0), rotated(1.0), rotated(2.0)], secondary_terminated: false}
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// All three wavelengths, the hero first.
+    pub fn lambdas(&self) -> [f64; 3] {
+        self.lambda
+    }
+
+    /// Follows only the hero wavelength from here on, after an interaction that sends different
+    /// wavelengths in different directions.
+    pub fn terminate_secondary(&self) -> Wavelengths {
+        Wavelengths {lambda: self.lambda, secondary_terminated: true}
+    }
+
+    /// Weights that carry a path from these wavelengths over to `next`: when the secondary wavelengths
+    /// are dropped the hero stands in for all three.
+    pub fn transition(&self, next: &Wavelengths) -> Color {
+        if !self.secondary_terminated && next.secondary_terminated {
+            Color::new(3.0, 0.0, 0.0)
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        }
+    }
+
+    /// Values at these wavelengths of a smooth spectrum with the given RGB color (Smits 1999).
+    pub fn upsample(&self, rgb: &Color) -> Color {
+        let spectrum = |lambda: f64| smits(rgb, lambda);
+        Color::new(spectrum(self.lambda[0]), spectrum(self.lambda[1]), spectrum(self.lambda[2]))
+    }
+
+    /// Linear RGB estimate of the spectral radiance sampled at these wavelengths. A constant spectrum of
+    /// one maps to white.
+    pub fn radiance_to_rgb(&self, radiance: &Color) -> Color {
+        let values = radiance.e();
+        let mut xyz = Color::new(0.0, 0.0, 0.0);
+        for (lambda, value) in self.lambda.iter().zip(values.iter()) {
+            xyz = xyz + cie_xyz(*lambda) * (value * (LAMBDA_MAX - LAMBDA_MIN) / 3.0);
+        }
+        let white = WHITE_RGB.get_or_init(|| {
+            let mut xyz = Color::new(0.0, 0.0, 0.0);
+            for lambda in (LAMBDA_MIN as i32)..(LAMBDA_MAX as i32) {
+                xyz = xyz + cie_xyz(lambda as f64 + 0.5);
+            }
+            xyz_to_rgb(&xyz)
+        });
+        let rgb = xyz_to_rgb(&xyz);
+        Color::new(rgb.r() / white.r(), rgb.g() / white.g(), rgb.b() / white.b())
+    }
+}
+
+static WHITE_RGB: OnceLock<Color> = OnceLock::new();
+
+/// Linear RGB color of a surface whose reflectance varies with wavelength, as seen under white light.
+pub fn reflectance_to_rgb(reflectance: impl Fn(f64) -> f64) -> Color {
+    const SAMPLES: usize = 32;
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / SAMPLES as f64;
+    let mut xyz = Color::new(0.0, 0.0, 0.0);
+    let mut white = Color::new(0.0, 0.0, 0.0);
+    for i in 0..SAMPLES {
+        let lambda = LAMBDA_MIN + (i as f64 + 0.5) * step;
+        let cmf = cie_xyz(lambda);
+        xyz = xyz + cmf * reflectance(lambda);
+        white = white + cmf;
+    }
+    let rgb = xyz_to_rgb(&xyz);
+    let white = xyz_to_rgb(&white);
+    Color::new((rgb.r() / white.r()).max(0.0), (rgb.g() / white.g()).max(0.0), (rgb.b() / white.b()).max(0.0))
+}
+
+/// Analytic fit of the CIE 1931 color matching functions (Wyman, Sloan and Shirley 2013).
+fn cie_xyz(lambda: f64) -> Color {
+    let g = |mu: f64, sigma1: f64, sigma2: f64| {
+        let t = (lambda - mu) / if lambda < mu { sigma1 } else { sigma2 };
+        (-0.5 * t * t).exp()
+    };
+    Color::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+fn xyz_to_rgb(xyz: &Color) -> Color {
+    Color::new(
+        3.2406 * xyz.x() - 1.5372 * xyz.y() - 0.4986 * xyz.z(),
+        -0.9689 * xyz.x() + 1.8758 * xyz.y() + 0.0415 * xyz.z(),
+        0.0557 * xyz.x() - 0.2040 * xyz.y() + 1.0570 * xyz.z(),
+    )
+}
+
+// Smits' basis spectra, sampled at ten evenly spaced wavelengths from 380 to 720 nanometers.
+const SMITS_WHITE: [f64; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f64; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f64; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f64; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f64; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f64; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f64; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+fn smits_basis(basis: &[f64; 10], lambda: f64) -> f64 {
+    let x = ((lambda - 380.0) / (720.0 - 380.0) * 9.0).clamp(0.0, 9.0);
+    let i = (x.floor() as usize).min(8);
+    let t = x - i as f64;
+    basis[i] * (1.0 - t) + basis[i + 1] * t
+}
+
+fn smits(rgb: &Color, lambda: f64) -> f64 {
+    let (r, g, b) = (rgb.r(), rgb.g(), rgb.b());
+    let basis = |spectrum: &[f64; 10]| smits_basis(spectrum, lambda);
+    if r <= g && r <= b {
+        r * basis(&SMITS_WHITE) + if g <= b {
+            (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+        } else {
+            (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+        }
+    } else if g <= r && g <= b {
+        g * basis(&SMITS_WHITE) + if r <= b {
+            (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+        } else {
+            (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+        }
+    } else {
+        b * basis(&SMITS_WHITE) + if r <= g {
+            (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+        } else {
+            (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+        }
+    }
+}
+
+/// Index of refraction of a transparent material, possibly varying with wavelength.
+#[derive(Clone, Copy)]
+pub enum IndexOfRefraction {
+    Constant(f64),
+    /// n = a + b / lambda^2, with lambda in micrometers.
+    Cauchy { a: f64, b: f64 },
+    /// n^2 = 1 + sum(b_i lambda^2 / (lambda^2 - c_i)), with lambda in micrometers.
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl IndexOfRefraction {
+    /// Wavelength of the sodium d-line, where indices of refraction are usually quoted.
+    const D_LINE: f64 = 587.6;
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, IndexOfRefraction::Constant(_))
+    }
+
+    /// Index at `lambda` nanometers, or at the d-line when rendering in RGB.
+    pub fn at(&self, lambda: Option<f64>) -> f64 {
+        let um = lambda.unwrap_or(IndexOfRefraction::D_LINE) / 1000.0;
+        let um2 = um * um;
+        match self {
+            IndexOfRefraction::Constant(n) => *n,
+            IndexOfRefraction::Cauchy {a, b} => a + b / um2,
+            IndexOfRefraction::Sellmeier {b, c} => {
+                (1.0 + (0..3).map(|i| b[i] * um2 / (um2 - c[i])).sum::<f64>()).sqrt()
+            }
+        }
+    }
+}
@@ -3,6 +3,9 @@ use crate::ray::{Hittable, Ray, HitRecord};
 use std::rc::Rc;
 use crate::material::Material;
 use crate::aabb::Aabb;
+use crate::onb::Onb;
+use crate::random_double;
+use crate::light_bvh::LightBounds;
 use std::f64::consts::PI;
 
 pub struct Sphere{
@@ -11,7 +14,7 @@ pub struct Sphere{
 
 impl Hittable for Sphere {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = &ray.origin - &self.center;
+        let oc = ray.origin - self.center;
         let a = ray.direction.dot(&ray.direction);
         let half_b = oc.dot(&ray.direction);
         let c = oc.dot(&oc) - self.radius * self.radius;
@@ -21,29 +24,86 @@ impl Hittable for Sphere {
         }
         let sqrtd = disc.sqrt();
 
-        let root = (-half_b - sqrtd)/a;
+        // Take the far root when the near one is out of range, e.g. for rays leaving the sphere
+        let mut root = (-half_b - sqrtd)/a;
         if root < t_min || t_max < root {
+            root = (-half_b + sqrtd)/a;
+            if root < t_min || t_max < root {
+                return None
+            }
+        }
+        Some(sphere_hit_record(ray, root, &self.center, self.radius, self.material.clone()))
+    }
+
+    // Spheres with a negative radius face inwards, for the inside of hollow objects
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        let r = self.radius.abs();
+        Some(Aabb::new(self.center - Vec3::new(r, r, r), self.center + Vec3::new(r, r, r)))
+    }
+
+    /// Directions are sampled uniformly within the cone the sphere subtends, or over all directions from
+    /// inside it.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f64::INFINITY).is_none() {
+            return 0.0
+        }
+        let distance_squared = (self.center - *origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return 1.0 / (4.0 * PI)
+        }
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let direction = self.center - *origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return Vec3::random_unit_vector()
+        }
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let z = 1.0 + random_double(0.0, 1.0) * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * random_double(0.0, 1.0);
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        Onb::from_w(&direction.unit()).local(&Vec3::new(phi.cos() * r, phi.sin() * r, z))
+    }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        let radiance = self.material.emission().luminance();
+        if radiance <= 0.0 {
             return None
         }
-        let p = ray.at(root);
-        let normal = (&p - &self.center)/self.radius;
-        let front_face = ray.direction.dot(&normal) < 0.0;
-        let normal = if front_face {
-            normal
-        }else{
-            -normal
-        };
-        let (u, v) = get_sphere_uv(&normal);
-        Some(HitRecord{p, normal, t: root, u, v, front_face, material: self.material.clone()})
+        let power = PI * radiance * 4.0 * PI * self.radius * self.radius;
+        Some(LightBounds::omnidirectional(self.bounding_box(0.0, 0.0)?, power))
+    }
 
+    fn sample_surface(&self) -> Option<(HitRecord, f64)> {
+        // Hit from just outside, straight on
+        let normal = Vec3::random_unit_vector();
+        let ray = Ray::new(self.center + normal * (self.radius + 1.0), -normal, 0.0);
+        let rec = sphere_hit_record(&ray, 1.0, &self.center, self.radius, self.material.clone());
+        Some((rec, self.surface_pdf(&self.center)))
     }
 
-    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
-        Some(Aabb::new(self.center - Vec3::new(self.radius, self.radius, self.radius),
-                       self.center + Vec3::new(self.radius, self.radius, self.radius)))
+    fn surface_pdf(&self, _p: &Point3) -> f64 {
+        1.0 / (4.0 * PI * self.radius * self.radius)
     }
 }
 
+fn sphere_hit_record(ray: &Ray, root: f64, center: &Point3, radius: f64, material: Rc<dyn Material>) -> HitRecord {
+    let p = ray.at(root);
+    let outward_normal = (p - *center)/radius;
+    let (u, v) = get_sphere_uv(&outward_normal);
+    let (dpdu, dpdv) = get_sphere_tangents(&outward_normal, radius);
+    let front_face = ray.direction.dot(&outward_normal) < 0.0;
+    let normal = if front_face {
+        outward_normal
+    }else{
+        -outward_normal
+    };
+    HitRecord{p, normal, geometric_normal: normal, dpdu, dpdv, t: root, u, v, front_face, material, outside_ior: None, object: None, light: None}
+}
+
 fn get_sphere_uv(p: &Point3) -> (f64, f64) {
     let theta = (-p).y().acos();
     let phi = (-p).z().atan2(p.x()) + PI;
@@ -52,6 +112,18 @@ fn get_sphere_uv(p: &Point3) -> (f64, f64) {
     (u, v)
 }
 
+/// Derivatives of the point on the sphere with respect to the (u, v) of `get_sphere_uv`, given the
+/// outward unit normal. At the poles dp/du vanishes, so an arbitrary tangent is used there instead.
+fn get_sphere_tangents(n: &Vec3, radius: f64) -> (Vec3, Vec3) {
+    let sin_theta = (n.x() * n.x() + n.z() * n.z()).sqrt();
+    if sin_theta < 1e-8 {
+        return (Vec3::new(2.0 * PI * radius, 0.0, 0.0), Vec3::new(0.0, 0.0, PI * radius))
+    }
+    let dpdu = Vec3::new(n.z(), 0.0, -n.x()) * (2.0 * PI * radius);
+    let dpdv = Vec3::new(-n.x() * n.y() / sin_theta, sin_theta, -n.y() * n.z() / sin_theta) * (PI * radius);
+    (dpdu, dpdv)
+}
+
 pub struct MovingSphere{
     pub center0: Point3,
     pub center1: Point3,
@@ -69,7 +141,7 @@ impl MovingSphere {
 
 impl Hittable for MovingSphere {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = &ray.origin - &self.center(ray.time);
+        let oc = ray.origin - self.center(ray.time);
         let a = ray.direction.dot(&ray.direction);
         let half_b = oc.dot(&ray.direction);
         let c = oc.dot(&oc) - self.radius * self.radius;
@@ -79,27 +151,21 @@ impl Hittable for MovingSphere {
         }
         let sqrtd = disc.sqrt();
 
-        let root = (-half_b - sqrtd)/a;
+        // Take the far root when the near one is out of range, e.g. for rays leaving the sphere
+        let mut root = (-half_b - sqrtd)/a;
         if root < t_min || t_max < root {
-            return None
+            root = (-half_b + sqrtd)/a;
+            if root < t_min || t_max < root {
+                return None
+            }
         }
-        let p = ray.at(root);
-        let normal = (&p - &self.center(ray.time))/self.radius;
-        let front_face = ray.direction.dot(&normal) < 0.0;
-        let normal = if front_face {
-            normal
-        }else{
-            -normal
-        };
-        let (u, v) = get_sphere_uv(&normal);
-        Some(HitRecord{p, normal, t: root, u, v, front_face, material: self.material.clone()})
+        Some(sphere_hit_record(ray, root, &self.center(ray.time), self.radius, self.material.clone()))
     }
 
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
-        let box0 = Aabb::new(self.center(time0) - Vec3::new(self.radius, self.radius, self.radius),
-                             self.center(time0) + Vec3::new(self.radius, self.radius, self.radius));
-        let box1 = Aabb::new(self.center(time1) - Vec3::new(self.radius, self.radius, self.radius),
-                             self.center(time1) + Vec3::new(self.radius, self.radius, self.radius));
+        let r = Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        let box0 = Aabb::new(self.center(time0) - r, self.center(time0) + r);
+        let box1 = Aabb::new(self.center(time1) - r, self.center(time1) + r);
         Some(Aabb::surrounding_box(&box0, &box1))
     }
 }
\ No newline at end of file
@@ -0,0 +1,309 @@
+use crate::integrator::{Integrator, Scene};
+use crate::light::Light;
+use crate::light_bvh::Sampled;
+use crate::material::{Lobe, Scatter, TransportMode};
+use crate::onb::Onb;
+use crate::path_tracer::{power_heuristic, sample_lights};
+use crate::pdf::{CosinePdf, MaterialPdf, Pdf};
+use crate::random_double;
+use crate::ray::{HitRecord, Ray};
+use crate::vec3::{Color, Point3, Vec3};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+/// How fast the gather radius shrinks, between 0 and 1. Lower shrinks faster, trading noise for bias.
+const ALPHA: f64 = 2.0 / 3.0;
+
+/// Light left on a surface by a path from a light.
+struct Photon {
+    p: Point3,
+    /// Unit direction the light was travelling in.
+    direction: Vec3,
+    /// Power the photon carries, divided by the density of having sampled its path.
+    power: Color,
+}
+
+/// Photons hashed into a grid of cubes as wide as the gather radius, so a gather only has to look at
+/// the cube around the point and its neighbors.
+struct PhotonMap {
+    cell_size: f64,
+    cells: HashMap<(i64, i64, i64), Vec<Photon>>,
+    /// Paths traced from the lights, including those that left no photon.
+    emitted: usize,
+}
+
+impl PhotonMap {
+    fn new(cell_size: f64) -> PhotonMap {
+        PhotonMap {cell_size, cells: HashMap::new(), emitted: 0}
+    }
+
+    fn cell(&self, p: &Point3) -> (i64, i64, i64) {
+        let index = |x: f64| (x / self.cell_size).floor() as i64;
+        (index(p.x()), index(p.y()), index(p.z()))
+    }
+
+    fn add(&mut self, photon: Photon) {
+        let cell = self.cell(&photon.p);
+        self.cells.entry(cell).or_default().push(photon);
+    }
+
+    /// Calls `f` with the photons that may be within a cell's width of `p`.
+    fn for_each_near(&self, p: &Point3, mut f: impl FnMut(&Photon)) {
+        let (x, y, z) = self.cell(p);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if let Some(photons) = self.cells.get(&(x + dx, y + dy, z + dz)) {
+                        photons.iter().for_each(&mut f);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Where photons leave from: the lights in the light BVH, directional lights and the background, the
+/// last two through a disk covering the scene. Each is picked with odds following its power.
+struct Sources<'a> {
+    center: Point3,
+    radius: f64,
+    lights: f64,
+    directional: Vec<(&'a dyn Light, f64)>,
+    background: f64,
+    total: f64,
+}
+
+impl<'a> Sources<'a> {
+    fn new(scene: &Scene<'a>) -> Sources<'a> {
+        let (center, radius) = match scene.world.bounding_box(0.0, 1.0) {
+            Some(bounds) => ((bounds.min() + bounds.max()) * 0.5, (bounds.max() - bounds.min()).length() / 2.0),
+            None => (Point3::new(0.0, 0.0, 0.0), 1.0),
+        };
+        let disk_area = PI * radius * radius;
+        let directional: Vec<_> = scene.lights.unbounded()
+            .map(|light| (light, light.sample(&center).map_or(0.0, |sample| sample.irradiance.luminance() * disk_area)))
+            .collect();
+        // A rough average of the background is plenty to pick it by
+        let mean_radiance = (0..256).map(|_| scene.background.value(&Vec3::random_unit_vector()).luminance()).sum::<f64>() / 256.0;
+        let background = 4.0 * PI * mean_radiance * disk_area;
+        let lights = scene.lights.power();
+        let total = lights + directional.iter().map(|(_, power)| power).sum::<f64>() + background;
+        Sources {center, radius, lights, directional, background, total}
+    }
+
+    /// Ray leaving a source, picked at random, and the power it carries.
+    fn emit(&self, scene: &Scene, time: f64) -> Option<(Ray, Color)> {
+        if self.total <= 0.0 {
+            return None
+        }
+        let mut x = random_double(0.0, self.total);
+        if x < self.lights {
+            let (ray, power) = emit_from_lights(scene, time)?;
+            return Some((ray, power * (self.total / self.lights)))
+        }
+        x -= self.lights;
+        for (light, power) in &self.directional {
+            if x < *power {
+                let sample = light.sample(&self.center)?;
+                let disk_area = PI * self.radius * self.radius;
+                return Some((self.ray_from_disk(&sample.direction, time), sample.irradiance * (disk_area * self.total / power)))
+            }
+            x -= power;
+        }
+        if self.background <= 0.0 {
+            return None
+        }
+        let (direction, pdf) = match scene.background.sample() {
+            Some(direction) => (direction, scene.background.pdf_value(&direction)),
+            None => (Vec3::random_unit_vector(), 1.0 / (4.0 * PI)),
+        };
+        if pdf == 0.0 {
+            return None
+        }
+        let disk_area = PI * self.radius * self.radius;
+        let power = scene.background.value(&direction) * (disk_area / pdf * self.total / self.background);
+        Some((self.ray_from_disk(&direction, time), power))
+    }
+
+    /// Ray coming from far away along `-to_source`, through a random point of the disk facing it that
+    /// covers the scene.
+    fn ray_from_disk(&self, to_source: &Vec3, time: f64) -> Ray {
+        let to_source = to_source.unit();
+        let offset = Vec3::random_in_unit_disk();
+        let origin = self.center + (to_source + Onb::from_w(&to_source).local(&Vec3::new(offset.x(), offset.y(), 0.0))) * self.radius;
+        Ray::new(origin, -to_source, time)
+    }
+}
+
+/// Ray leaving a light picked from the light BVH by its power, and the power it carries.
+fn emit_from_lights(scene: &Scene, time: f64) -> Option<(Ray, Color)> {
+    let (light, pmf) = scene.lights.sample_emitter()?;
+    match light {
+        Sampled::Shape(_, shape) => {
+            let (rec, area_pdf) = shape.sample_surface()?;
+            let direction = CosinePdf::new(&rec.geometric_normal).generate()?.unit();
+            let emitted = rec.material.emitted(&Ray::new(rec.p + direction, -direction, time), &rec);
+            // The cosine of the direction cancels out with the density of having picked it
+            Some((Ray::new(rec.p, direction, time), emitted * (PI / (pmf * area_pdf))))
+        },
+        Sampled::Delta(light) => {
+            let emission = light.emit()?;
+            if emission.pdf == 0.0 {
+                return None
+            }
+            Some((Ray::new(emission.origin, emission.direction, time), emission.intensity / (pmf * emission.pdf)))
+        },
+    }
+}
+
+fn max_channel(c: &Color) -> f64 {
+    c.x().max(c.y()).max(c.z())
+}
+
+/// State of the pass being rendered.
+struct Pass {
+    number: u32,
+    radius: f64,
+    photons: PhotonMap,
+}
+
+/// Stochastic progressive photon mapping, after Knaus and Zwicker's probabilistic formulation. Each
+/// pass traces `photons` paths from the lights and stores where they land. Camera paths go through
+/// mirrors, glass and glossy surfaces, and at the first diffuse surface take the light coming straight
+/// from the lights by sampling them, and the light that bounced at least once from the photons within
+/// the gather radius. That makes caustics, which paths from the camera rarely find, as smooth as the
+/// rest of the indirect light.
+///
+/// Density estimation blurs light over the radius, so it shrinks a little every pass, slowly enough for
+/// the average over passes to converge to the right image.
+pub struct Sppm {
+    max_depth: u32,
+    photons: usize,
+    pass: RefCell<Pass>,
+}
+
+impl Sppm {
+    pub fn new(max_depth: u32, photons: u32, radius: f64) -> Sppm {
+        Sppm {max_depth, photons: photons as usize, pass: RefCell::new(Pass {number: 0, radius, photons: PhotonMap::new(radius)})}
+    }
+
+    fn trace_photon(&self, scene: &Scene, mut ray: Ray, mut power: Color, photons: &mut PhotonMap) {
+        for depth in 0..self.max_depth {
+            let rec = match scene.world.hit(&ray, 0.001, f64::MAX) {
+                Some(rec) => rec,
+                None => return,
+            };
+            // Light arriving straight from the lights is sampled from the camera instead
+            if depth > 0 {
+                photons.add(Photon {p: rec.p, direction: ray.direction.unit(), power});
+            }
+            let scatter = match rec.material.sample(&ray, &rec) {
+                Some(scatter) => scatter,
+                None => return,
+            };
+            // Photons keep about the same power, and are ended with the odds of it dropping instead
+            let scattered = power * scatter.attenuation;
+            let survival = (max_channel(&scattered) / max_channel(&power)).min(1.0);
+            if random_double(0.0, 1.0) >= survival {
+                return
+            }
+            power = scattered / survival;
+            ray = scatter.scatter;
+        }
+    }
+
+    /// Light arriving at `rec` after bouncing at least once, from the photons around it.
+    fn gather(&self, ray: &Ray, rec: &HitRecord, pass: &Pass) -> Color {
+        let radius_squared = pass.radius * pass.radius;
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        pass.photons.for_each_near(&rec.p, |photon| {
+            if (photon.p - rec.p).length_squared() > radius_squared {
+                return
+            }
+            let incoming = -photon.direction;
+            // The photon's power already accounts for the angle it arrives at
+            let cosine = rec.normal.dot(&incoming).abs();
+            if cosine > 1e-6 {
+                sum = sum + rec.material.eval(ray, rec, &incoming, TransportMode::Radiance) * photon.power / cosine;
+            }
+        });
+        if pass.photons.emitted == 0 {
+            return sum
+        }
+        sum / (PI * radius_squared * pass.photons.emitted as f64)
+    }
+}
+
+/// Light arriving at `rec` straight from the lights and the background. Both are sampled, and also
+/// found by `scatter`, with multiple importance sampling between the two.
+fn direct_light(ray: &Ray, rec: &HitRecord, scatter: &Scatter, scene: &Scene) -> Color {
+    let Scene {world, lights, background, ..} = *scene;
+    let material_pdf = MaterialPdf::new(rec.material.as_ref(), ray, rec);
+    let mut direct = sample_lights(ray, rec, world, lights, Some(&rec.normal), Some(&material_pdf));
+    if let Some(direction) = background.sample() {
+        let pdf = background.pdf_value(&direction);
+        let f = rec.material.eval(ray, rec, &direction, TransportMode::Radiance);
+        if pdf > 0.0 && !f.near_zero() && world.hit(&Ray::new(rec.p, direction, ray.time), 0.001, f64::MAX).is_none() {
+            direct = direct + f * background.value(&direction) * (power_heuristic(pdf, material_pdf.value(&direction)) / pdf);
+        }
+    }
+
+    let scattered = &scatter.scatter;
+    match world.hit(scattered, 0.001, f64::MAX) {
+        Some(hit) => {
+            let light_pdf = lights.pdf_value(&rec.p, Some(&rec.normal), &scattered.direction, &hit);
+            direct + scatter.attenuation * hit.material.emitted(scattered, &hit) * power_heuristic(scatter.pdf, light_pdf)
+        },
+        None => {
+            let background_pdf = background.pdf_value(&scattered.direction);
+            direct + scatter.attenuation * background.value(&scattered.direction) * power_heuristic(scatter.pdf, background_pdf)
+        },
+    }
+}
+
+impl Integrator for Sppm {
+    fn ray_color(&self, mut ray: Ray, scene: &Scene) -> Color {
+        let pass = self.pass.borrow();
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        for _ in 0..=self.max_depth {
+            let rec = match scene.world.hit(&ray, 0.001, f64::MAX) {
+                Some(rec) => rec,
+                None => return radiance + throughput * scene.background.value(&ray.direction),
+            };
+            radiance = radiance + throughput * rec.material.emitted(&ray, &rec);
+            let scatter = match rec.material.sample(&ray, &rec) {
+                Some(scatter) => scatter,
+                None => return radiance,
+            };
+            if scatter.lobe != Lobe::Diffuse || scatter.pdf == 0.0 {
+                throughput = throughput * scatter.attenuation;
+                ray = scatter.scatter;
+                continue
+            }
+            let light = direct_light(&ray, &rec, &scatter, scene) + self.gather(&ray, &rec, &pass);
+            return radiance + throughput * light
+        }
+        radiance
+    }
+
+    fn begin_pass(&self, scene: &Scene) {
+        let mut pass = self.pass.borrow_mut();
+        if pass.number > 0 {
+            let number = pass.number as f64;
+            pass.radius *= ((number + ALPHA) / (number + 1.0)).sqrt();
+        }
+        pass.number += 1;
+
+        let sources = Sources::new(scene);
+        let mut photons = PhotonMap::new(pass.radius);
+        for _ in 0..self.photons {
+            photons.emitted += 1;
+            if let Some((ray, power)) = sources.emit(scene, random_double(0.0, 1.0)) {
+                self.trace_photon(scene, ray, power, &mut photons);
+            }
+        }
+        pass.photons = photons;
+    }
+}
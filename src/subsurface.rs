@@ -0,0 +1,91 @@
+use crate::fresnel::{fresnel_dielectric, refract};
+use crate::material::{Material, Scatter};
+use crate::medium::{HomogeneousMedium, MediumEvent};
+use crate::random_double;
+use crate::ray::{HitRecord, Ray};
+use crate::texture::Texture;
+use crate::vec3::Color;
+
+enum Coefficients {
+    MeanFreePath {albedo: Box<dyn Texture>, mean_free_path: Box<dyn Texture>},
+    Explicit {absorption: Box<dyn Texture>, scattering: Box<dyn Texture>},
+}
+
+/// Translucent material like skin, wax, marble or milk. Light refracts through a smooth dielectric
+/// boundary into a scattering medium filling the object and random walks through it, one bounce at a
+/// time, until it leaves again somewhere else on the surface. The object has to be closed.
+///
+/// The coefficients are looked up from their textures where each step of the walk ends, so solid
+/// textures vary them through the volume.
+pub struct Subsurface {
+    coefficients: Coefficients,
+    anisotropy: f64,
+    ir: f64,
+}
+
+impl Subsurface {
+    /// Medium that looks like it has the diffuse `albedo` and in which light travels `mean_free_path`, per
+    /// channel, between scattering events.
+    pub fn new(albedo: Box<dyn Texture>, mean_free_path: Box<dyn Texture>, ir: f64) -> Subsurface {
+        Subsurface {coefficients: Coefficients::MeanFreePath {albedo, mean_free_path}, anisotropy: 0.0, ir}
+    }
+
+    /// Medium given by its absorption and scattering coefficients, per unit of distance.
+    pub fn from_coefficients(absorption: Box<dyn Texture>, scattering: Box<dyn Texture>, ir: f64) -> Subsurface {
+        Subsurface {coefficients: Coefficients::Explicit {absorption, scattering}, anisotropy: 0.0, ir}
+    }
+
+    /// Mean cosine of the scattering angle inside; see `HomogeneousMedium::new`.
+    pub fn with_anisotropy(mut self, anisotropy: f64) -> Subsurface {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    fn medium(&self, rec: &HitRecord) -> HomogeneousMedium {
+        match &self.coefficients {
+            Coefficients::MeanFreePath {albedo, mean_free_path} => {
+                let albedo = albedo.value(rec.u, rec.v, &rec.p);
+                let mean_free_path = mean_free_path.value(rec.u, rec.v, &rec.p);
+                let extinction = |d: f64| 1.0 / d.max(1e-6);
+                let extinction = Color::new(extinction(mean_free_path.r()), extinction(mean_free_path.g()), extinction(mean_free_path.b()));
+                let albedo = Color::new(single_scattering_albedo(albedo.r()), single_scattering_albedo(albedo.g()), single_scattering_albedo(albedo.b()));
+                let scattering = extinction * albedo;
+                HomogeneousMedium::new(extinction - scattering, scattering, self.anisotropy)
+            },
+            Coefficients::Explicit {absorption, scattering} => {
+                HomogeneousMedium::new(absorption.value(rec.u, rec.v, &rec.p), scattering.value(rec.u, rec.v, &rec.p), self.anisotropy)
+            },
+        }
+    }
+}
+
+/// Albedo of the individual scattering events that gives a semi-infinite slab the diffuse `albedo`
+/// overall, after Van de Hulst's fit.
+fn single_scattering_albedo(albedo: f64) -> f64 {
+    let a = albedo.clamp(0.0, 0.999);
+    let s = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+    1.0 - s * s
+}
+
+impl Material for Subsurface {
+    fn sample(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        // Coming from inside, the walk may scatter before reaching the boundary
+        let mut attenuation = Color::new(1.0, 1.0, 1.0);
+        if !rec.front_face {
+            match self.medium(rec).interact(r_in, rec) {
+                MediumEvent::Scattered(scatter) => return Some(scatter),
+                MediumEvent::Reached(throughput) => attenuation = throughput,
+            }
+        }
+
+        let unit_direction = r_in.direction.unit();
+        let wo = -unit_direction;
+        let eta = if rec.front_face { self.ir } else { 1.0 / self.ir };
+        let direction = if random_double(0.0, 1.0) < fresnel_dielectric(wo.dot(&rec.normal), eta) {
+            unit_direction.reflect(&rec.normal)
+        } else {
+            refract(&wo, &rec.normal, eta)?
+        };
+        Some(Scatter::specular(attenuation, Ray::new(rec.p, direction, r_in.time)))
+    }
+}
@@ -18,7 +18,7 @@ impl SolidColor {
 }
 
 impl Texture for SolidColor {
-    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+    fn value(&self, _u: f64, _v: f64, _p: &Vec3) -> Color {
         self.color_value
     }
 }
@@ -40,7 +40,7 @@ impl CheckerTexture {
 impl Texture for CheckerTexture {
     fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
         let sines = (10.0*p.x()).sin() * (10.0 * p.y()).sin() * (10.0*p.z()).sin();
-        if (sines < 0.0){
+        if sines < 0.0 {
             self.odd.value(u, v, p)
         }else{
             self.even.value(u, v, p)
@@ -0,0 +1,155 @@
+use crate::ray::HitRecord;
+use crate::spectrum::{reflectance_to_rgb, RGB_WAVELENGTHS};
+use crate::texture::Texture;
+use crate::vec3::Color;
+use std::f64::consts::PI;
+use std::ops;
+
+/// Thin transparent coating whose reflections interfere with each other, giving the angle dependent
+/// colors of soap bubbles, oil slicks and coated lenses. The thickness is in nanometers, read from the
+/// luminance of its texture.
+pub struct ThinFilm {
+    thickness: Box<dyn Texture>,
+    ior: f64,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: Box<dyn Texture>, ior: f64) -> ThinFilm {
+        ThinFilm {thickness, ior}
+    }
+
+    /// Reflectance of the film at `rec` over a dielectric with index of refraction `substrate`, for light
+    /// arriving at `cos_theta` from a medium with index `incident`.
+    pub fn reflectance_dielectric(&self, rec: &HitRecord, cos_theta: f64, incident: f64, substrate: f64) -> Color {
+        let thickness = self.thickness.value(rec.u, rec.v, &rec.p).luminance().max(0.0);
+        reflectance_to_rgb(|lambda| {
+            airy_reflectance(lambda, cos_theta, incident, self.ior, Complex::new(substrate, 0.0), thickness)
+        })
+    }
+
+    /// Reflectance of the film at `rec` over a conductor with complex index of refraction `eta` + i`k`,
+    /// given per color channel, for light arriving at `cos_theta` from a vacuum.
+    pub fn reflectance_conductor(&self, rec: &HitRecord, cos_theta: f64, eta: &Color, k: &Color) -> Color {
+        let thickness = self.thickness.value(rec.u, rec.v, &rec.p).luminance().max(0.0);
+        reflectance_to_rgb(|lambda| {
+            let substrate = Complex::new(channel_at(eta, lambda), channel_at(k, lambda));
+            airy_reflectance(lambda, cos_theta, 1.0, self.ior, substrate, thickness)
+        })
+    }
+
+    /// Reflectance of the film at `rec` over a conductor, at the wavelengths `lambdas` with the complex
+    /// indices of refraction `eta` + i`k` given at each, as in spectral mode.
+    pub fn reflectance_conductor_at(&self, rec: &HitRecord, cos_theta: f64, lambdas: [f64; 3], eta: &Color, k: &Color) -> Color {
+        let thickness = self.thickness.value(rec.u, rec.v, &rec.p).luminance().max(0.0);
+        let at = |i: usize| {
+            let substrate = Complex::new(eta.e()[i], k.e()[i]);
+            airy_reflectance(lambdas[i], cos_theta, 1.0, self.ior, substrate, thickness)
+        };
+        Color::new(at(0), at(1), at(2))
+    }
+}
+
+/// Interpolates between the values of a color at the wavelengths its channels stand for.
+fn channel_at(c: &Color, lambda: f64) -> f64 {
+    let [r, g, b] = RGB_WAVELENGTHS;
+    if lambda >= r {
+        c.r()
+    } else if lambda >= g {
+        c.g() + (c.r() - c.g()) * (lambda - g) / (r - g)
+    } else if lambda >= b {
+        c.b() + (c.g() - c.b()) * (lambda - b) / (g - b)
+    } else {
+        c.b()
+    }
+}
+
+/// Unpolarized reflectance at `lambda` nanometers of a film of index `film` and `thickness` nanometers on
+/// top of a substrate, summing the multiple reflections inside the film (Airy).
+fn airy_reflectance(lambda: f64, cos_theta: f64, incident: f64, film: f64, substrate: Complex, thickness: f64) -> f64 {
+    let n1 = Complex::new(incident, 0.0);
+    let n2 = Complex::new(film, 0.0);
+    let cos1 = Complex::new(cos_theta.clamp(0.0, 1.0), 0.0);
+    let sin1_2 = Complex::new(1.0 - cos_theta * cos_theta, 0.0);
+    // Snell's law, with complex cosines past the critical angle or in absorbing media
+    let cos_in = |n: Complex| (Complex::new(1.0, 0.0) - sin1_2 * (n1 * n1) / (n * n)).sqrt();
+    let cos2 = cos_in(n2);
+    let cos3 = cos_in(substrate);
+
+    let phase = Complex::new(0.0, 4.0 * PI * thickness / lambda) * n2 * cos2;
+    let shift = phase.exp();
+    let airy = |r12: Complex, r23: Complex| {
+        let r = (r12 + r23 * shift) / (Complex::new(1.0, 0.0) + r12 * r23 * shift);
+        r.norm_sqr().min(1.0)
+    };
+    let rs = airy(
+        (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2),
+        (n2 * cos2 - substrate * cos3) / (n2 * cos2 + substrate * cos3),
+    );
+    let rp = airy(
+        (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2),
+        (substrate * cos2 - n2 * cos3) / (substrate * cos2 + n2 * cos3),
+    );
+    0.5 * (rs + rp)
+}
+
+#[derive(Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Complex {
+        Complex {re, im}
+    }
+
+    fn norm_sqr(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// Principal square root.
+    fn sqrt(&self) -> Complex {
+        let norm = self.norm_sqr().sqrt();
+        let re = ((norm + self.re) / 2.0).max(0.0).sqrt();
+        let im = ((norm - self.re) / 2.0).max(0.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    fn exp(&self) -> Complex {
+        let scale = self.re.exp();
+        Complex::new(scale * self.im.cos(), scale * self.im.sin())
+    }
+}
+
+impl ops::Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl ops::Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl ops::Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(self.re * rhs.re - self.im * rhs.im, self.re * rhs.im + self.im * rhs.re)
+    }
+}
+
+impl ops::Div for Complex {
+    type Output = Complex;
+
+    fn div(self, rhs: Complex) -> Complex {
+        let d = rhs.norm_sqr();
+        Complex::new((self.re * rhs.re + self.im * rhs.im) / d, (self.im * rhs.re - self.re * rhs.im) / d)
+    }
+}
@@ -1,5 +1,4 @@
 use core::ops;
-use rand::Rng;
 
 #[derive(Clone, Copy)]
 pub struct Vec3 {
@@ -36,7 +35,7 @@ impl Vec3 {
     }
 
     pub fn random(min: f64, max: f64) -> Vec3 {
-        Vec3::new(rand::thread_rng().gen_range(min..max), rand::thread_rng().gen_range(min..max), rand::thread_rng().gen_range(min..max))
+        Vec3::new(crate::random_double(min, max), crate::random_double(min, max), crate::random_double(min, max))
     }
 
     pub fn random_in_unit_sphere() -> Vec3{
@@ -52,6 +51,15 @@ impl Vec3 {
         Vec3::random_in_unit_sphere().unit()
     }
 
+    /// Direction in the hemisphere around +z with a density proportional to its z coordinate.
+    pub fn random_cosine_direction() -> Vec3 {
+        let r1: f64 = crate::random_double(0.0, 1.0);
+        let r2: f64 = crate::random_double(0.0, 1.0);
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let z = (1.0 - r2).sqrt();
+        Vec3::new(phi.cos() * r2.sqrt(), phi.sin() * r2.sqrt(), z)
+    }
+
     pub fn random_in_hemisphere(normal: &Vec3) -> Vec3 {
         let in_unit_sphere = Vec3::random_in_unit_sphere();
         if in_unit_sphere.dot(normal) > 0.0 {
@@ -61,6 +69,10 @@ impl Vec3 {
         }
     }
 
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r() + 0.7152 * self.g() + 0.0722 * self.b()
+    }
+
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
         self.x().abs() < s && self.y().abs() < s && self.z().abs() < s
@@ -79,7 +91,7 @@ impl Vec3 {
 
     pub fn random_in_unit_disk() -> Vec3 {
         loop {
-            let p = Vec3 { e: [rand::thread_rng().gen_range(-1.0..1.0), rand::thread_rng().gen_range(-1.0..1.0), 0.0]};
+            let p = Vec3 { e: [crate::random_double(-1.0, 1.0), crate::random_double(-1.0, 1.0), 0.0]};
             if p.length_squared() >= 1.0 {
                 continue;
             }
@@ -0,0 +1,62 @@
+use crate::integrator::{Integrator, Scene};
+use crate::material::Lobe;
+use crate::path_tracer::{at_wavelengths, sample_lights};
+use crate::ray::Ray;
+use crate::vec3::Color;
+
+/// Classic recursive ray tracer. Mirrors and glass are followed up to `max_depth` times, and everything
+/// else is only lit by the lights and by the background it can see in one sampled direction, without
+/// any light bouncing between surfaces. Fast and noise free for simple scenes.
+pub struct Whitted {
+    max_depth: u32,
+}
+
+impl Whitted {
+    pub fn new(max_depth: u32) -> Whitted {
+        Whitted {max_depth}
+    }
+}
+
+impl Integrator for Whitted {
+    fn ray_color(&self, mut ray: Ray, scene: &Scene) -> Color {
+        let Scene {world, lights, background, ..} = *scene;
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        for _ in 0..=self.max_depth {
+            let upsample = |c: &Color| ray.wavelengths.map_or(*c, |w| w.upsample(c));
+            let rec = match world.hit(&ray, 0.001, f64::MAX) {
+                Some(rec) => rec,
+                None => return radiance + throughput * upsample(&background.value(&ray.direction)),
+            };
+            radiance = radiance + throughput * upsample(&rec.material.emitted(&ray, &rec));
+
+            let scatter = match rec.material.sample(&ray, &rec) {
+                Some(scatter) => scatter,
+                None => return radiance,
+            };
+            let mut scattered = scatter.scatter;
+            scattered.wavelengths = scattered.wavelengths.or(ray.wavelengths);
+            let attenuation = match (ray.wavelengths, scattered.wavelengths) {
+                (Some(w), Some(next)) => at_wavelengths(&ray, &rec, &scatter.attenuation) * w.transition(&next),
+                _ => scatter.attenuation,
+            };
+            if scatter.lobe == Lobe::Specular {
+                throughput = throughput * attenuation;
+                ray = scattered;
+                continue
+            }
+
+            let normal = if scatter.lobe == Lobe::Volume { None } else { Some(rec.normal) };
+            let mut direct = sample_lights(&ray, &rec, world, lights, normal.as_ref(), None);
+            if world.hit(&scattered, 0.001, f64::MAX).is_none() {
+                direct = direct + attenuation * upsample(&background.value(&scattered.direction));
+            }
+            return radiance + throughput * direct
+        }
+        radiance
+    }
+
+    fn is_spectral(&self) -> bool {
+        true
+    }
+}